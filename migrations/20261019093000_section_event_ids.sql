@@ -0,0 +1,2 @@
+-- shared event ids for the LISTEN/NOTIFY event bus, so Last-Event-ID is valid on every replica
+CREATE SEQUENCE section_event_ids;
@@ -0,0 +1,9 @@
+-- section changes waiting to be published on the event bus, written in the same transaction as the change
+CREATE TABLE outbox (
+    id BIGSERIAL PRIMARY KEY,
+    payload TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    delivered_at TIMESTAMPTZ
+);
+
+CREATE INDEX outbox_undelivered ON outbox (id) WHERE delivered_at IS NULL;
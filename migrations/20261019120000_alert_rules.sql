@@ -0,0 +1,10 @@
+CREATE TABLE alert_rules (
+    id SERIAL PRIMARY KEY,
+    kind TEXT NOT NULL CHECK (kind IN ('full', 'freed', 'disabled_ratio')),
+    threshold INT CHECK (threshold BETWEEN 0 AND 100),
+    gender TEXT,
+    building TEXT,
+    floor INT,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,18 @@
+-- a location created twice before the constraint keeps its first section, the later ones
+-- hand their usage_history over to it and go away
+UPDATE usage_history
+SET section_id = kept.id
+FROM sections duplicate
+JOIN (
+    SELECT MIN(id) AS id, gender, building, floor
+    FROM sections
+    GROUP BY gender, building, floor
+) kept USING (gender, building, floor)
+WHERE usage_history.section_id = duplicate.id AND duplicate.id <> kept.id;
+
+DELETE FROM sections
+WHERE id NOT IN (SELECT MIN(id) FROM sections GROUP BY gender, building, floor);
+
+-- one section per location, find_by_floor relies on it
+ALTER TABLE sections
+    ADD CONSTRAINT sections_location_key UNIQUE (gender, building, floor);
@@ -0,0 +1,14 @@
+CREATE TABLE audit_log (
+    id SERIAL PRIMARY KEY,
+    section_id INTEGER NOT NULL REFERENCES sections (id),
+    action TEXT NOT NULL,
+    transition TEXT,
+    actor TEXT,
+    source_ip TEXT,
+    old_counters JSONB,
+    new_counters JSONB,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX audit_log_section_idx ON audit_log (section_id, created_at);
+CREATE INDEX audit_log_actor_idx ON audit_log (actor, created_at);
@@ -0,0 +1,15 @@
+-- sites (campuses) sit above buildings, every existing section belongs to the default one
+ALTER TABLE sections ADD COLUMN site TEXT NOT NULL DEFAULT 'main';
+
+-- the same building name can exist on every site
+DROP INDEX sections_location_key;
+CREATE UNIQUE INDEX sections_location_key ON sections (site, gender, building, floor)
+    WHERE deleted_at IS NULL;
+
+-- a site has its own buildings and floors, so the seeded campus' A-C and 1-4 no longer
+-- bound them, a building just needs a name and floors count from 1
+ALTER TABLE sections DROP CONSTRAINT sections_building_check;
+ALTER TABLE sections DROP CONSTRAINT sections_floor_check;
+ALTER TABLE sections ALTER COLUMN building TYPE TEXT;
+ALTER TABLE sections ADD CONSTRAINT sections_building_check CHECK (building <> '');
+ALTER TABLE sections ADD CONSTRAINT sections_floor_check CHECK (floor >= 1);
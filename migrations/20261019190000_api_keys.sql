@@ -0,0 +1,11 @@
+-- keys for the write routes, only a sha-256 hash of each key is kept
+CREATE TABLE api_keys (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    -- none for every site, or every building of the site
+    site TEXT,
+    building TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    revoked_at TIMESTAMPTZ
+);
@@ -0,0 +1,3 @@
+-- webhooks and alert rules can be limited to one site, like the event streams
+ALTER TABLE webhooks ADD COLUMN site TEXT;
+ALTER TABLE alert_rules ADD COLUMN site TEXT;
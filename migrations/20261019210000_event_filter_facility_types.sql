@@ -0,0 +1,3 @@
+-- webhooks and alert rules can be limited to one facility type, like the event streams
+ALTER TABLE webhooks ADD COLUMN facility_type TEXT;
+ALTER TABLE alert_rules ADD COLUMN facility_type TEXT;
@@ -0,0 +1,29 @@
+-- mirrors the postgres schema in migrations/ for single-box deployments
+CREATE TABLE sections (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    building TEXT NOT NULL CHECK (building IN('A', 'B', 'C')),
+    floor INTEGER NOT NULL CHECK (floor >= 1 AND floor <= 4),
+    gender TEXT NOT NULL CHECK (gender IN('male', 'female')),
+    total INTEGER NOT NULL,
+    available INTEGER NOT NULL CHECK (available >= 0),
+    occupied INTEGER NOT NULL CHECK (occupied >= 0) DEFAULT 0,
+    disabled_rooms INTEGER NOT NULL CHECK (disabled_rooms >= 0) DEFAULT 0,
+    CHECK (total = available + occupied + disabled_rooms)
+);
+
+CREATE TABLE usage_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    section_id INTEGER REFERENCES sections (id),
+    start_time TIMESTAMP NOT NULL,
+    end_time TIMESTAMP
+);
+
+-- same order as the postgres seed so both backends hand out the same ids
+WITH
+    buildings(building) AS (VALUES ('A'), ('B'), ('C')),
+    floors(floor) AS (VALUES (1), (2), (3), (4)),
+    genders(position, gender) AS (VALUES (1, 'male'), (2, 'female'))
+INSERT INTO sections (building, floor, gender, total, available)
+SELECT building, floor, gender, 10, 10
+FROM buildings, floors, genders
+ORDER BY building, floor, position;
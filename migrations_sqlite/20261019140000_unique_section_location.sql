@@ -0,0 +1,16 @@
+-- a location created twice before the index keeps its first section, the later ones hand
+-- their usage_history over to it and go away
+UPDATE usage_history
+SET section_id = (
+    SELECT MIN(kept.id)
+    FROM sections duplicate
+    JOIN sections kept USING (gender, building, floor)
+    WHERE duplicate.id = usage_history.section_id
+)
+WHERE section_id IS NOT NULL;
+
+DELETE FROM sections
+WHERE id NOT IN (SELECT MIN(id) FROM sections GROUP BY gender, building, floor);
+
+-- one section per location, find_by_floor relies on it
+CREATE UNIQUE INDEX sections_location_key ON sections (gender, building, floor);
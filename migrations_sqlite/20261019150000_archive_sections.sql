@@ -0,0 +1,7 @@
+-- sections are archived instead of deleted so usage_history keeps its rows
+ALTER TABLE sections ADD COLUMN deleted_at TEXT;
+
+-- an archived section does not hold its location
+DROP INDEX sections_location_key;
+CREATE UNIQUE INDEX sections_location_key ON sections (gender, building, floor)
+    WHERE deleted_at IS NULL;
@@ -0,0 +1,32 @@
+-- sites (campuses) sit above buildings, every existing section belongs to the default one.
+-- a site has its own buildings and floors, so the seeded campus' A-C and 1-4 no longer
+-- bound them. sqlite can't drop a check, the table is rebuilt without them
+PRAGMA defer_foreign_keys = ON;
+
+CREATE TABLE sections_before_sites AS SELECT * FROM sections;
+DROP TABLE sections;
+
+CREATE TABLE sections (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    building TEXT NOT NULL CHECK (building <> ''),
+    floor INTEGER NOT NULL CHECK (floor >= 1),
+    gender TEXT NOT NULL CHECK (gender IN('male', 'female')),
+    total INTEGER NOT NULL,
+    available INTEGER NOT NULL CHECK (available >= 0),
+    occupied INTEGER NOT NULL CHECK (occupied >= 0) DEFAULT 0,
+    disabled_rooms INTEGER NOT NULL CHECK (disabled_rooms >= 0) DEFAULT 0,
+    deleted_at TEXT,
+    site TEXT NOT NULL DEFAULT 'main',
+    CHECK (total = available + occupied + disabled_rooms)
+);
+
+-- the rows come back under their ids, so usage_history points at them again by the commit
+INSERT INTO sections
+    (id, building, floor, gender, total, available, occupied, disabled_rooms, deleted_at)
+SELECT id, building, floor, gender, total, available, occupied, disabled_rooms, deleted_at
+FROM sections_before_sites;
+DROP TABLE sections_before_sites;
+
+-- the same building name can exist on every site
+CREATE UNIQUE INDEX sections_location_key ON sections (site, gender, building, floor)
+    WHERE deleted_at IS NULL;
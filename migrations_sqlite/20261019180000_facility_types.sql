@@ -0,0 +1,7 @@
+-- laundry machines, toilets... are counted like shower stalls, every existing section is a shower
+ALTER TABLE sections ADD COLUMN facility_type TEXT NOT NULL DEFAULT 'shower';
+
+-- a floor can have one section of every facility type
+DROP INDEX sections_location_key;
+CREATE UNIQUE INDEX sections_location_key ON sections (site, facility_type, gender, building, floor)
+    WHERE deleted_at IS NULL;
@@ -0,0 +1,11 @@
+-- mirrors migrations/20261019190000_api_keys.sql, only a sha-256 hash of each key is kept
+CREATE TABLE api_keys (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    -- none for every site, or every building of the site
+    site TEXT,
+    building TEXT,
+    created_at TEXT NOT NULL,
+    revoked_at TEXT
+);
@@ -0,0 +1,136 @@
+// `api-shower keys ...` manages the api keys in the database of DATABASE_URL
+use anyhow::{bail, Context};
+#[cfg(feature = "postgres")]
+use sqlx::PgPool;
+use std::env;
+
+#[cfg(feature = "postgres")]
+use crate::repositories::api_key::db::DBApiKeyRepository;
+use crate::repositories::api_key::{
+    models::{generate_key, hash_key, NewApiKey},
+    traits::ApiKeyRepository,
+};
+#[cfg(feature = "sqlite")]
+use crate::repositories::{
+    api_key::sqlite::SqliteApiKeyRepository, section::sqlite::SqliteSectionRepository,
+};
+
+const USAGE: &str = "usage:
+    api-shower keys create <name> [--site <site>] [--building <building>]
+    api-shower keys list
+    api-shower keys revoke <id>";
+
+pub async fn run(args: &[String]) -> anyhow::Result<()> {
+    let database_url = env::var("DATABASE_URL").context("DATABASE_URL must be set")?;
+    // the same scheme picks the key store as the backend of the server
+    let output = match database_url.as_str() {
+        #[cfg(feature = "sqlite")]
+        url if url.starts_with("sqlite:") => {
+            let sections = SqliteSectionRepository::connect(url).await?;
+            let repository = SqliteApiKeyRepository::new(sections.pool().clone());
+            keys(&repository, args).await?
+        }
+        #[cfg(feature = "postgres")]
+        url if url.starts_with("postgres") => {
+            let pool = PgPool::connect(url).await?;
+            keys(&DBApiKeyRepository::new(pool), args).await?
+        }
+        url => bail!("no key store for {}, check the enabled features", url),
+    };
+    println!("{}", output);
+    Ok(())
+}
+
+// returns what to print, the key store is passed in so the commands run against any of them
+async fn keys(repository: &dyn ApiKeyRepository, args: &[String]) -> anyhow::Result<String> {
+    match args {
+        [command, name, options @ ..] if command == "create" => {
+            let (mut site, mut building) = (None, None);
+            for option in options.chunks(2) {
+                match option {
+                    [flag, value] if flag == "--site" => site = Some(value.clone()),
+                    [flag, value] if flag == "--building" => building = Some(value.clone()),
+                    _ => bail!(USAGE),
+                }
+            }
+            let secret = generate_key();
+            let key = repository
+                .create(NewApiKey {
+                    name: name.clone(),
+                    key_hash: hash_key(&secret),
+                    site,
+                    building,
+                })
+                .await?;
+            Ok(format!(
+                "created key {} ({}), it is not shown again:\n{}",
+                key.id, key.name, secret
+            ))
+        }
+        [command] if command == "list" => {
+            let lines: Vec<String> = repository
+                .find_all()
+                .await?
+                .iter()
+                .map(|key| {
+                    let status = match key.revoked_at {
+                        Some(at) => format!("revoked {}", at.to_rfc3339()),
+                        None => "active".to_string(),
+                    };
+                    format!(
+                        "{}\t{}\tsite={}\tbuilding={}\t{}",
+                        key.id,
+                        key.name,
+                        key.site.as_deref().unwrap_or("*"),
+                        key.building.as_deref().unwrap_or("*"),
+                        status
+                    )
+                })
+                .collect();
+            Ok(lines.join("\n"))
+        }
+        [command, id] if command == "revoke" => {
+            let id = id.parse::<i32>().context(USAGE)?;
+            let key = repository.revoke(id).await?;
+            Ok(format!("revoked key {} ({})", key.id, key.name))
+        }
+        _ => bail!(USAGE),
+    }
+}
+
+#[cfg(all(test, feature = "in-memory"))]
+mod tests {
+    use super::*;
+    use crate::repositories::api_key::in_memory::InMemoryApiKeyRepository;
+
+    fn args(args: &str) -> Vec<String> {
+        args.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[tokio::test]
+    async fn test_keys() -> anyhow::Result<()> {
+        let repository = InMemoryApiKeyRepository::new();
+
+        let output = keys(&repository, &args("create sensor --site main --building A")).await?;
+        let secret = output.lines().last().unwrap();
+        let key = repository.find_by_hash(&hash_key(secret)).await?.unwrap();
+        assert_eq!(key.name, "sensor");
+        assert_eq!(
+            (key.site.as_deref(), key.building.as_deref()),
+            (Some("main"), Some("A"))
+        );
+
+        let output = keys(&repository, &args("list")).await?;
+        assert_eq!(output, "1\tsensor\tsite=main\tbuilding=A\tactive");
+        keys(&repository, &args("revoke 1")).await?;
+        assert_eq!(repository.find_by_hash(&hash_key(secret)).await?, None);
+
+        assert!(keys(&repository, &args("create sensor --floor 2"))
+            .await
+            .is_err());
+        assert!(keys(&repository, &args("revoke one")).await.is_err());
+        assert!(keys(&repository, &args("rotate")).await.is_err());
+
+        Ok(())
+    }
+}
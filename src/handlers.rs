@@ -1,2 +1,11 @@
+pub mod alert;
+pub mod api_key;
+pub mod audit;
+pub mod auth;
+pub mod client;
 pub mod events;
+pub mod facility;
+pub mod health;
 pub mod section;
+pub mod site;
+pub mod webhook;
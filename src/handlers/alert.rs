@@ -0,0 +1,109 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use std::sync::Arc;
+use validator::Validate;
+
+use crate::handlers::audit::Actor;
+use crate::repositories::{
+    alert::{
+        errors::AlertError,
+        models::{CreateAlertRule, UpdateAlertRule},
+        traits::AlertRepository,
+    },
+    events::traits::EventTrait,
+    section::models::Section,
+};
+
+fn error_status(e: anyhow::Error) -> StatusCode {
+    match e.downcast_ref::<AlertError>() {
+        Some(AlertError::NotFound(_)) => StatusCode::NOT_FOUND,
+        None => {
+            tracing::error!("alert repository error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+// alerts are derived from a committed change, a failure here must not fail the request
+pub async fn notify_alerts(
+    alerts: &dyn AlertRepository,
+    events: &dyn EventTrait,
+    before: &Section,
+    after: &Section,
+) {
+    let rules = match alerts.find_all().await {
+        Ok(rules) => rules,
+        Err(e) => {
+            tracing::error!("failed to load alert rules: {}", e);
+            return;
+        }
+    };
+    for rule in rules.iter().filter(|rule| rule.fires(before, after)) {
+        if let Err(e) = events.notify(rule.message(after)).await {
+            tracing::error!("failed to publish alert {}: {}", rule.id, e);
+        }
+    }
+}
+
+pub async fn alerts_all(
+    State(repository): State<Arc<dyn AlertRepository>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let rules = repository.find_all().await.map_err(error_status)?;
+    Ok((StatusCode::OK, Json(rules)))
+}
+
+// alert rules reach every section, so only admins change them
+pub async fn create_alert(
+    State(repository): State<Arc<dyn AlertRepository>>,
+    actor: Actor,
+    Json(payload): Json<CreateAlertRule>,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !actor.may_manage() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    payload
+        .validate()
+        .map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+    let rule = repository.create(payload).await.map_err(error_status)?;
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+pub async fn find_alert(
+    Path(id): Path<i32>,
+    State(repository): State<Arc<dyn AlertRepository>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let rule = repository.find_by_id(id).await.map_err(error_status)?;
+    Ok((StatusCode::OK, Json(rule)))
+}
+
+pub async fn update_alert(
+    Path(id): Path<i32>,
+    State(repository): State<Arc<dyn AlertRepository>>,
+    actor: Actor,
+    Json(payload): Json<UpdateAlertRule>,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !actor.may_manage() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    payload
+        .validate()
+        .map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+    let rule = repository.update(id, payload).await.map_err(error_status)?;
+    Ok((StatusCode::OK, Json(rule)))
+}
+
+pub async fn delete_alert(
+    Path(id): Path<i32>,
+    State(repository): State<Arc<dyn AlertRepository>>,
+    actor: Actor,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !actor.may_manage() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    repository.delete(id).await.map_err(error_status)?;
+    Ok(StatusCode::NO_CONTENT)
+}
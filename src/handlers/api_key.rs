@@ -0,0 +1,77 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, Method, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::{collections::HashMap, sync::Arc};
+
+use crate::handlers::site::Site;
+use crate::repositories::api_key::{
+    models::{hash_key, ApiKey},
+    traits::ApiKeyRepository,
+};
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+// reads stay public, every other method needs a key whose scope covers the section it writes.
+// the routes are open when the app has no key store (the in-memory demo with OPEN_WRITES=true)
+pub async fn require_api_key<B>(
+    State(keys): State<Option<Arc<dyn ApiKeyRepository>>>,
+    Site(site): Site,
+    path: Option<Path<HashMap<String, String>>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, StatusCode> {
+    let (Some(keys), false) = (keys, is_read(request.method())) else {
+        return Ok(next.run(request).await);
+    };
+    let key = presented_key(keys.as_ref(), request.headers()).await?;
+
+    let building = path.and_then(|Path(mut params)| params.remove("building"));
+    if !key.allows(&site, building.as_deref()) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(next.run(request).await)
+}
+
+// the audit log names callers and their addresses and the webhooks their subscribers' urls,
+// so even reading them takes an unscoped key
+pub async fn require_admin_key<B>(
+    State(keys): State<Option<Arc<dyn ApiKeyRepository>>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, StatusCode> {
+    let Some(keys) = keys else {
+        return Ok(next.run(request).await);
+    };
+    if !presented_key(keys.as_ref(), request.headers())
+        .await?
+        .is_admin()
+    {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(next.run(request).await)
+}
+
+// the key of the X-Api-Key header, also the caller the audit log names when there is no token
+pub async fn presented_key(
+    keys: &dyn ApiKeyRepository,
+    headers: &HeaderMap,
+) -> Result<ApiKey, StatusCode> {
+    let key = headers
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    keys.find_by_hash(&hash_key(key))
+        .await
+        .map_err(|e| {
+            tracing::error!("api key repository error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::UNAUTHORIZED)
+}
+
+fn is_read(method: &Method) -> bool {
+    [Method::GET, Method::HEAD, Method::OPTIONS].contains(method)
+}
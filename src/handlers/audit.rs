@@ -0,0 +1,268 @@
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRef, FromRequestParts, Query, State},
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::{
+    env,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use crate::handlers::{
+    api_key::presented_key,
+    auth::{bearer_claims, unauthorized, JwtKeys, Role},
+};
+use crate::repositories::{
+    api_key::{models::ApiKey, traits::ApiKeyRepository},
+    audit::{
+        models::{AuditFilter, NewAuditEntry},
+        traits::AuditRepository,
+    },
+    section::models::{Section, UpdatePayload},
+};
+
+pub const ACTOR_HEADER: &str = "x-actor";
+
+// the proxies in front of the api, TRUSTED_PROXIES in the environment as addresses or
+// networks, e.g. "127.0.0.1,172.16.0.0/12". only their forwarded headers are believed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies(Arc<Vec<(IpAddr, u8)>>);
+
+impl TrustedProxies {
+    pub fn parse(spec: &str) -> Self {
+        let networks = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| {
+                let network = parse_network(entry);
+                if network.is_none() {
+                    tracing::warn!("ignoring the trusted proxy {:?}", entry);
+                }
+                network
+            })
+            .collect();
+        Self(Arc::new(networks))
+    }
+
+    pub fn from_env() -> Self {
+        env::var("TRUSTED_PROXIES")
+            .map(|spec| Self::parse(&spec))
+            .unwrap_or_default()
+    }
+
+    pub fn trusts(&self, ip: IpAddr) -> bool {
+        self.0
+            .iter()
+            .any(|&(network, prefix)| in_network(network, prefix, ip))
+    }
+
+    // the closest address that is not one of our proxies: the peer itself unless it is a
+    // trusted proxy, otherwise the forwarded chain walked from the right, since a client
+    // can put anything at the left of X-Forwarded-For
+    fn client(&self, peer: IpAddr, forwarded_for: Option<&str>, real_ip: Option<&str>) -> IpAddr {
+        if !self.trusts(peer) {
+            return peer;
+        }
+        let forwarded: Vec<IpAddr> = match forwarded_for {
+            Some(chain) => chain
+                .split(',')
+                .map_while(|ip| ip.trim().parse().ok())
+                .collect(),
+            None => real_ip.and_then(|ip| ip.parse().ok()).into_iter().collect(),
+        };
+        forwarded
+            .into_iter()
+            .rev()
+            .find(|&ip| !self.trusts(ip))
+            .unwrap_or(peer)
+    }
+}
+
+fn parse_network(entry: &str) -> Option<(IpAddr, u8)> {
+    let (ip, prefix) = match entry.split_once('/') {
+        Some((ip, prefix)) => (ip.parse::<IpAddr>().ok()?, prefix.parse::<u8>().ok()?),
+        None => {
+            let ip = entry.parse::<IpAddr>().ok()?;
+            (ip, if ip.is_ipv4() { 32 } else { 128 })
+        }
+    };
+    let bits = if ip.is_ipv4() { 32 } else { 128 };
+    (prefix <= bits).then_some((ip, prefix))
+}
+
+fn in_network(network: IpAddr, prefix: u8, ip: IpAddr) -> bool {
+    match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            u32::from(network) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            u128::from(network) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
+
+// who made a change, as far as the request tells. with jwt keys configured the caller is
+// the subject of its bearer token, and the token's role decides what it may change. with
+// api keys alone it is the name of its key, only an unauthenticated api takes its word
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Actor {
+    pub name: Option<String>,
+    pub source_ip: Option<String>,
+    // none when tokens are not required, every change is allowed then
+    pub role: Option<Role>,
+    // the key that named the caller when there is no token, its scope was checked on the route
+    pub key: Option<ApiKey>,
+}
+
+impl Actor {
+    pub fn may_transition(&self, payload: &UpdatePayload) -> bool {
+        self.role.iter().all(|role| role.may_transition(payload))
+    }
+
+    pub fn may_manage(&self) -> bool {
+        self.role.iter().all(|role| role.may_manage())
+    }
+
+    // what the admin routes ask of an api key, for the reads that only admins make
+    pub fn is_admin(&self) -> bool {
+        self.may_manage() && self.key.iter().all(ApiKey::is_admin)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Actor
+where
+    S: Send + Sync,
+    JwtKeys: FromRef<S>,
+    Option<Arc<dyn ApiKeyRepository>>: FromRef<S>,
+    TrustedProxies: FromRef<S>,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let header = |name: &str| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        let keys = JwtKeys::from_ref(state);
+        let (name, role, key) = if keys.is_enabled() {
+            let claims = bearer_claims(parts, &keys).ok_or_else(unauthorized)?;
+            (Some(claims.sub), Some(claims.role), None)
+        } else if let Some(api_keys) = Option::<Arc<dyn ApiKeyRepository>>::from_ref(state) {
+            let key = presented_key(api_keys.as_ref(), &parts.headers)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            (Some(key.name.clone()), None, Some(key))
+        } else {
+            // the header is only trusted when nothing authenticates the caller
+            (header(ACTOR_HEADER).map(str::to_string), None, None)
+        };
+        // behind nginx the peer is the proxy and the client comes from its headers
+        let source_ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| {
+                let proxies = TrustedProxies::from_ref(state);
+                let forwarded_for = header("x-forwarded-for");
+                proxies.client(addr.ip(), forwarded_for, header("x-real-ip"))
+            })
+            .map(|ip| ip.to_string());
+        Ok(Self {
+            name,
+            source_ip,
+            role,
+            key,
+        })
+    }
+}
+
+// the entry describes a committed change, a failure here must not fail the request
+pub async fn record_audit(
+    audit: &dyn AuditRepository,
+    actor: &Actor,
+    action: &str,
+    transition: Option<String>,
+    before: Option<&Section>,
+    after: Option<&Section>,
+) {
+    let Some(section_id) = after.or(before).map(|section| section.id) else {
+        return;
+    };
+    let entry = NewAuditEntry {
+        section_id,
+        action: action.to_string(),
+        transition,
+        actor: actor.name.clone(),
+        source_ip: actor.source_ip.clone(),
+        old_counters: before.map(Into::into),
+        new_counters: after.map(Into::into),
+    };
+    if let Err(e) = audit.record(entry).await {
+        tracing::error!(
+            "failed to record audit entry for section {}: {}",
+            section_id,
+            e
+        );
+    }
+}
+
+// the log names who changed what from where, so only admins read it
+pub async fn audit_log(
+    Query(filter): Query<AuditFilter>,
+    State(audit): State<Arc<dyn AuditRepository>>,
+    actor: Actor,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !actor.may_manage() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let entries = audit.find(filter).await.map_err(|e| {
+        tracing::error!("audit repository error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok((StatusCode::OK, Json(entries)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trusted_proxies() {
+        let proxies = TrustedProxies::parse("127.0.0.1, 172.16.0.0/12, ::1, 10.0.0.0/33, nginx");
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+        assert!(proxies.trusts(ip("127.0.0.1")));
+        assert!(proxies.trusts(ip("172.18.0.5")));
+        assert!(proxies.trusts(ip("::1")));
+        assert!(!proxies.trusts(ip("172.32.0.1")));
+        assert!(!proxies.trusts(ip("10.0.0.1")));
+
+        let proxy = ip("172.18.0.5");
+        let chain = Some("198.51.100.9, 203.0.113.7");
+        // what a client claims at the left of the chain is not believed
+        assert_eq!(proxies.client(proxy, chain, None), ip("203.0.113.7"));
+        assert_eq!(
+            proxies.client(proxy, Some("203.0.113.7, 127.0.0.1"), None),
+            ip("203.0.113.7")
+        );
+        assert_eq!(
+            proxies.client(proxy, None, Some("203.0.113.7")),
+            ip("203.0.113.7")
+        );
+        assert_eq!(proxies.client(proxy, Some("garbage"), None), proxy);
+        // a client talking to the api directly can't forward anything
+        let client = ip("198.51.100.9");
+        assert_eq!(proxies.client(client, chain, None), client);
+        assert_eq!(TrustedProxies::default().client(proxy, chain, None), proxy);
+    }
+}
@@ -0,0 +1,154 @@
+use axum::{
+    http::{header, request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+};
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use std::{env, fs, sync::Arc};
+
+use crate::repositories::section::models::UpdatePayload;
+
+// what a caller may do, students and the occupancy sensors only count people in and out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Student,
+    Device,
+    Staff,
+    Admin,
+}
+
+impl Role {
+    // staff take stalls out of service and back, the others only toggle available <-> occupied
+    pub fn may_transition(self, payload: &UpdatePayload) -> bool {
+        match self {
+            Role::Student | Role::Device => [&payload.current_status, &payload.next_status]
+                .iter()
+                .all(|status| *status == "available" || *status == "occupied"),
+            Role::Staff | Role::Admin => true,
+        }
+    }
+
+    // creating, deleting and restoring sections, and changing how many stalls they have
+    pub fn may_manage(self) -> bool {
+        self == Role::Admin
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub role: Role,
+    pub exp: u64,
+}
+
+// the keys bearer tokens are verified against: JWT_SECRET for HS256 (comma separated while
+// a secret is rotated) and JWT_PUBLIC_KEY_FILE for RS256. with none, no token is asked for
+#[derive(Clone, Default)]
+pub struct JwtKeys(Arc<Vec<(DecodingKey, Algorithm)>>);
+
+fn hs256_key(secret: &str) -> (DecodingKey, Algorithm) {
+    (
+        DecodingKey::from_secret(secret.as_bytes()),
+        Algorithm::HS256,
+    )
+}
+
+impl JwtKeys {
+    pub fn hs256(secrets: &[&str]) -> Self {
+        Self(Arc::new(
+            secrets.iter().map(|secret| hs256_key(secret)).collect(),
+        ))
+    }
+
+    pub fn from_env() -> Self {
+        let secrets = env::var("JWT_SECRET").unwrap_or_default();
+        let mut keys: Vec<_> = secrets
+            .split(',')
+            .map(str::trim)
+            .filter(|secret| !secret.is_empty())
+            .map(hs256_key)
+            .collect();
+        if let Ok(path) = env::var("JWT_PUBLIC_KEY_FILE") {
+            let pem = fs::read(&path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e));
+            let key = DecodingKey::from_rsa_pem(&pem)
+                .unwrap_or_else(|e| panic!("{} is not an RSA public key: {}", path, e));
+            keys.push((key, Algorithm::RS256));
+        }
+        Self(Arc::new(keys))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.0.is_empty()
+    }
+
+    // any of the keys may have signed the token, expired tokens are refused
+    pub fn verify(&self, token: &str) -> Option<Claims> {
+        self.0.iter().find_map(|(key, algorithm)| {
+            decode::<Claims>(token, key, &Validation::new(*algorithm))
+                .ok()
+                .map(|data| data.claims)
+        })
+    }
+}
+
+// the claims of the `Authorization: Bearer` token, none when it is missing or does not verify
+pub fn bearer_claims(parts: &Parts, keys: &JwtKeys) -> Option<Claims> {
+    parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|token| keys.verify(token.trim()))
+}
+
+pub fn unauthorized() -> Response {
+    let challenge = [(header::WWW_AUTHENTICATE, "Bearer")];
+    (StatusCode::UNAUTHORIZED, challenge).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    fn token(secret: &str, role: Role, exp: u64) -> String {
+        let claims = Claims {
+            sub: "alice".to_string(),
+            role,
+            exp,
+        };
+        let key = EncodingKey::from_secret(secret.as_bytes());
+        encode(&Header::default(), &claims, &key).unwrap()
+    }
+
+    #[test]
+    fn test_verify() {
+        let keys = JwtKeys::hs256(&["current", "previous"]);
+        let exp = chrono::Utc::now().timestamp() as u64 + 60;
+        let claims = keys.verify(&token("previous", Role::Staff, exp)).unwrap();
+        assert_eq!((claims.sub.as_str(), claims.role), ("alice", Role::Staff));
+        assert_eq!(keys.verify(&token("forged", Role::Admin, exp)), None);
+        assert_eq!(keys.verify(&token("current", Role::Admin, 1)), None);
+        assert_eq!(keys.verify("not a token"), None);
+        assert!(!JwtKeys::default().is_enabled());
+    }
+
+    #[test]
+    fn test_roles() {
+        let payload = |current: &str, next: &str| UpdatePayload {
+            current_status: current.to_string(),
+            next_status: next.to_string(),
+        };
+        for role in [Role::Student, Role::Device] {
+            assert!(role.may_transition(&payload("available", "occupied")));
+            assert!(role.may_transition(&payload("occupied", "available")));
+            assert!(!role.may_transition(&payload("available", "disabled")));
+            assert!(!role.may_transition(&payload("disabled", "available")));
+            assert!(!role.may_manage());
+        }
+        assert!(Role::Staff.may_transition(&payload("occupied", "disabled")));
+        assert!(!Role::Staff.may_manage());
+        assert!(Role::Admin.may_manage());
+    }
+}
@@ -0,0 +1,15 @@
+use axum::{http::Request, middleware::Next, response::Response};
+
+use crate::repositories::client;
+
+pub const CLIENT_HEADER: &str = "x-client-id";
+
+// repositories see who is calling without every handler passing it down
+pub async fn client_scope<B>(request: Request<B>, next: Next<B>) -> Response {
+    let client = request
+        .headers()
+        .get(CLIENT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    client::scope(client, next.run(request)).await
+}
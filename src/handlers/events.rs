@@ -1,18 +1,67 @@
-use axum::response::IntoResponse;
-use http::{Response, StatusCode};
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Json,
+};
+use http::{HeaderMap, Response, StatusCode};
 use hyper::Body;
-use std::sync::Arc;
-use tokio_stream::wrappers::UnboundedReceiverStream;
-use tokio_stream::StreamExt;
+use serde_json::json;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::time::{interval_at, Instant};
+use tokio_stream::wrappers::IntervalStream;
+use tokio_stream::{Stream, StreamExt};
 
-use crate::repositories::events::traits::EventTrait;
+use crate::handlers::{facility::FacilityTypes, site::Site};
+use crate::repositories::events::{
+    models::{Event, EventFilter, Subscription},
+    traits::EventTrait,
+};
+
+// comment lines keep idle connections from being cut by proxies
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+fn last_event_id(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+}
+
+fn format_event(event: &Event) -> String {
+    match &event.name {
+        Some(name) => format!(
+            "id: {}\nevent: {}\ndata: {}\n\n",
+            event.id, name, event.data
+        ),
+        None => format!("id: {}\ndata: {}\n\n", event.id, event.data),
+    }
+}
+
+fn event_stream(
+    subscription: Subscription,
+    heartbeat: Duration,
+) -> impl Stream<Item = Result<String, hyper::Error>> {
+    let heartbeats = IntervalStream::new(interval_at(Instant::now() + heartbeat, heartbeat))
+        .map(|_| ": heartbeat\n\n".to_string());
+    subscription
+        .map(|event| format_event(&event))
+        .merge(heartbeats)
+        .map(Ok)
+}
 
 pub async fn server_sents_events(
-    events: Arc<impl EventTrait>,
+    headers: HeaderMap,
+    filter: EventFilter,
+    events: Arc<dyn EventTrait>,
 ) -> Result<impl IntoResponse, StatusCode> {
-    let rx = events.subscribe().await;
-    let stream = UnboundedReceiverStream::new(rx)
-        .map(|msg| Ok::<_, hyper::Error>(format!("data: {}\n\n", msg)));
+    let subscription = events
+        .subscribe(last_event_id(&headers), filter)
+        .await
+        .map_err(|e| {
+            tracing::warn!("rejecting subscriber: {}", e);
+            StatusCode::SERVICE_UNAVAILABLE
+        })?;
+    let stream = event_stream(subscription, HEARTBEAT_INTERVAL);
 
     let response = Response::builder()
         .header("Content-Type", "text/event-stream")
@@ -22,36 +71,103 @@ pub async fn server_sents_events(
     Ok((StatusCode::OK, response))
 }
 
+// /events takes ?site= and ?facility_type=, the nested routes name them in the path
+pub async fn events_all(
+    headers: HeaderMap,
+    path: Option<Path<HashMap<String, String>>>,
+    Query(mut filter): Query<EventFilter>,
+    State(facilities): State<FacilityTypes>,
+    State(events): State<Arc<dyn EventTrait>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let mut params = path.map(|Path(params)| params).unwrap_or_default();
+    if let Some(site) = params.remove("site") {
+        filter.site = Some(site);
+    }
+    if let Some(facility_type) = facility_type(&mut params, &facilities)? {
+        filter.facility_type = Some(facility_type);
+    }
+    server_sents_events(headers, filter, events).await
+}
+
+// like the section routes, a building is on the default site unless the request names one.
+// it carries every facility type of the building unless under /facilities/:facility
+pub async fn events_building(
+    headers: HeaderMap,
+    Site(site): Site,
+    Path(mut params): Path<HashMap<String, String>>,
+    State(facilities): State<FacilityTypes>,
+    State(events): State<Arc<dyn EventTrait>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let filter = EventFilter {
+        site: Some(site),
+        facility_type: facility_type(&mut params, &facilities)?,
+        gender: params.remove("gender"),
+        building: params.remove("building"),
+        floor: None,
+    };
+    server_sents_events(headers, filter, events).await
+}
+
+// the facility type a /facilities/:facility route names, which must be configured
+fn facility_type(
+    params: &mut HashMap<String, String>,
+    facilities: &FacilityTypes,
+) -> Result<Option<String>, StatusCode> {
+    params
+        .remove("facility")
+        .map(|name| {
+            let facility = facilities.get(&name).ok_or(StatusCode::NOT_FOUND)?;
+            Ok(facility.name.clone())
+        })
+        .transpose()
+}
+
+pub async fn subscribers(State(events): State<Arc<dyn EventTrait>>) -> impl IntoResponse {
+    let subscribers = events.subscribers().await;
+    (StatusCode::OK, Json(json!({ "subscribers": subscribers })))
+}
+
 #[cfg(test)]
 mod test_sse_handler {
     use super::*;
     use axum::async_trait;
     use std::sync::Arc;
-    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+    use tokio::sync::mpsc::unbounded_channel;
 
-    use crate::repositories::events::traits::EventTrait;
+    use crate::repositories::events::{
+        models::{EventMessage, Events},
+        traits::EventTrait,
+    };
 
     struct MockEvents {
         message: String,
     }
 
     impl MockEvents {
-        pub async fn subscrive(&self) -> UnboundedReceiver<String> {
+        pub async fn subscrive(&self) -> Subscription {
             let (tx, rx) = unbounded_channel();
-            let _ = tx.send(self.message.clone());
-            rx
+            let _ = tx.send(Event::new(1, self.message.clone()));
+            Subscription::new(rx)
         }
     }
 
     #[async_trait]
     impl EventTrait for MockEvents {
-        async fn subscribe(&self) -> UnboundedReceiver<String> {
-            self.subscrive().await
+        async fn subscribe(
+            &self,
+            _last_event_id: Option<u64>,
+            _filter: EventFilter,
+        ) -> anyhow::Result<Subscription> {
+            Ok(self.subscrive().await)
         }
 
-        async fn notify(&self, _msg: String) -> anyhow::Result<()> {
+        async fn notify(&self, _msg: EventMessage) -> anyhow::Result<()> {
             Ok(())
         }
+
+        async fn subscribers(&self) -> usize {
+            1
+        }
     }
 
     #[tokio::test]
@@ -59,14 +175,79 @@ mod test_sse_handler {
         let mock_events = Arc::new(MockEvents {
             message: "Hello!".to_string(),
         });
-        let result = server_sents_events(mock_events).await;
+        let result =
+            server_sents_events(HeaderMap::new(), EventFilter::default(), mock_events).await;
         let mut response = result.unwrap().into_response();
 
         assert_eq!(
             response.headers().get("Content-Type").unwrap(),
             "text/event-stream"
         );
-        let bytes = hyper::body::to_bytes(response.body_mut()).await.unwrap();
-        assert_eq!(bytes, "data: Hello!\n\n".as_bytes());
+        let chunk = hyper::body::HttpBody::data(response.body_mut())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(chunk, "id: 1\ndata: Hello!\n\n".as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_last_event_id() {
+        let events = Arc::new(Events::new());
+        for msg in ["male/A/1", "male/A/2", "male/A/3"] {
+            events.notify(msg.to_string().into()).await.unwrap();
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Last-Event-ID", "2".parse().unwrap());
+        let response = server_sents_events(headers, EventFilter::default(), events.clone())
+            .await
+            .unwrap()
+            .into_response();
+        let mut body = response.into_body();
+        let chunk = hyper::body::HttpBody::data(&mut body)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(chunk, "id: 3\ndata: male/A/3\n\n".as_bytes());
+    }
+
+    #[test]
+    fn test_format_resync_event() {
+        assert_eq!(
+            format_event(&Event::resync(7)),
+            "id: 7\nevent: resync\ndata: resync required\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat() {
+        let (_tx, rx) = unbounded_channel();
+        let mut stream = Box::pin(event_stream(
+            Subscription::new(rx),
+            Duration::from_millis(10),
+        ));
+        let chunk = stream.next().await.unwrap().unwrap();
+        assert_eq!(chunk, ": heartbeat\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_dropped_with_response() {
+        let events = Arc::new(Events::new());
+        let response =
+            server_sents_events(HeaderMap::new(), EventFilter::default(), events.clone())
+                .await
+                .unwrap()
+                .into_response();
+        assert_eq!(events.subscribers().await, 1);
+
+        drop(response);
+        assert_eq!(events.subscribers().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_too_many_subscribers() {
+        let events = Arc::new(Events::new().with_max_subscribers(0));
+        let result = server_sents_events(HeaderMap::new(), EventFilter::default(), events).await;
+        assert_eq!(result.err(), Some(StatusCode::SERVICE_UNAVAILABLE));
     }
 }
@@ -0,0 +1,153 @@
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Path},
+    http::{request::Parts, StatusCode},
+};
+use std::{collections::HashMap, env};
+
+use crate::repositories::section::models::{UpdatePayload, DEFAULT_FACILITY, STATUSES};
+
+// a kind of facility sections count, and the statuses its stalls can be in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FacilityType {
+    pub name: String,
+    pub statuses: Vec<String>,
+}
+
+impl FacilityType {
+    pub fn new(name: &str, statuses: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            statuses: statuses.iter().map(|status| status.to_string()).collect(),
+        }
+    }
+
+    // a stall moves between two different statuses of the facility type
+    pub fn allows(&self, payload: &UpdatePayload) -> bool {
+        payload.current_status != payload.next_status
+            && [&payload.current_status, &payload.next_status]
+                .iter()
+                .all(|status| self.statuses.contains(status))
+    }
+
+    // "laundry" uses every status, "toilet:available|occupied" only the ones it names
+    fn parse(entry: &str) -> Option<Self> {
+        let (name, statuses) = match entry.split_once(':') {
+            Some((name, statuses)) => (name.trim(), statuses.split('|').collect()),
+            None => (entry.trim(), STATUSES.to_vec()),
+        };
+        let statuses: Vec<&str> = statuses.into_iter().map(str::trim).collect();
+        // new sections start with every stall available
+        if name.is_empty()
+            || !statuses.contains(&"available")
+            || !statuses.iter().all(|status| STATUSES.contains(status))
+        {
+            return None;
+        }
+        Some(Self::new(name, &statuses))
+    }
+}
+
+// the facility types sections can be created for, FACILITY_TYPES in the environment,
+// e.g. "shower,laundry,toilet:available|occupied"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FacilityTypes(Vec<FacilityType>);
+
+impl FacilityTypes {
+    // the /showerrooms routes are always served, so shower is added when it is missing
+    pub fn new(mut types: Vec<FacilityType>) -> Self {
+        if !types
+            .iter()
+            .any(|facility| facility.name == DEFAULT_FACILITY)
+        {
+            types.insert(0, FacilityType::new(DEFAULT_FACILITY, &STATUSES));
+        }
+        Self(types)
+    }
+
+    pub fn parse(spec: &str) -> Self {
+        let types = spec
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .filter_map(|entry| {
+                let facility = FacilityType::parse(entry);
+                if facility.is_none() {
+                    tracing::warn!("ignoring the facility type {:?}", entry);
+                }
+                facility
+            })
+            .collect();
+        Self::new(types)
+    }
+
+    pub fn from_env() -> Self {
+        env::var("FACILITY_TYPES")
+            .map(|spec| Self::parse(&spec))
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&FacilityType> {
+        self.0.iter().find(|facility| facility.name == name)
+    }
+}
+
+impl Default for FacilityTypes {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+// /facilities/:facility/... routes name the facility type, the /showerrooms ones are showers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Facility(pub FacilityType);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Facility
+where
+    S: Send + Sync,
+    FacilityTypes: FromRef<S>,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let path = Path::<HashMap<String, String>>::from_request_parts(parts, state).await;
+        let name = path
+            .ok()
+            .and_then(|Path(mut params)| params.remove("facility"))
+            .unwrap_or(DEFAULT_FACILITY.to_string());
+        let facility = FacilityTypes::from_ref(state).get(&name).cloned();
+        facility.map(Self).ok_or(StatusCode::NOT_FOUND)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_facility_types() {
+        let types = FacilityTypes::parse("laundry, toilet:available|occupied,booth:occupied,,x:y");
+        let names: Vec<&str> = types
+            .0
+            .iter()
+            .map(|facility| facility.name.as_str())
+            .collect();
+        assert_eq!(names, ["shower", "laundry", "toilet"]);
+        assert_eq!(types.get("laundry").unwrap().statuses, STATUSES);
+        assert_eq!(
+            types.get("toilet").unwrap().statuses,
+            ["available", "occupied"]
+        );
+        assert_eq!(FacilityTypes::parse(""), FacilityTypes::default());
+
+        let toilet = types.get("toilet").unwrap();
+        let payload = |current: &str, next: &str| UpdatePayload {
+            current_status: current.to_string(),
+            next_status: next.to_string(),
+        };
+        assert!(toilet.allows(&payload("available", "occupied")));
+        assert!(!toilet.allows(&payload("available", "disabled")));
+        assert!(!toilet.allows(&payload("disabled", "available")));
+        assert!(!toilet.allows(&payload("occupied", "occupied")));
+    }
+}
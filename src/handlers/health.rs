@@ -0,0 +1,26 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::repositories::section::traits::SectionRepository;
+
+// answers every request while the storage backend is still connecting
+pub async fn not_ready() -> impl IntoResponse {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({ "status": "not ready" })),
+    )
+}
+
+pub async fn health<R: SectionRepository>(State(repository): State<Arc<R>>) -> impl IntoResponse {
+    match repository.ping().await {
+        Ok(()) => (StatusCode::OK, Json(json!({ "status": "ready" }))),
+        Err(e) => {
+            tracing::warn!("health check failed: {}", e);
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({ "status": "not ready" })),
+            )
+        }
+    }
+}
@@ -1,20 +1,41 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
-    response::IntoResponse,
+    response::{IntoResponse, Response},
     Json,
 };
+use serde_json::json;
 use std::sync::Arc;
 
-use crate::{
-    repositories::{
-        events::traits::EventTrait,
-        section::{
-            models::{CreateSection, SectionInfo, UpdatePayload, UpdateSection},
-            traits::SectionRepository,
+use crate::handlers::{
+    alert::notify_alerts,
+    audit::{record_audit, Actor},
+    facility::Facility,
+    site::{BuildingPath, GenderPath, Location, Site},
+};
+use crate::repositories::{
+    alert::traits::AlertRepository,
+    audit::{
+        models::{CAPACITY_CHANGED, CREATED, DELETED, RESTORED, UPDATED},
+        traits::AuditRepository,
+    },
+    events::{
+        models::{
+            EventMessage, SECTION_CAPACITY_CHANGED, SECTION_CREATED, SECTION_DELETED,
+            SECTION_RESTORED,
         },
+        traits::EventTrait,
+    },
+    section::{
+        cached::SectionCacheStats,
+        errors::RepositoryError,
+        instrumented::SectionMetrics,
+        models::{
+            ArchiveQuery, CreateSection, Section, SectionInfo, UpdateCapacity, UpdatePayload,
+            UpdateSection,
+        },
+        traits::SectionRepository,
     },
-    EVENTS,
 };
 
 pub async fn handler_404() -> impl IntoResponse {
@@ -25,86 +46,424 @@ pub async fn root() -> &'static str {
     "Hello, World!"
 }
 
+// a failed read is the backend's fault, not a missing section
+fn read_error(e: anyhow::Error) -> StatusCode {
+    tracing::error!("section repository error: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+// admins list archived sections next to the active ones with ?include_archived=true, the
+// caller only has to say who it is then, the active ones stay public
+async fn with_archived<R: SectionRepository>(
+    repository: &R,
+    active: anyhow::Result<Vec<Section>>,
+    query: ArchiveQuery,
+    actor: Result<Actor, Response>,
+    at: impl Fn(&Section) -> bool,
+) -> Result<Vec<Section>, Response> {
+    let mut sections = active.map_err(|e| read_error(e).into_response())?;
+    if !query.include_archived {
+        return Ok(sections);
+    }
+    if !actor?.is_admin() {
+        return Err(StatusCode::FORBIDDEN.into_response());
+    }
+    let archived = repository
+        .find_archived()
+        .await
+        .map_err(|e| read_error(e).into_response())?;
+    sections.extend(archived.into_iter().filter(|section| at(section)));
+    sections.sort_by_key(|section| section.id);
+    Ok(sections)
+}
+
+async fn find_at<R: SectionRepository>(
+    repository: &R,
+    info: &SectionInfo,
+) -> anyhow::Result<Vec<Section>> {
+    repository
+        .find_by_floor(
+            info.site.clone(),
+            info.facility_type.clone(),
+            info.gender.clone(),
+            info.building.clone(),
+            info.floor,
+        )
+        .await
+}
+
+// the active section at the location a write is about
+async fn section_at<R: SectionRepository>(
+    repository: &R,
+    info: &SectionInfo,
+) -> Result<Section, Response> {
+    let sections = find_at(repository, info)
+        .await
+        .map_err(|e| read_error(e).into_response())?;
+    sections
+        .first()
+        .cloned()
+        .ok_or(StatusCode::NOT_FOUND.into_response())
+}
+
+// a write that collided with an active section tells the client which one
+async fn write_error<R: SectionRepository>(repository: &R, e: anyhow::Error) -> Response {
+    match e.downcast_ref::<RepositoryError>() {
+        Some(RepositoryError::DuplicateLocation(info)) => {
+            let existing = find_at(repository, info)
+                .await
+                .ok()
+                .and_then(|sections| sections.first().map(|section| section.id));
+            let body = json!({ "message": e.to_string(), "id": existing });
+            (StatusCode::CONFLICT, Json(body)).into_response()
+        }
+        Some(RepositoryError::NotFound(_)) => StatusCode::NOT_FOUND.into_response(),
+        Some(RepositoryError::InvalidSection(_)) => {
+            let body = json!({ "message": e.to_string() });
+            (StatusCode::BAD_REQUEST, Json(body)).into_response()
+        }
+        Some(RepositoryError::InvalidTransition(_))
+        | Some(RepositoryError::InsufficientCapacity(_)) => {
+            let body = json!({ "message": e.to_string() });
+            (StatusCode::CONFLICT, Json(body)).into_response()
+        }
+        None => {
+            tracing::error!("section repository error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+// the change is committed already, so a failure to announce it is logged and the request succeeds
+async fn publish(events: &dyn EventTrait, message: EventMessage) {
+    if let Err(e) = events.notify(message).await {
+        tracing::error!("failed to publish event: {}", e);
+    }
+}
+
 pub async fn showerrooms_all<R: SectionRepository>(
+    Site(site): Site,
+    Facility(facility): Facility,
+    Query(query): Query<ArchiveQuery>,
+    actor: Result<Actor, Response>,
     State(repository): State<Arc<R>>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let sections = repository.find_all().await.unwrap();
+) -> Result<impl IntoResponse, Response> {
+    let at = |section: &Section| section.site == site && section.facility_type == facility.name;
+    let mut sections = repository
+        .find_all()
+        .await
+        .map_err(|e| read_error(e).into_response())?;
+    sections.retain(at);
+    let sections = with_archived(repository.as_ref(), Ok(sections), query, actor, at).await?;
     Ok((StatusCode::OK, Json(sections)))
 }
 
 pub async fn showerrooms_gender<R: SectionRepository>(
-    Path(gender): Path<String>,
+    Path(GenderPath { gender }): Path<GenderPath>,
+    Site(site): Site,
+    Facility(facility): Facility,
+    Query(query): Query<ArchiveQuery>,
+    actor: Result<Actor, Response>,
     State(repository): State<Arc<R>>,
-) -> Result<impl IntoResponse, StatusCode> {
+) -> Result<impl IntoResponse, Response> {
     //use find_by gender
     let sections = repository
-        .find_by_gender(gender)
-        .await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+        .find_by_gender(site.clone(), facility.name.clone(), gender.clone())
+        .await;
+    let sections = with_archived(repository.as_ref(), sections, query, actor, |section| {
+        section.site == site && section.facility_type == facility.name && section.gender == gender
+    })
+    .await?;
+    if sections.is_empty() {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    }
     Ok((StatusCode::OK, Json(sections)))
 }
 
 pub async fn showerrooms_building<R: SectionRepository>(
-    Path((gender, building)): Path<(String, String)>,
+    Path(BuildingPath { gender, building }): Path<BuildingPath>,
+    Site(site): Site,
+    Facility(facility): Facility,
+    Query(query): Query<ArchiveQuery>,
+    actor: Result<Actor, Response>,
     State(repository): State<Arc<R>>,
-) -> Result<impl IntoResponse, StatusCode> {
+) -> Result<impl IntoResponse, Response> {
     let sections = repository
-        .find_by_building(gender, building)
-        .await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+        .find_by_building(
+            site.clone(),
+            facility.name.clone(),
+            gender.clone(),
+            building.clone(),
+        )
+        .await;
+    let sections = with_archived(repository.as_ref(), sections, query, actor, |section| {
+        section.site == site
+            && section.facility_type == facility.name
+            && section.gender == gender
+            && section.building == building
+    })
+    .await?;
+    if sections.is_empty() {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    }
     Ok((StatusCode::OK, Json(sections)))
 }
 
 pub async fn showerrooms_floor<R: SectionRepository>(
-    Path((gender, building, floor)): Path<(String, String, i32)>,
+    Location(info, _): Location,
+    Query(query): Query<ArchiveQuery>,
+    actor: Result<Actor, Response>,
     State(repository): State<Arc<R>>,
+) -> Result<impl IntoResponse, Response> {
+    let sections = find_at(repository.as_ref(), &info).await;
+    let sections = with_archived(repository.as_ref(), sections, query, actor, |section| {
+        SectionInfo::from(section.clone()) == info
+    })
+    .await?;
+    if sections.is_empty() {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    }
+    Ok((StatusCode::OK, Json(sections)))
+}
+
+pub async fn cache_stats(
+    State(cache): State<Option<SectionCacheStats>>,
 ) -> Result<impl IntoResponse, StatusCode> {
-    let sections = repository
-        .find_by_floor(gender, building, floor)
+    let stats = cache.ok_or(StatusCode::NOT_FOUND)?.snapshot();
+    Ok((StatusCode::OK, Json(stats)))
+}
+
+pub async fn repository_metrics(
+    State(metrics): State<Option<SectionMetrics>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let metrics = metrics.ok_or(StatusCode::NOT_FOUND)?.snapshot();
+    Ok((StatusCode::OK, Json(metrics)))
+}
+
+pub async fn schema_version<R: SectionRepository>(
+    State(repository): State<Arc<R>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let version = repository
+        .schema_version()
         .await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
-    Ok((StatusCode::OK, Json(sections)))
+        .map_err(read_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok((StatusCode::OK, Json(version)))
 }
 
 pub async fn create_section<R: SectionRepository>(
-    Path((gender, building, floor)): Path<(String, String, i32)>,
+    Location(info, _): Location,
     State(repository): State<Arc<R>>,
+    State(events): State<Arc<dyn EventTrait>>,
+    State(audit): State<Arc<dyn AuditRepository>>,
+    actor: Actor,
     Json(payload): Json<CreateSection>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let info = SectionInfo {
-        gender,
-        building,
-        floor,
+) -> Result<impl IntoResponse, Response> {
+    if !actor.may_manage() {
+        return Err(StatusCode::FORBIDDEN.into_response());
+    }
+    let section = match repository.create(payload, info).await {
+        Ok(section) => section,
+        Err(e) => return Err(write_error(repository.as_ref(), e).await),
     };
-    let section = repository.create(payload, info).await.unwrap();
+
+    if !repository.has_outbox() {
+        let msg = EventMessage::section(SECTION_CREATED, &section);
+        publish(events.as_ref(), msg).await;
+    }
+    record_audit(audit.as_ref(), &actor, CREATED, None, None, Some(&section)).await;
 
     Ok((StatusCode::CREATED, Json(section)))
 }
 
 pub async fn update_section<R: SectionRepository>(
-    Path((gender, building, floor)): Path<(String, String, i32)>,
+    Location(info, facility): Location,
     State(repository): State<Arc<R>>,
+    State(events): State<Arc<dyn EventTrait>>,
+    State(alerts): State<Arc<dyn AlertRepository>>,
+    State(audit): State<Arc<dyn AuditRepository>>,
+    actor: Actor,
     Json(payload): Json<UpdatePayload>,
-) -> Result<impl IntoResponse, StatusCode> {
-    // first get the id of the section
-    let id = repository
-        .find_by_floor(gender.clone(), building.clone(), floor)
-        .await
-        .unwrap()
-        .first()
-        .unwrap()
-        .id;
+) -> Result<impl IntoResponse, Response> {
+    if !actor.may_transition(&payload) {
+        return Err(StatusCode::FORBIDDEN.into_response());
+    }
+    // a toilet, for one, has no disabled stalls to count
+    if !facility.allows(&payload) {
+        return Err(StatusCode::BAD_REQUEST.into_response());
+    }
+    // first get the section, its counters are the baseline for the alert rules
+    let before = section_at(repository.as_ref(), &info).await?;
+    let transition = payload.transition();
     let section = UpdateSection {
-        id,
+        id: before.id,
         current_status: payload.current_status,
         next_status: payload.next_status,
     };
-    let section = repository.update(section).await.unwrap();
+    let section = match repository.update(section).await {
+        Ok(section) => section,
+        Err(e) => return Err(write_error(repository.as_ref(), e).await),
+    };
 
     // if section update is successful, notify the event
-    let events = Arc::clone(&EVENTS);
-    let msg = format!("{}/{}/{}", gender, building, floor);
-    events.notify(msg).await.unwrap();
+    if !repository.has_outbox() {
+        publish(events.as_ref(), EventMessage::updated(&section)).await;
+    }
+    notify_alerts(alerts.as_ref(), events.as_ref(), &before, &section).await;
+    record_audit(
+        audit.as_ref(),
+        &actor,
+        UPDATED,
+        Some(transition),
+        Some(&before),
+        Some(&section),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(section)))
+}
+
+// several stalls change at once, either every transition applies or none does
+pub async fn batch_transitions<R: SectionRepository>(
+    Location(info, facility): Location,
+    State(repository): State<Arc<R>>,
+    State(events): State<Arc<dyn EventTrait>>,
+    State(alerts): State<Arc<dyn AlertRepository>>,
+    State(audit): State<Arc<dyn AuditRepository>>,
+    actor: Actor,
+    Json(payloads): Json<Vec<UpdatePayload>>,
+) -> Result<impl IntoResponse, Response> {
+    if !payloads.iter().all(|payload| actor.may_transition(payload)) {
+        return Err(StatusCode::FORBIDDEN.into_response());
+    }
+    if !payloads.iter().all(|payload| facility.allows(payload)) {
+        return Err(StatusCode::BAD_REQUEST.into_response());
+    }
+    let id = section_at(repository.as_ref(), &info).await?.id;
+    // every step is audited on its own, with the counters on both sides of it
+    let applied = async {
+        let mut tx = repository.begin().await?;
+        let before = tx.find_by_id(id).await?;
+        let mut steps = Vec::with_capacity(payloads.len());
+        let mut section = before.clone();
+        for payload in &payloads {
+            let update = UpdateSection {
+                id,
+                current_status: payload.current_status.clone(),
+                next_status: payload.next_status.clone(),
+            };
+            // the handle rolls back when dropped
+            let next = tx.update(update).await?;
+            steps.push((payload.transition(), section, next.clone()));
+            section = next;
+        }
+        tx.commit().await?;
+        anyhow::Ok((before, steps, section))
+    };
+    let (before, steps, section) = match applied.await {
+        Ok(applied) => applied,
+        Err(e) => return Err(write_error(repository.as_ref(), e).await),
+    };
+
+    // one event per transition, the same as the outbox rows written in the transaction
+    if !repository.has_outbox() {
+        for (_, _, next) in &steps {
+            publish(events.as_ref(), EventMessage::updated(next)).await;
+        }
+    }
+    notify_alerts(alerts.as_ref(), events.as_ref(), &before, &section).await;
+    for (transition, old, new) in &steps {
+        let transition = Some(transition.clone());
+        record_audit(
+            audit.as_ref(),
+            &actor,
+            UPDATED,
+            transition,
+            Some(old),
+            Some(new),
+        )
+        .await;
+    }
+
+    Ok((StatusCode::OK, Json(section)))
+}
+
+pub async fn update_capacity<R: SectionRepository>(
+    Location(info, _): Location,
+    State(repository): State<Arc<R>>,
+    State(events): State<Arc<dyn EventTrait>>,
+    State(alerts): State<Arc<dyn AlertRepository>>,
+    State(audit): State<Arc<dyn AuditRepository>>,
+    actor: Actor,
+    Json(payload): Json<UpdateCapacity>,
+) -> Result<impl IntoResponse, Response> {
+    if !actor.may_manage() {
+        return Err(StatusCode::FORBIDDEN.into_response());
+    }
+    let before = section_at(repository.as_ref(), &info).await?;
+    // fails when the new total can't hold the stalls currently in use
+    let section = match repository.update_capacity(before.id, payload.total).await {
+        Ok(section) => section,
+        Err(e) => return Err(write_error(repository.as_ref(), e).await),
+    };
+
+    if !repository.has_outbox() {
+        let msg = EventMessage::section(SECTION_CAPACITY_CHANGED, &section);
+        publish(events.as_ref(), msg).await;
+    }
+    notify_alerts(alerts.as_ref(), events.as_ref(), &before, &section).await;
+    let (old, new) = (Some(&before), Some(&section));
+    record_audit(audit.as_ref(), &actor, CAPACITY_CHANGED, None, old, new).await;
+
+    Ok((StatusCode::OK, Json(section)))
+}
+
+pub async fn delete_section<R: SectionRepository>(
+    Location(info, _): Location,
+    State(repository): State<Arc<R>>,
+    State(events): State<Arc<dyn EventTrait>>,
+    State(audit): State<Arc<dyn AuditRepository>>,
+    actor: Actor,
+) -> Result<impl IntoResponse, Response> {
+    if !actor.may_manage() {
+        return Err(StatusCode::FORBIDDEN.into_response());
+    }
+    let section = section_at(repository.as_ref(), &info).await?;
+    if let Err(e) = repository.delete(section.id).await {
+        return Err(write_error(repository.as_ref(), e).await);
+    }
+
+    // the deleted section is sent so subscribers know what went away
+    if !repository.has_outbox() {
+        let msg = EventMessage::section(SECTION_DELETED, &section);
+        publish(events.as_ref(), msg).await;
+    }
+    record_audit(audit.as_ref(), &actor, DELETED, None, Some(&section), None).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// brings an archived section back, unless another section took its location meanwhile
+pub async fn restore_section<R: SectionRepository>(
+    Path(id): Path<i32>,
+    State(repository): State<Arc<R>>,
+    State(events): State<Arc<dyn EventTrait>>,
+    State(audit): State<Arc<dyn AuditRepository>>,
+    actor: Actor,
+) -> Result<impl IntoResponse, Response> {
+    if !actor.may_manage() {
+        return Err(StatusCode::FORBIDDEN.into_response());
+    }
+    let section = match repository.restore(id).await {
+        Ok(section) => section,
+        Err(e) => return Err(write_error(repository.as_ref(), e).await),
+    };
+
+    if !repository.has_outbox() {
+        let msg = EventMessage::section(SECTION_RESTORED, &section);
+        publish(events.as_ref(), msg).await;
+    }
+    record_audit(audit.as_ref(), &actor, RESTORED, None, None, Some(&section)).await;
 
     Ok((StatusCode::OK, Json(section)))
 }
@@ -0,0 +1,103 @@
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Path, Query},
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::{collections::HashMap, convert::Infallible, env};
+
+use crate::handlers::facility::{Facility, FacilityType, FacilityTypes};
+use crate::repositories::section::models::{SectionInfo, SiteQuery, DEFAULT_SITE};
+
+// the site of the routes that name none, DEFAULT_SITE in the environment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefaultSite(pub String);
+
+impl DefaultSite {
+    pub fn from_env() -> Self {
+        Self(env::var("DEFAULT_SITE").unwrap_or(DEFAULT_SITE.to_string()))
+    }
+}
+
+impl Default for DefaultSite {
+    fn default() -> Self {
+        Self(DEFAULT_SITE.to_string())
+    }
+}
+
+// /sites/:site/... routes name the site in the path, the others take ?site= or the default
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Site(pub String);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Site
+where
+    S: Send + Sync,
+    DefaultSite: FromRef<S>,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let path = Path::<HashMap<String, String>>::from_request_parts(parts, state).await;
+        if let Some(site) = path.ok().and_then(|Path(mut params)| params.remove("site")) {
+            return Ok(Self(site));
+        }
+        let query = Query::<SiteQuery>::from_request_parts(parts, state).await;
+        let site = query.ok().and_then(|Query(query)| query.site);
+        Ok(Self(site.unwrap_or_else(|| DefaultSite::from_ref(state).0)))
+    }
+}
+
+// the same handlers serve both route shapes, so the path is read by name
+#[derive(Debug, Deserialize)]
+pub struct GenderPath {
+    pub gender: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BuildingPath {
+    pub gender: String,
+    pub building: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FloorPath {
+    gender: String,
+    building: String,
+    floor: i32,
+}
+
+// the section a floor route is about, on the site and of the facility type the request is for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location(pub SectionInfo, pub FacilityType);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Location
+where
+    S: Send + Sync,
+    DefaultSite: FromRef<S>,
+    FacilityTypes: FromRef<S>,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(path) = Path::<FloorPath>::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let Facility(facility) = Facility::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let Site(site) = Site::from_request_parts(parts, state)
+            .await
+            .unwrap_or_else(|never| match never {});
+        let info = SectionInfo {
+            site,
+            facility_type: facility.name.clone(),
+            gender: path.gender,
+            building: path.building,
+            floor: path.floor,
+        };
+        Ok(Self(info, facility))
+    }
+}
@@ -0,0 +1,117 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use std::sync::Arc;
+use validator::Validate;
+
+use crate::handlers::audit::Actor;
+use crate::repositories::webhook::{
+    errors::WebhookError,
+    models::{CreateWebhook, UpdateWebhook},
+    traits::WebhookRepository,
+};
+
+fn error_status(e: anyhow::Error) -> StatusCode {
+    match e.downcast_ref::<WebhookError>() {
+        Some(WebhookError::NotFound(_)) => StatusCode::NOT_FOUND,
+        None => {
+            tracing::error!("webhook repository error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+// subscriber urls, delivery logs and dead letters are only shown to admins
+pub async fn webhooks_all(
+    State(repository): State<Arc<dyn WebhookRepository>>,
+    actor: Actor,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !actor.may_manage() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let webhooks = repository.find_all().await.map_err(error_status)?;
+    Ok((StatusCode::OK, Json(webhooks)))
+}
+
+// webhooks reach every section, so only admins change them
+pub async fn create_webhook(
+    State(repository): State<Arc<dyn WebhookRepository>>,
+    actor: Actor,
+    Json(payload): Json<CreateWebhook>,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !actor.may_manage() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    payload
+        .validate()
+        .map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+    let webhook = repository.create(payload).await.map_err(error_status)?;
+    Ok((StatusCode::CREATED, Json(webhook)))
+}
+
+pub async fn find_webhook(
+    Path(id): Path<i32>,
+    State(repository): State<Arc<dyn WebhookRepository>>,
+    actor: Actor,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !actor.may_manage() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let webhook = repository.find_by_id(id).await.map_err(error_status)?;
+    Ok((StatusCode::OK, Json(webhook)))
+}
+
+pub async fn update_webhook(
+    Path(id): Path<i32>,
+    State(repository): State<Arc<dyn WebhookRepository>>,
+    actor: Actor,
+    Json(payload): Json<UpdateWebhook>,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !actor.may_manage() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    payload
+        .validate()
+        .map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+    let webhook = repository.update(id, payload).await.map_err(error_status)?;
+    Ok((StatusCode::OK, Json(webhook)))
+}
+
+pub async fn delete_webhook(
+    Path(id): Path<i32>,
+    State(repository): State<Arc<dyn WebhookRepository>>,
+    actor: Actor,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !actor.may_manage() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    repository.delete(id).await.map_err(error_status)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn webhook_deliveries(
+    Path(id): Path<i32>,
+    State(repository): State<Arc<dyn WebhookRepository>>,
+    actor: Actor,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !actor.may_manage() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    repository.find_by_id(id).await.map_err(error_status)?;
+    let deliveries = repository.find_deliveries(id).await.map_err(error_status)?;
+    Ok((StatusCode::OK, Json(deliveries)))
+}
+
+pub async fn dead_letters(
+    State(repository): State<Arc<dyn WebhookRepository>>,
+    actor: Actor,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !actor.may_manage() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let deliveries = repository.find_dead_letters().await.map_err(error_status)?;
+    Ok((StatusCode::OK, Json(deliveries)))
+}
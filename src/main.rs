@@ -1,29 +1,99 @@
+#[cfg(not(any(feature = "postgres", feature = "sqlite", feature = "in-memory")))]
+compile_error!("enable at least one storage backend: postgres, sqlite or in-memory");
+
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+mod admin;
 mod handlers;
 mod repositories;
+mod state;
 
+#[cfg(feature = "postgres")]
+use crate::repositories::{
+    alert::db::DBAlertRepository,
+    api_key::db::DBApiKeyRepository,
+    audit::db::DBAuditRepository,
+    events::{db::DBEvents, outbox::OutboxDispatcher},
+    pool::PoolConfig,
+    section::db::{DBSectionRepository, MIGRATOR},
+    webhook::db::DBWebhookRepository,
+};
+#[cfg(feature = "in-memory")]
+use crate::repositories::{
+    alert::in_memory::InMemoryAlertRepository,
+    api_key::{
+        in_memory::InMemoryApiKeyRepository,
+        models::{generate_key, hash_key, NewApiKey},
+    },
+    audit::in_memory::InMemoryAuditRepository,
+    section::in_memory::InMemorySectionRepository,
+    webhook::in_memory::InMemoryWebhookRepository,
+};
 use crate::repositories::{
-    events::models::Events,
-    section::{db::DBSectionRepository, traits::SectionRepository},
+    alert::traits::AlertRepository,
+    api_key::traits::ApiKeyRepository,
+    audit::traits::AuditRepository,
+    events::{models::Events, traits::EventTrait},
+    section::{
+        cached::CachedSectionRepository,
+        instrumented::{InstrumentedSectionRepository, SectionMetrics},
+        traits::SectionRepository,
+    },
+    webhook::{traits::WebhookRepository, worker::WebhookWorker},
 };
+#[cfg(feature = "sqlite")]
+use crate::repositories::{
+    api_key::sqlite::SqliteApiKeyRepository, section::sqlite::SqliteSectionRepository,
+};
+use crate::state::AppState;
 
 use handlers::{
-    events::server_sents_events,
+    alert::{alerts_all, create_alert, delete_alert, find_alert, update_alert},
+    api_key::{require_admin_key, require_api_key, API_KEY_HEADER},
+    audit::{audit_log, TrustedProxies, ACTOR_HEADER},
+    auth::JwtKeys,
+    client::{client_scope, CLIENT_HEADER},
+    events::{events_all, events_building, subscribers},
+    facility::FacilityTypes,
+    health::{health, not_ready},
     section::{
-        create_section, handler_404, root, showerrooms_all, showerrooms_building,
-        showerrooms_floor, showerrooms_gender, update_section,
+        batch_transitions, cache_stats, create_section, delete_section, handler_404,
+        repository_metrics, restore_section, root, schema_version, showerrooms_all,
+        showerrooms_building, showerrooms_floor, showerrooms_gender, update_capacity,
+        update_section,
+    },
+    site::DefaultSite,
+    webhook::{
+        create_webhook, dead_letters, delete_webhook, find_webhook, update_webhook,
+        webhook_deliveries, webhooks_all,
     },
 };
 
-use axum::{routing::get, Router};
+#[cfg(feature = "postgres")]
+use anyhow::Context;
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    middleware,
+    routing::{get, post, put},
+    Router,
+};
 use dotenv::dotenv;
-use hyper::{header, http::HeaderValue};
-use sqlx::PgPool;
-use std::{env, net::SocketAddr, sync::Arc};
+use hyper::{
+    header,
+    http::{HeaderName, HeaderValue, Request},
+    server::conn::AddrStream,
+    service::make_service_fn,
+};
+use std::{
+    convert::Infallible,
+    env,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tower::ServiceExt;
 use tower_http::cors::{Any, CorsLayer};
 
-static EVENTS: once_cell::sync::Lazy<Arc<Events>> =
-    once_cell::sync::Lazy::new(|| Arc::new(Events::new()));
-
 #[tokio::main]
 async fn main() {
     let log_level = env::var("RUST_LOG").unwrap_or("info".to_string());
@@ -31,85 +101,541 @@ async fn main() {
 
     dotenv().ok();
 
-    let database_url = &env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    // `api-shower keys ...` is the admin cli, it runs instead of the server
+    #[cfg(any(feature = "postgres", feature = "sqlite"))]
+    if env::args().nth(1).as_deref() == Some("keys") {
+        let args: Vec<String> = env::args().skip(2).collect();
+        if let Err(e) = admin::run(&args).await {
+            eprintln!("{:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let database_url = env::var("DATABASE_URL").ok();
     let (tx, rx) = tokio::sync::oneshot::channel::<()>();
 
-    tracing::info!("Starting server at: {}", database_url);
-    let pool = PgPool::connect(database_url)
-        .await
-        .expect(&format!("Failed to connect to {}", database_url));
-    let repository = DBSectionRepository::new(pool.clone());
+    let events = Events::new();
+    let events = match env::var("SSE_MAX_SUBSCRIBERS").map(|max| max.parse::<usize>()) {
+        Ok(Ok(max)) => events.with_max_subscribers(max),
+        _ => events,
+    };
 
-    let app = create_app(repository);
-    // add 404 handler
-    let app = app.fallback(handler_404);
     //let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
     let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
     tracing::info!("mode: {}", log_level);
     tracing::debug!("Listening on {}", addr);
 
+    // the server listens right away, health reports not ready until the app below is built
+    let router = Arc::new(Mutex::new(starting_app()));
+    let service = {
+        let router = Arc::clone(&router);
+        make_service_fn(move |conn: &AddrStream| {
+            let router = Arc::clone(&router);
+            // the peer address is the source ip of audit entries when no proxy forwards one
+            let remote_addr = conn.remote_addr();
+            let service = tower::service_fn(move |mut request: Request<Body>| {
+                request.extensions_mut().insert(ConnectInfo(remote_addr));
+                let app = router.lock().unwrap().clone();
+                app.oneshot(request)
+            });
+            async move { Ok::<_, Infallible>(service) }
+        })
+    };
     let graceful = axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(service)
         .with_graceful_shutdown(async {
             rx.await.ok();
         });
+    let server = tokio::spawn(graceful);
+
+    // the scheme picks the backend, sqlite is meant for a single box without postgres
+    let app = match database_url.as_deref() {
+        #[cfg(feature = "sqlite")]
+        Some(url) if url.starts_with("sqlite:") => sqlite_app(url, events).await,
+        #[cfg(feature = "postgres")]
+        // the orchestrator restarts us, an unreachable database is no reason for a panic
+        Some(url) if url.starts_with("postgres") => match postgres_app(url, events).await {
+            Ok(app) => app,
+            Err(e) => {
+                tracing::error!("{:#}", e);
+                std::process::exit(1);
+            }
+        },
+        Some(url) => panic!("no storage backend for {}, check the enabled features", url),
+        #[cfg(feature = "in-memory")]
+        None => in_memory_app(events).await,
+        #[cfg(not(feature = "in-memory"))]
+        None => panic!("DATABASE_URL must be set"),
+    };
+    // add 404 handler
+    *router.lock().unwrap() = app.fallback(handler_404);
+    tracing::info!("ready");
 
-    if let Err(e) = graceful.await {
+    if let Err(e) = server.await.expect("server task panicked") {
         tracing::error!("server error: {}", e);
     }
 
     let _ = tx.send(());
 }
 
-fn create_app<R: SectionRepository>(repository: R) -> Router {
+#[cfg(feature = "postgres")]
+async fn postgres_app(database_url: &str, events: Events) -> anyhow::Result<Router> {
+    tracing::info!("Starting server at: {}", database_url);
+    let pool_config = PoolConfig::from_env();
+    let pool = pool_config
+        .connect(database_url)
+        .await
+        .with_context(|| format!("Failed to connect to {}", database_url))?;
+    if env::var("RUN_MIGRATIONS").as_deref() == Ok("true") {
+        MIGRATOR
+            .run(&pool)
+            .await
+            .context("Failed to apply migrations")?;
+    }
+    let mut repository = DBSectionRepository::new(pool.clone());
+    // section reads can go to a streaming replica, everything else stays on the primary
+    if let Ok(read_url) = env::var("DATABASE_READ_URL") {
+        let read_pool = pool_config
+            .connect(&read_url)
+            .await
+            .context("Failed to connect to the read replica")?;
+        tracing::info!("section reads go to the read replica");
+        repository = repository.with_read_pool(read_pool);
+    }
+    if let Some(window) = env::var("READ_YOUR_WRITES_MS")
+        .ok()
+        .and_then(|ms| ms.parse::<u64>().ok())
+    {
+        repository = repository.with_read_your_writes(Duration::from_millis(window));
+    }
+    // an older binary must not write to a schema a newer one already changed
+    let schema = repository
+        .schema_version()
+        .await
+        .context("Refusing to start")?
+        .context("Refusing to start, the schema version is unknown")?;
+    tracing::info!("schema version: {:?}", schema.version);
+    if schema.pending > 0 {
+        tracing::warn!(
+            "{} migrations are pending, set RUN_MIGRATIONS=true to apply them",
+            schema.pending
+        );
+    }
+
+    // postgres delivers every change to the subscribers of every api replica
+    let event_bus = env::var("EVENT_BUS").unwrap_or("memory".to_string());
+    tracing::info!("event bus: {}", event_bus);
+    let events: Arc<dyn EventTrait> = match event_bus.as_str() {
+        "postgres" => Arc::new(
+            DBEvents::new(pool.clone(), events)
+                .await
+                .context("Failed to listen for events")?,
+        ),
+        // a single replica, the outbox rows go to its own subscribers
+        _ => Arc::new(events),
+    };
+    // changes are announced from the outbox they are committed with, whatever the bus, so
+    // a crash between the commit and the publish loses nothing
+    let outbox_interval = env::var("OUTBOX_POLL_MS")
+        .ok()
+        .and_then(|ms| ms.parse::<u64>().ok())
+        .unwrap_or(100);
+    let dispatcher = OutboxDispatcher::new(
+        pool.clone(),
+        Arc::clone(&events),
+        Duration::from_millis(outbox_interval),
+    );
+    tokio::spawn(dispatcher.run());
+
+    let webhooks = Arc::new(DBWebhookRepository::new(pool.clone()));
+    spawn_webhook_worker(webhooks.clone(), Arc::clone(&events));
+
+    let alerts = Arc::new(DBAlertRepository::new(pool.clone()));
+    let audit = Arc::new(DBAuditRepository::new(pool.clone()));
+    let api_keys = Arc::new(DBApiKeyRepository::new(pool));
+    Ok(section_app(
+        repository,
+        events,
+        webhooks,
+        alerts,
+        audit,
+        Some(api_keys),
+    ))
+}
+
+// a single process, so events stay in memory and webhooks, alert rules and the audit log
+// are not persisted. the api keys are, `api-shower keys create` works on the same file
+#[cfg(feature = "sqlite")]
+async fn sqlite_app(database_url: &str, events: Events) -> Router {
+    tracing::info!("Starting server at: {}", database_url);
+    let repository = SqliteSectionRepository::connect(database_url)
+        .await
+        .unwrap_or_else(|e| panic!("Failed to open {}: {}", database_url, e));
+    let events: Arc<dyn EventTrait> = Arc::new(events);
+
+    let webhooks = Arc::new(InMemoryWebhookRepository::new());
+    spawn_webhook_worker(webhooks.clone(), Arc::clone(&events));
+
+    let alerts = Arc::new(InMemoryAlertRepository::new());
+    let audit = Arc::new(InMemoryAuditRepository::new());
+    let api_keys = Arc::new(SqliteApiKeyRepository::new(repository.pool().clone()));
+    section_app(repository, events, webhooks, alerts, audit, Some(api_keys))
+}
+
+// nothing is persisted, every restart starts from the seeded sections
+#[cfg(feature = "in-memory")]
+async fn in_memory_app(events: Events) -> Router {
+    tracing::warn!("DATABASE_URL is not set, starting with an in-memory store");
+    let events: Arc<dyn EventTrait> = Arc::new(events);
+
+    let webhooks = Arc::new(InMemoryWebhookRepository::new());
+    spawn_webhook_worker(webhooks.clone(), Arc::clone(&events));
+
+    let alerts = Arc::new(InMemoryAlertRepository::new());
+    let audit = Arc::new(InMemoryAuditRepository::new());
+    section_app(
+        InMemorySectionRepository::seeded(),
+        events,
+        webhooks,
+        alerts,
+        audit,
+        demo_api_keys(env::var("OPEN_WRITES").as_deref() == Ok("true")).await,
+    )
+}
+
+// the demo leaves the writes open only when asked to, otherwise it makes an admin key for
+// the run since there is no database for the admin cli to add one to
+#[cfg(feature = "in-memory")]
+async fn demo_api_keys(open_writes: bool) -> Option<Arc<dyn ApiKeyRepository>> {
+    if open_writes {
+        tracing::warn!("OPEN_WRITES=true, anyone can write");
+        return None;
+    }
+    let keys = InMemoryApiKeyRepository::new();
+    let secret = generate_key();
+    let key = NewApiKey {
+        name: "admin".to_string(),
+        key_hash: hash_key(&secret),
+        site: None,
+        building: None,
+    };
+    keys.create(key)
+        .await
+        .expect("Failed to create the admin key");
+    tracing::warn!("admin api key for this run: {}", secret);
+    Some(Arc::new(keys))
+}
+
+// run the worker on a single replica when several share the postgres event bus
+fn spawn_webhook_worker(webhooks: Arc<dyn WebhookRepository>, events: Arc<dyn EventTrait>) {
+    if env::var("WEBHOOK_WORKER").as_deref() == Ok("false") {
+        return;
+    }
+    let max_attempts = env::var("WEBHOOK_MAX_ATTEMPTS")
+        .ok()
+        .and_then(|attempts| attempts.parse::<i32>().ok())
+        .unwrap_or(5);
+    let worker = WebhookWorker::new(webhooks, max_attempts, Duration::from_secs(1));
+    tokio::spawn(async move {
+        if let Err(e) = worker.run(events).await {
+            tracing::error!("webhook worker stopped: {}", e);
+        }
+    });
+}
+
+// wraps the repository in the decorators enabled by the environment, the cache sits
+// in front so only the calls that reach the backend are measured
+fn section_app<R: SectionRepository>(
+    repository: R,
+    events: Arc<dyn EventTrait>,
+    webhooks: Arc<dyn WebhookRepository>,
+    alerts: Arc<dyn AlertRepository>,
+    audit: Arc<dyn AuditRepository>,
+    api_keys: Option<Arc<dyn ApiKeyRepository>>,
+) -> Router {
+    if env::var("SECTION_INSTRUMENTATION").as_deref() == Ok("true") {
+        let repository = InstrumentedSectionRepository::new(repository);
+        let metrics = Some(repository.metrics());
+        cached_app(
+            repository, events, webhooks, alerts, audit, api_keys, metrics,
+        )
+    } else {
+        cached_app(repository, events, webhooks, alerts, audit, api_keys, None)
+    }
+}
+
+fn cached_app<R: SectionRepository>(
+    repository: R,
+    events: Arc<dyn EventTrait>,
+    webhooks: Arc<dyn WebhookRepository>,
+    alerts: Arc<dyn AlertRepository>,
+    audit: Arc<dyn AuditRepository>,
+    api_keys: Option<Arc<dyn ApiKeyRepository>>,
+    metrics: Option<SectionMetrics>,
+) -> Router {
+    if env::var("SECTION_CACHE").as_deref() == Ok("true") {
+        // the memory bus never hears of the writes of other replicas, the ttl bounds how long
+        // they stay unseen
+        let ttl = env::var("SECTION_CACHE_TTL_MS")
+            .ok()
+            .and_then(|ms| ms.parse::<u64>().ok())
+            .unwrap_or(5000);
+        let repository =
+            CachedSectionRepository::new(repository).with_ttl(Duration::from_millis(ttl));
+        let invalidation = repository.clone().invalidate_on(Arc::clone(&events));
+        tokio::spawn(async move {
+            if let Err(e) = invalidation.await {
+                tracing::error!("section cache invalidation stopped: {}", e);
+            }
+        });
+        let stats = repository.stats();
+        let state = AppState::new(repository, events, webhooks, alerts, audit)
+            .with_cache_stats(Some(stats))
+            .with_metrics(metrics);
+        configured_app(state, api_keys)
+    } else {
+        let state =
+            AppState::new(repository, events, webhooks, alerts, audit).with_metrics(metrics);
+        configured_app(state, api_keys)
+    }
+}
+
+// the settings every backend takes from the environment the same way
+fn configured_app<R: SectionRepository>(
+    state: AppState<R>,
+    api_keys: Option<Arc<dyn ApiKeyRepository>>,
+) -> Router {
+    let state = state
+        .with_default_site(DefaultSite::from_env())
+        .with_facility_types(FacilityTypes::from_env())
+        .with_api_keys(api_keys)
+        .with_jwt_keys(JwtKeys::from_env())
+        .with_trusted_proxies(TrustedProxies::from_env());
+    create_app(state)
+}
+
+// served while the storage backend is connecting
+fn starting_app() -> Router {
     Router::new()
-        .route("/", get(root))
+        .route("/health", get(not_ready))
+        .fallback(not_ready)
+}
+
+// served as they are against the default site, and under /sites/:site for every site.
+// the /showerrooms routes are about showers, /facilities/:facility about any facility type
+fn section_routes<R: SectionRepository>() -> Router<AppState<R>> {
+    Router::new()
+        .route("/facilities/:facility", get(showerrooms_all::<R>))
+        .route(
+            "/facilities/:facility/:gender",
+            get(showerrooms_gender::<R>),
+        )
+        .route(
+            "/facilities/:facility/:gender/:building",
+            get(showerrooms_building::<R>),
+        )
+        .route(
+            "/facilities/:facility/:gender/:building/:floor",
+            get(showerrooms_floor::<R>)
+                .post(create_section::<R>)
+                .patch(update_section::<R>)
+                .delete(delete_section::<R>),
+        )
+        .route(
+            "/facilities/:facility/:gender/:building/:floor/capacity",
+            put(update_capacity::<R>),
+        )
+        .route(
+            "/facilities/:facility/:gender/:building/:floor/transitions",
+            post(batch_transitions::<R>),
+        )
         .route("/showerrooms", get(showerrooms_all::<R>))
         .route("/:gender/showerrooms", get(showerrooms_gender::<R>))
         .route(
             "/:gender/:building/showerrooms",
             get(showerrooms_building::<R>),
         )
-        .route(
-            "/events",
-            get({
-                let events = Arc::clone(&EVENTS);
-                move || server_sents_events(Arc::clone(&events))
-            }),
-        )
         .route(
             "/:gender/:building/:floor/showerrooms",
             get(showerrooms_floor::<R>)
                 .post(create_section::<R>)
-                .patch(update_section::<R>),
+                .patch(update_section::<R>)
+                .delete(delete_section::<R>),
         )
-        .with_state(Arc::new(repository))
+        .route(
+            "/:gender/:building/:floor/showerrooms/capacity",
+            put(update_capacity::<R>),
+        )
+        .route(
+            "/:gender/:building/:floor/showerrooms/transitions",
+            post(batch_transitions::<R>),
+        )
+}
+
+// the event streams of the default site, or of every site on /events, and of one site
+// under /sites/:site. /facilities/:facility narrows them to one facility type
+fn event_routes<R: SectionRepository>() -> Router<AppState<R>> {
+    Router::new()
+        .route("/events", get(events_all))
+        .route("/:gender/:building/events", get(events_building))
+        .route("/facilities/:facility/events", get(events_all))
+        .route(
+            "/facilities/:facility/:gender/:building/events",
+            get(events_building),
+        )
+}
+
+// subscriber urls, delivery logs and dead letters are only shown to an unscoped key, the
+// same as the audit log
+fn webhook_routes<R: SectionRepository>(state: AppState<R>) -> Router<AppState<R>> {
+    Router::new()
+        .route("/webhooks", get(webhooks_all).post(create_webhook))
+        .route("/webhooks/dead-letters", get(dead_letters))
+        .route(
+            "/webhooks/:id",
+            get(find_webhook)
+                .patch(update_webhook)
+                .delete(delete_webhook),
+        )
+        .route("/webhooks/:id/deliveries", get(webhook_deliveries))
+        .route_layer(middleware::from_fn_with_state(state, require_admin_key))
+}
+
+fn create_app<R: SectionRepository>(state: AppState<R>) -> Router {
+    Router::new()
+        .route("/", get(root))
+        .route("/health", get(health::<R>))
+        .merge(section_routes())
+        .merge(event_routes())
+        .nest("/sites/:site", section_routes().merge(event_routes()))
+        .route("/showerrooms/:id/restore", post(restore_section::<R>))
+        .route("/cache/stats", get(cache_stats))
+        .route("/metrics/repository", get(repository_metrics))
+        .route("/schema/version", get(schema_version::<R>))
+        .route("/events/subscribers", get(subscribers))
+        .merge(webhook_routes(state.clone()))
+        .route("/alerts", get(alerts_all).post(create_alert))
+        .route(
+            "/audit",
+            get(audit_log).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                require_admin_key,
+            )),
+        )
+        .route(
+            "/alerts/:id",
+            get(find_alert).patch(update_alert).delete(delete_alert),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_api_key,
+        ))
+        .with_state(state)
+        .layer(middleware::from_fn(client_scope))
         .layer(
             CorsLayer::new()
                 .allow_origin("http://localhost:5173".parse::<HeaderValue>().unwrap())
                 .allow_methods(Any)
-                .allow_headers(vec![header::CONTENT_TYPE, header::ACCEPT]),
+                .allow_headers(vec![
+                    header::CONTENT_TYPE,
+                    header::ACCEPT,
+                    header::AUTHORIZATION,
+                    HeaderName::from_static(CLIENT_HEADER),
+                    HeaderName::from_static(ACTOR_HEADER),
+                    HeaderName::from_static(API_KEY_HEADER),
+                ]),
         )
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "in-memory"))]
 mod unite_tests {
-    use crate::repositories::section::in_memory::InMemorySectionRepository;
-    use crate::repositories::section::models::{CreateSection, Section, SectionInfo};
+    use crate::handlers::auth::{Claims, Role};
+    use crate::repositories::alert::in_memory::InMemoryAlertRepository;
+    use crate::repositories::audit::{in_memory::InMemoryAuditRepository, models::AuditEntry};
+    use crate::repositories::events::models::{EventFilter, SECTION_UPDATED};
+    use crate::repositories::section::models::{
+        CreateSection, Section, SectionInfo, DEFAULT_FACILITY, DEFAULT_SITE,
+    };
+    use crate::repositories::webhook::in_memory::InMemoryWebhookRepository;
 
     use super::*;
     use axum::body::Body;
     use axum::http::Method;
     use axum::http::Request;
     use axum::http::StatusCode;
-    use hyper::header;
+    use chrono::{SecondsFormat, Utc};
+    use hyper::{body::HttpBody, header};
+    use jsonwebtoken::{EncodingKey, Header};
     use tower::ServiceExt;
 
+    fn create_state<R: SectionRepository>(repository: R) -> AppState<R> {
+        AppState::new(
+            repository,
+            Arc::new(Events::new()),
+            Arc::new(InMemoryWebhookRepository::new()),
+            Arc::new(InMemoryAlertRepository::new()),
+            Arc::new(InMemoryAuditRepository::new()),
+        )
+    }
+
+    // sends a JSON request, `headers` carry an api key or a bearer token
+    async fn send_with(
+        app: &Router,
+        method: Method,
+        uri: &str,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> axum::response::Response {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref());
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let request = request.body(Body::from(body.to_string())).unwrap();
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    async fn send(app: &Router, method: Method, uri: &str, body: &str) -> axum::response::Response {
+        send_with(app, method, uri, &[], body).await
+    }
+
+    async fn sections(response: axum::response::Response) -> Vec<Section> {
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    // an unscoped key and one for building A of the default site, in that order
+    async fn create_keys(keys: &dyn ApiKeyRepository) -> (String, String) {
+        let mut secrets = Vec::new();
+        for building in [None, Some("A")] {
+            let secret = generate_key();
+            let key = NewApiKey {
+                name: building.unwrap_or("admin").to_string(),
+                key_hash: hash_key(&secret),
+                site: building.map(|_| DEFAULT_SITE.to_string()),
+                building: building.map(str::to_string),
+            };
+            keys.create(key).await.unwrap();
+            secrets.push(secret);
+        }
+        let building_a = secrets.pop().unwrap();
+        (secrets.pop().unwrap(), building_a)
+    }
+
+    // the section of the next event on an SSE stream
+    async fn next_section(body: &mut axum::body::BoxBody) -> Section {
+        let chunk = body.data().await.unwrap().unwrap();
+        let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+        let data = chunk.lines().find_map(|line| line.strip_prefix("data: "));
+        serde_json::from_str(data.unwrap()).unwrap()
+    }
+
     // utility function to create populated repository
     async fn create_populated_repository() -> InMemorySectionRepository {
-        let repository = InMemorySectionRepository {
-            store: Arc::default(),
-        };
+        let repository = InMemorySectionRepository::new();
         let genders = vec!["male", "female"];
         let buildings = vec!["A", "B", "C"];
         let floors = vec![1, 2, 3, 4];
@@ -118,6 +644,8 @@ mod unite_tests {
             for building in &buildings {
                 for &floor in &floors {
                     let section_info = SectionInfo {
+                        site: DEFAULT_SITE.to_string(),
+                        facility_type: DEFAULT_FACILITY.to_string(),
                         gender: gender.to_string(),
                         building: building.to_string(),
                         floor,
@@ -136,10 +664,8 @@ mod unite_tests {
 
     #[tokio::test]
     async fn test_root() {
-        let repository = InMemorySectionRepository {
-            store: Arc::default(),
-        };
-        let app = create_app(repository);
+        let repository = InMemorySectionRepository::new();
+        let app = create_app(create_state(repository));
         let request = Request::builder()
             .method(Method::GET)
             .uri("/")
@@ -155,10 +681,8 @@ mod unite_tests {
     // post section test case
     #[tokio::test]
     async fn should_return_section_data() {
-        let repository = InMemorySectionRepository {
-            store: Arc::default(),
-        };
-        let app = create_app(repository);
+        let repository = InMemorySectionRepository::new();
+        let app = create_app(create_state(repository));
         let request_body = Body::from(r#"{"total": 10}"#);
         let request = Request::builder()
             .method(Method::POST)
@@ -177,7 +701,7 @@ mod unite_tests {
     #[tokio::test]
     async fn test_showerrooms_gender() {
         let repository = create_populated_repository().await;
-        let app = create_app(repository);
+        let app = create_app(create_state(repository));
         let request = Request::builder()
             .method(Method::GET)
             .uri("/female/showerrooms")
@@ -195,7 +719,7 @@ mod unite_tests {
     #[tokio::test]
     async fn test_showerrooms_building() {
         let repository = create_populated_repository().await;
-        let app = create_app(repository);
+        let app = create_app(create_state(repository));
         let request = Request::builder()
             .method(Method::GET)
             .uri("/female/C/showerrooms")
@@ -213,7 +737,7 @@ mod unite_tests {
     #[tokio::test]
     async fn test_showerrooms_floor() {
         let repository = create_populated_repository().await;
-        let app = create_app(repository);
+        let app = create_app(create_state(repository));
         let request = Request::builder()
             .method(Method::GET)
             .uri("/female/C/1/showerrooms")
@@ -238,7 +762,7 @@ mod unite_tests {
     #[tokio::test]
     async fn test_invalid_gender() {
         let repository = create_populated_repository().await;
-        let app = create_app(repository);
+        let app = create_app(create_state(repository));
         let request = Request::builder()
             .method(Method::GET)
             .uri("/invalidgender/showerrooms")
@@ -251,7 +775,7 @@ mod unite_tests {
     #[tokio::test]
     async fn test_invalid_building() {
         let repository = create_populated_repository().await;
-        let app = create_app(repository);
+        let app = create_app(create_state(repository));
         let request = Request::builder()
             .method(Method::GET)
             .uri("/female/invalidbuilding/showerrooms")
@@ -264,7 +788,7 @@ mod unite_tests {
     #[tokio::test]
     async fn test_invalid_floor() {
         let repository = create_populated_repository().await;
-        let app = create_app(repository);
+        let app = create_app(create_state(repository));
         let request = Request::builder()
             .method(Method::GET)
             .uri("/female/C/5/showerrooms")
@@ -277,12 +801,12 @@ mod unite_tests {
     #[tokio::test]
     async fn test_update_section() {
         let repository = create_populated_repository().await;
-        let app = create_app(repository);
+        let app = create_app(create_state(repository));
         let request_body = Body::from(
             r#"{
                 "current_status": "available",
                 "next_status": "occupied"
-            }"#
+            }"#,
         );
         let request = Request::builder()
             .method(Method::PATCH)
@@ -305,4 +829,1344 @@ mod unite_tests {
         assert_eq!(body.available, 4);
         assert_eq!(body.occupied, 1);
     }
+
+    #[tokio::test]
+    async fn test_update_section_errors() {
+        let repository = create_populated_repository().await;
+        let app = create_app(create_state(repository));
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(Method::DELETE)
+                    .uri("/male/A/2/showerrooms")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        for (uri, current_status, next_status, status) in [
+            // unknown, archived and unknown facility locations
+            (
+                "/male/A/5/showerrooms",
+                "available",
+                "occupied",
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                "/male/A/2/showerrooms",
+                "available",
+                "occupied",
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                "/facilities/laundry/male/A/1",
+                "available",
+                "occupied",
+                StatusCode::NOT_FOUND,
+            ),
+            // no stall is occupied yet
+            (
+                "/male/A/1/showerrooms",
+                "occupied",
+                "available",
+                StatusCode::CONFLICT,
+            ),
+            (
+                "/male/A/1/showerrooms",
+                "available",
+                "available",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                "/male/A/1/showerrooms",
+                "available",
+                "closed",
+                StatusCode::BAD_REQUEST,
+            ),
+        ] {
+            let body = format!(
+                r#"{{"current_status": "{}", "next_status": "{}"}}"#,
+                current_status, next_status
+            );
+            let request = Request::builder()
+                .method(Method::PATCH)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(Body::from(body))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), status, "{} {}", uri, current_status);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_errors() {
+        let repository = create_populated_repository().await;
+        let app = create_app(create_state(repository));
+        let occupy = r#"{"current_status": "available", "next_status": "occupied"}"#;
+        let response = send(&app, Method::PATCH, "/male/A/1/showerrooms", occupy).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        for (method, uri, body, status) in [
+            // the occupied stall can't be removed
+            (
+                Method::PUT,
+                "/male/A/1/showerrooms/capacity",
+                r#"{"total": 0}"#,
+                StatusCode::CONFLICT,
+            ),
+            (
+                Method::POST,
+                "/male/A/2/showerrooms/transitions",
+                r#"[{"current_status": "occupied", "next_status": "available"}]"#,
+                StatusCode::CONFLICT,
+            ),
+            (
+                Method::PUT,
+                "/male/A/5/showerrooms/capacity",
+                r#"{"total": 3}"#,
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                Method::DELETE,
+                "/male/A/5/showerrooms",
+                "",
+                StatusCode::NOT_FOUND,
+            ),
+        ] {
+            let response = send(&app, method, uri, body).await;
+            assert_eq!(response.status(), status, "{uri}");
+        }
+    }
+
+    // the database takes the locations the in-memory store does, and a section its checks
+    // refuse is the client's mistake
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_locations() {
+        let repository = SqliteSectionRepository::connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let app = create_app(create_state(repository));
+
+        for (uri, body, status) in [
+            (
+                "/female/A/5/showerrooms",
+                r#"{"total": 5}"#,
+                StatusCode::CREATED,
+            ),
+            (
+                "/sites/north/male/Tower/12/showerrooms",
+                r#"{"total": 2}"#,
+                StatusCode::CREATED,
+            ),
+            (
+                "/female/D/1/showerrooms",
+                r#"{"total": -1}"#,
+                StatusCode::BAD_REQUEST,
+            ),
+        ] {
+            let response = send(&app, Method::POST, uri, body).await;
+            assert_eq!(response.status(), status, "{uri}");
+        }
+    }
+
+    // a backend that fails is a 500, not a missing section or a bad request
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_backend_errors() {
+        let repository = SqliteSectionRepository::connect("sqlite::memory:")
+            .await
+            .unwrap();
+        repository.pool().close().await;
+        let app = create_app(create_state(repository));
+        let occupy = r#"{"current_status": "available", "next_status": "occupied"}"#;
+
+        for (method, uri, body) in [
+            (Method::GET, "/male/showerrooms", ""),
+            (Method::GET, "/male/A/showerrooms?include_archived=true", ""),
+            (Method::GET, "/male/A/1/showerrooms", ""),
+            (Method::PATCH, "/male/A/1/showerrooms", occupy),
+            (
+                Method::PUT,
+                "/male/A/1/showerrooms/capacity",
+                r#"{"total": 3}"#,
+            ),
+            (Method::DELETE, "/male/A/1/showerrooms", ""),
+        ] {
+            let response = send(&app, method, uri, body).await;
+            assert_eq!(
+                response.status(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "{uri}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_filtered_events() {
+        for uri in [
+            "/events?gender=female&building=C&floor=2",
+            "/female/C/events",
+        ] {
+            let repository = create_populated_repository().await;
+            let app = create_app(create_state(repository));
+            let request = Request::builder()
+                .method(Method::GET)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers().get(header::CONTENT_TYPE).unwrap(),
+                "text/event-stream"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribers() {
+        let repository = create_populated_repository().await;
+        let app = create_app(create_state(repository));
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("/events/subscribers")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert!(body["subscribers"].is_u64());
+    }
+
+    #[tokio::test]
+    async fn test_update_section_notifies() {
+        let repository = create_populated_repository().await;
+        let events = Arc::new(Events::new());
+        let mut subscription = events
+            .subscribe(None, EventFilter::default())
+            .await
+            .unwrap();
+        let app = create_app(AppState::new(
+            repository,
+            events.clone(),
+            Arc::new(InMemoryWebhookRepository::new()),
+            Arc::new(InMemoryAlertRepository::new()),
+            Arc::new(InMemoryAuditRepository::new()),
+        ));
+        let request_body = Body::from(
+            r#"{
+                "current_status": "available",
+                "next_status": "occupied"
+            }"#,
+        );
+        let request = Request::builder()
+            .method(Method::PATCH)
+            .uri("/female/B/3/showerrooms")
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(request_body)
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // the new counters are pushed, so clients don't have to refetch
+        let event = subscription.recv().await.unwrap();
+        assert_eq!((event.id, event.kind()), (1, SECTION_UPDATED));
+        assert_eq!(event.topic, "main/shower/female/B/3");
+        let section: Section = serde_json::from_str(&event.data).unwrap();
+        assert_eq!((section.available, section.occupied), (4, 1));
+        assert!(subscription.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_section_conflict() {
+        let repository = create_populated_repository().await;
+        let existing = repository
+            .find_by_floor(
+                DEFAULT_SITE.to_string(),
+                DEFAULT_FACILITY.to_string(),
+                "female".to_string(),
+                "C".to_string(),
+                1,
+            )
+            .await
+            .unwrap()[0]
+            .clone();
+        let app = create_app(create_state(repository.clone()));
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/female/C/1/showerrooms")
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(r#"{"total": 5}"#))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["id"], existing.id);
+        assert_eq!(repository.find_all().await.unwrap().len(), 24);
+    }
+
+    #[tokio::test]
+    async fn test_site_routes() {
+        let repository = create_populated_repository().await;
+        let app = create_app(create_state(repository.clone()));
+        let create = r#"{"total": 3}"#;
+        let occupy = r#"{"current_status": "available", "next_status": "occupied"}"#;
+
+        let response = send(
+            &app,
+            Method::POST,
+            "/sites/north/male/A/1/showerrooms",
+            create,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = send(
+            &app,
+            Method::POST,
+            "/sites/north/male/A/1/showerrooms",
+            create,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        for uri in [
+            "/sites/north/male/A/1/showerrooms",
+            "/male/A/1/showerrooms?site=north",
+            "/sites/north/male/showerrooms",
+        ] {
+            let response = send(&app, Method::GET, uri, "").await;
+            assert_eq!(response.status(), StatusCode::OK, "{uri}");
+            let found = sections(response).await;
+            assert_eq!(found.len(), 1, "{uri}");
+            assert_eq!((found[0].site.as_str(), found[0].total), ("north", 3));
+        }
+        let response = send(&app, Method::GET, "/male/A/1/showerrooms", "").await;
+        assert_eq!(sections(response).await[0].site, DEFAULT_SITE);
+
+        let response = send(&app, Method::GET, "/showerrooms", "").await;
+        assert_eq!(sections(response).await.len(), 24);
+        let response = send(&app, Method::GET, "/showerrooms?site=north", "").await;
+        assert_eq!(sections(response).await.len(), 1);
+
+        // each site has its own event streams, the unprefixed building one is the default site
+        let mut streams = Vec::new();
+        for uri in [
+            "/sites/north/events",
+            "/sites/north/male/A/events",
+            "/events?site=north",
+            "/male/A/events",
+        ] {
+            let response = send(&app, Method::GET, uri, "").await;
+            assert_eq!(response.status(), StatusCode::OK, "{uri}");
+            streams.push((uri, response.into_body()));
+        }
+
+        let response = send(&app, Method::PATCH, "/male/A/1/showerrooms", occupy).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(
+            &app,
+            Method::PATCH,
+            "/sites/north/male/A/1/showerrooms",
+            occupy,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(&app, Method::GET, "/sites/north/male/A/1/showerrooms", "").await;
+        assert_eq!(sections(response).await[0].occupied, 1);
+        let response = send(&app, Method::GET, "/male/A/1/showerrooms", "").await;
+        assert_eq!(sections(response).await[0].occupied, 1);
+
+        for (uri, mut body) in streams {
+            let section = next_section(&mut body).await;
+            let site = if uri == "/male/A/events" {
+                DEFAULT_SITE
+            } else {
+                "north"
+            };
+            assert_eq!(section.site, site, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_facility_routes() {
+        let repository = create_populated_repository().await;
+        let facilities = FacilityTypes::parse("laundry,toilet:available|occupied");
+        let app = create_app(create_state(repository.clone()).with_facility_types(facilities));
+        let create = r#"{"total": 2}"#;
+        let occupy = r#"{"current_status": "available", "next_status": "occupied"}"#;
+
+        for facility in ["laundry", "toilet"] {
+            let uri = format!("/facilities/{facility}/male/A/1");
+            let response = send(&app, Method::POST, &uri, create).await;
+            assert_eq!(response.status(), StatusCode::CREATED, "{facility}");
+        }
+        let response = send(&app, Method::POST, "/facilities/bike/male/A/1", create).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        for uri in [
+            "/facilities/laundry",
+            "/facilities/laundry/male",
+            "/facilities/laundry/male/A",
+            "/facilities/laundry/male/A/1",
+            "/sites/main/facilities/laundry/male/A/1",
+        ] {
+            let response = send(&app, Method::GET, uri, "").await;
+            assert_eq!(response.status(), StatusCode::OK, "{uri}");
+            let found = sections(response).await;
+            assert_eq!(found.len(), 1, "{uri}");
+            assert_eq!(
+                (found[0].facility_type.as_str(), found[0].total),
+                ("laundry", 2)
+            );
+        }
+        // the /showerrooms routes are the shower facility
+        let response = send(&app, Method::GET, "/showerrooms", "").await;
+        assert_eq!(sections(response).await.len(), 24);
+        let response = send(&app, Method::GET, "/facilities/shower/male/A/1", "").await;
+        let shower = sections(response).await;
+        let response = send(&app, Method::GET, "/male/A/1/showerrooms", "").await;
+        assert_eq!(sections(response).await, shower);
+
+        // each facility type has its own event streams
+        let response = send(&app, Method::GET, "/facilities/bike/events", "").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let mut streams = Vec::new();
+        for uri in [
+            "/facilities/toilet/events",
+            "/facilities/toilet/male/A/events",
+            "/sites/main/facilities/toilet/events",
+            "/events?facility_type=toilet",
+        ] {
+            let response = send(&app, Method::GET, uri, "").await;
+            assert_eq!(response.status(), StatusCode::OK, "{uri}");
+            streams.push((uri, response.into_body()));
+        }
+        let response = send(&app, Method::GET, "/male/A/events", "").await;
+        let mut building = response.into_body();
+
+        // toilets have no disabled stalls
+        let response = send(
+            &app,
+            Method::PATCH,
+            "/facilities/toilet/male/A/1",
+            r#"{"current_status": "available", "next_status": "disabled"}"#,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = send(&app, Method::PATCH, "/male/A/1/showerrooms", occupy).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(&app, Method::PATCH, "/facilities/toilet/male/A/1", occupy).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(
+            &app,
+            Method::POST,
+            "/facilities/toilet/male/A/1/transitions",
+            r#"[{"current_status": "occupied", "next_status": "disabled"}]"#,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = send(&app, Method::GET, "/facilities/toilet/male/A/1", "").await;
+        let toilet = &sections(response).await[0];
+        assert_eq!((toilet.available, toilet.occupied), (1, 1));
+
+        for (uri, mut body) in streams {
+            let section = next_section(&mut body).await;
+            assert_eq!(section.facility_type, "toilet", "{uri}");
+        }
+        // the building stream carries every facility type
+        assert_eq!(next_section(&mut building).await.facility_type, "shower");
+        assert_eq!(next_section(&mut building).await.facility_type, "toilet");
+    }
+
+    #[tokio::test]
+    async fn test_api_keys() {
+        let keys = InMemoryApiKeyRepository::new();
+        let (admin, building_a) = create_keys(&keys).await;
+        let repository = create_populated_repository().await;
+        let state = create_state(repository).with_api_keys(Some(Arc::new(keys.clone())));
+        let app = create_app(state);
+        let occupy = r#"{"current_status": "available", "next_status": "occupied"}"#;
+        let status = |method: Method, uri: &'static str, key: Option<&str>| {
+            let (app, key) = (&app, key.map(str::to_string));
+            async move {
+                let headers: Vec<_> = key
+                    .iter()
+                    .map(|key| (API_KEY_HEADER, key.as_str()))
+                    .collect();
+                send_with(app, method, uri, &headers, occupy).await.status()
+            }
+        };
+
+        for uri in [
+            "/showerrooms",
+            "/male/A/1/showerrooms",
+            "/events?gender=male",
+        ] {
+            assert_eq!(
+                status(Method::GET, uri, None).await,
+                StatusCode::OK,
+                "{uri}"
+            );
+        }
+
+        let uri = "/male/A/1/showerrooms";
+        assert_eq!(
+            status(Method::PATCH, uri, None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(Method::PATCH, uri, Some("shw_unknown")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(Method::PATCH, uri, Some(&building_a)).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(Method::PATCH, uri, Some(&admin)).await,
+            StatusCode::OK
+        );
+
+        // a scoped key writes to its own building only
+        for uri in [
+            "/male/B/1/showerrooms",
+            "/sites/north/male/A/1/showerrooms",
+            "/webhooks",
+        ] {
+            let code = status(Method::POST, uri, Some(&building_a)).await;
+            assert_eq!(code, StatusCode::FORBIDDEN, "{uri}");
+        }
+        let code = status(Method::DELETE, "/male/B/1/showerrooms", Some(&admin)).await;
+        assert_eq!(code, StatusCode::NO_CONTENT);
+
+        // who is subscribed, and what was delivered to them, is for unscoped keys only
+        for uri in ["/webhooks", "/webhooks/dead-letters"] {
+            assert_eq!(
+                status(Method::GET, uri, None).await,
+                StatusCode::UNAUTHORIZED
+            );
+            let code = status(Method::GET, uri, Some(&building_a)).await;
+            assert_eq!(code, StatusCode::FORBIDDEN, "{uri}");
+            let code = status(Method::GET, uri, Some(&admin)).await;
+            assert_eq!(code, StatusCode::OK, "{uri}");
+        }
+
+        keys.revoke(2).await.unwrap();
+        assert_eq!(
+            status(Method::PATCH, uri, Some(&building_a)).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_demo_api_keys() {
+        // the in-memory demo is only open when asked to
+        let keys = demo_api_keys(false).await.unwrap();
+        let found = keys.find_all().await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(found[0].is_admin());
+        assert!(demo_api_keys(true).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_jwt_roles() {
+        let repository = create_populated_repository().await;
+        let state = create_state(repository).with_jwt_keys(JwtKeys::hs256(&["secret"]));
+        let app = create_app(state);
+        let valid = Utc::now().timestamp() + 3600;
+        let token = |role: Role, exp: i64, secret: &[u8]| {
+            let claims = Claims {
+                sub: format!("{:?}", role).to_lowercase(),
+                role,
+                exp: exp as u64,
+            };
+            let key = EncodingKey::from_secret(secret);
+            Some(jsonwebtoken::encode(&Header::default(), &claims, &key).unwrap())
+        };
+        let status =
+            |method: Method, uri: &'static str, token: Option<String>, body: &'static str| {
+                let app = &app;
+                async move {
+                    let bearer = token.map(|token| format!("Bearer {}", token));
+                    let headers: Vec<_> = bearer
+                        .iter()
+                        .map(|bearer| (header::AUTHORIZATION.as_str(), bearer.as_str()))
+                        .collect();
+                    send_with(app, method, uri, &headers, body).await.status()
+                }
+            };
+        let occupy = r#"{"current_status": "available", "next_status": "occupied"}"#;
+        let disable = r#"{"current_status": "available", "next_status": "disabled"}"#;
+        let uri = "/male/A/1/showerrooms";
+
+        // reads stay public, writes need a token that verifies
+        assert_eq!(status(Method::GET, uri, None, "").await, StatusCode::OK);
+        for token in [
+            None,
+            token(Role::Admin, 1, b"secret"),
+            token(Role::Admin, valid, b"guess"),
+        ] {
+            let code = status(Method::PATCH, uri, token, occupy).await;
+            assert_eq!(code, StatusCode::UNAUTHORIZED);
+        }
+
+        let student = || token(Role::Student, valid, b"secret");
+        let staff = || token(Role::Staff, valid, b"secret");
+        let admin = || token(Role::Admin, valid, b"secret");
+        assert_eq!(
+            status(Method::PATCH, uri, student(), occupy).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(Method::PATCH, uri, student(), disable).await,
+            StatusCode::FORBIDDEN
+        );
+        let batch = "/male/A/1/showerrooms/transitions";
+        let steps = r#"[{"current_status": "available", "next_status": "occupied"},
+            {"current_status": "available", "next_status": "disabled"}]"#;
+        assert_eq!(
+            status(Method::POST, batch, student(), steps).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(Method::PATCH, uri, staff(), disable).await,
+            StatusCode::OK
+        );
+
+        // only admins change which sections exist, the seeded floor is archived and recreated
+        let floor = "/male/A/2/showerrooms";
+        let create = r#"{"total": 2}"#;
+        for token in [student(), staff()] {
+            let code = status(Method::DELETE, floor, token.clone(), "").await;
+            assert_eq!(code, StatusCode::FORBIDDEN);
+            let code = status(Method::POST, floor, token, create).await;
+            assert_eq!(code, StatusCode::FORBIDDEN);
+        }
+        assert_eq!(
+            status(Method::DELETE, floor, admin(), "").await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            status(Method::POST, floor, admin(), create).await,
+            StatusCode::CREATED
+        );
+
+        // and which webhooks and alert rules there are
+        let webhook = r#"{"url": "http://localhost/hook", "secret": "0123456789abcdef"}"#;
+        let rule = r#"{"kind": "full"}"#;
+        for (uri, one, body) in [
+            ("/webhooks", "/webhooks/1", webhook),
+            ("/alerts", "/alerts/1", rule),
+        ] {
+            for token in [student(), staff()] {
+                let code = status(Method::POST, uri, token, body).await;
+                assert_eq!(code, StatusCode::FORBIDDEN, "{uri}");
+            }
+            assert_eq!(
+                status(Method::POST, uri, admin(), body).await,
+                StatusCode::CREATED
+            );
+            let update = r#"{"active": false}"#;
+            assert_eq!(
+                status(Method::PATCH, one, staff(), update).await,
+                StatusCode::FORBIDDEN
+            );
+            assert_eq!(
+                status(Method::DELETE, one, staff(), "").await,
+                StatusCode::FORBIDDEN
+            );
+            assert_eq!(
+                status(Method::PATCH, one, admin(), update).await,
+                StatusCode::OK
+            );
+            assert_eq!(
+                status(Method::DELETE, one, admin(), "").await,
+                StatusCode::NO_CONTENT
+            );
+        }
+        // and who the webhooks deliver to
+        assert_eq!(
+            status(Method::GET, "/webhooks", staff(), "").await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(Method::GET, "/webhooks", admin(), "").await,
+            StatusCode::OK
+        );
+
+        // only admins read the audit log, which names the token's subject, not the header
+        let audit = "/audit?actor=student";
+        assert_eq!(
+            status(Method::GET, audit, None, "").await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(Method::GET, audit, staff(), "").await,
+            StatusCode::FORBIDDEN
+        );
+        let bearer = format!("Bearer {}", admin().unwrap());
+        let headers = [(header::AUTHORIZATION.as_str(), bearer.as_str())];
+        let response = send_with(&app, Method::GET, audit, &headers, "").await;
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let entries = serde_json::from_slice::<Vec<AuditEntry>>(&bytes).unwrap();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_archive_and_restore() {
+        let repository = create_populated_repository().await;
+        let app = create_app(create_state(repository.clone()));
+        let section = repository
+            .find_by_floor(
+                DEFAULT_SITE.to_string(),
+                DEFAULT_FACILITY.to_string(),
+                "male".to_string(),
+                "A".to_string(),
+                2,
+            )
+            .await
+            .unwrap()[0]
+            .clone();
+        let uri = "/male/A/2/showerrooms";
+
+        let response = send(&app, Method::DELETE, uri, "").await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = send(&app, Method::GET, uri, "").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = send(
+            &app,
+            Method::GET,
+            &format!("{uri}?include_archived=true"),
+            "",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let archived = sections(response).await;
+        assert_eq!(archived.len(), 1);
+        assert!(archived[0].deleted_at.is_some());
+        let response = send(&app, Method::GET, "/male/showerrooms", "").await;
+        assert_eq!(sections(response).await.len(), 11);
+        let response = send(&app, Method::GET, "/showerrooms?include_archived=true", "").await;
+        let all = sections(response).await;
+        assert_eq!(all.len(), 24);
+        assert!(all.windows(2).all(|pair| pair[0].id < pair[1].id));
+
+        // a new section took the location, so the archived one can't come back yet
+        let response = send(&app, Method::POST, uri, r#"{"total": 3}"#).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let restore = format!("/showerrooms/{}/restore", section.id);
+        let response = send(&app, Method::POST, &restore, "").await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = send(&app, Method::DELETE, uri, "").await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = send(&app, Method::POST, &restore, "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(repository.find_by_id(section.id).await.unwrap(), section);
+        let response = send(&app, Method::POST, &restore, "").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_archived_for_admins() {
+        let repository = create_populated_repository().await;
+        let section = repository.find_by_id(1).await.unwrap();
+        repository.delete(section.id).await.unwrap();
+        let uri = "/showerrooms?include_archived=true";
+
+        let keys = InMemoryApiKeyRepository::new();
+        let (admin, building_a) = create_keys(&keys).await;
+        let state = create_state(repository.clone()).with_api_keys(Some(Arc::new(keys)));
+        let app = create_app(state);
+        // the active sections stay public, the archived ones take an unscoped key
+        let response = send(&app, Method::GET, "/showerrooms", "").await;
+        assert_eq!(sections(response).await.len(), 23);
+        let response = send(&app, Method::GET, uri, "").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let headers = [(API_KEY_HEADER, building_a.as_str())];
+        let response = send_with(&app, Method::GET, uri, &headers, "").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let headers = [(API_KEY_HEADER, admin.as_str())];
+        let response = send_with(&app, Method::GET, uri, &headers, "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(sections(response).await.len(), 24);
+
+        // or an admin token
+        let state = create_state(repository).with_jwt_keys(JwtKeys::hs256(&["secret"]));
+        let app = create_app(state);
+        let bearer = |role: Role| {
+            let claims = Claims {
+                sub: format!("{:?}", role).to_lowercase(),
+                role,
+                exp: (Utc::now().timestamp() + 3600) as u64,
+            };
+            let key = EncodingKey::from_secret(b"secret");
+            let token = jsonwebtoken::encode(&Header::default(), &claims, &key).unwrap();
+            format!("Bearer {}", token)
+        };
+        let response = send(
+            &app,
+            Method::GET,
+            "/male/A/1/showerrooms?include_archived=true",
+            "",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let student = bearer(Role::Student);
+        let headers = [(header::AUTHORIZATION.as_str(), student.as_str())];
+        let response = send_with(&app, Method::GET, uri, &headers, "").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let admin = bearer(Role::Admin);
+        let headers = [(header::AUTHORIZATION.as_str(), admin.as_str())];
+        let response = send_with(
+            &app,
+            Method::GET,
+            "/male/A/1/showerrooms?include_archived=true",
+            &headers,
+            "",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let archived = sections(response).await;
+        assert_eq!(archived.len(), 1);
+        assert!(archived[0].deleted_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_audit_log() {
+        let keys = InMemoryApiKeyRepository::new();
+        let (admin, building_a) = create_keys(&keys).await;
+        let (admin, building_a) = (admin.as_str(), building_a.as_str());
+        let repository = create_populated_repository().await;
+        let state = create_state(repository.clone())
+            .with_api_keys(Some(Arc::new(keys)))
+            .with_trusted_proxies(TrustedProxies::parse("10.0.0.0/8"));
+        let app = create_app(state);
+        let section = repository
+            .find_by_floor(
+                DEFAULT_SITE.to_string(),
+                DEFAULT_FACILITY.to_string(),
+                "male".to_string(),
+                "A".to_string(),
+                1,
+            )
+            .await
+            .unwrap()[0]
+            .clone();
+        let headers = [(API_KEY_HEADER, admin)];
+        let app = &app;
+        let get =
+            |uri: String| async move { send_with(app, Method::GET, &uri, &headers, "").await };
+        let entries = |response: axum::response::Response| async {
+            let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            serde_json::from_slice::<Vec<AuditEntry>>(&bytes).unwrap()
+        };
+        // the connection the request came in on, set by the server outside of tests
+        let from = |peer: &str, method: Method, headers: &[(&str, &str)], body: &'static str| {
+            let peer: SocketAddr = peer.parse().unwrap();
+            let mut request = Request::builder()
+                .method(method)
+                .uri("/male/A/1/showerrooms")
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .header(API_KEY_HEADER, building_a)
+                .extension(ConnectInfo(peer));
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            request.body(Body::from(body)).unwrap()
+        };
+
+        // through the proxy, whatever the client put in front of the chain is ignored, and
+        // so is the actor it claims to be, the key names it
+        let occupy = r#"{"current_status": "available", "next_status": "occupied"}"#;
+        let headers = [
+            (ACTOR_HEADER, "alice"),
+            ("x-forwarded-for", "198.51.100.9, 203.0.113.7"),
+        ];
+        let request = from("10.0.0.1:41000", Method::PATCH, &headers, occupy);
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        // straight to the api, the forwarded header is the client's word only
+        let headers = [("x-forwarded-for", "203.0.113.7")];
+        let request = from("198.51.100.20:52000", Method::DELETE, &headers, "");
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        // who changed what from where is for admins only
+        let response = send(app, Method::GET, "/audit", "").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let headers = [(API_KEY_HEADER, building_a)];
+        let response = send_with(app, Method::GET, "/audit", &headers, "").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = get("/audit?actor=alice".to_string()).await;
+        assert!(entries(response).await.is_empty());
+        let response = get("/audit?actor=A".to_string()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let changes = entries(response).await;
+        // newest first, the key also made the delete
+        assert_eq!(changes.len(), 2);
+        let update = &changes[1];
+        assert_eq!(update.section_id, section.id);
+        assert_eq!(update.action, "updated");
+        assert_eq!(update.transition.as_deref(), Some("available->occupied"));
+        assert_eq!(update.source_ip.as_deref(), Some("203.0.113.7"));
+        let (old, new) = (update.old_counters.unwrap(), update.new_counters.unwrap());
+        assert_eq!((old.available, old.occupied), (5, 0));
+        assert_eq!((new.available, new.occupied), (4, 1));
+
+        let response = get(format!("/audit?section={}", section.id)).await;
+        let history = entries(response).await;
+        let actions = history.iter().map(|entry| entry.action.as_str());
+        assert_eq!(actions.collect::<Vec<_>>(), ["deleted", "updated"]);
+        assert_eq!(history[0].actor.as_deref(), Some("A"));
+        assert_eq!(history[0].source_ip.as_deref(), Some("198.51.100.20"));
+        assert_eq!(history[0].new_counters, None);
+        // a '+' offset would be decoded as a space, so the time goes in UTC with a 'Z'
+        let to = update
+            .created_at
+            .to_rfc3339_opts(SecondsFormat::Nanos, true);
+        let response = get(format!("/audit?to={}", to)).await;
+        assert!(entries(response).await.is_empty());
+        let response = get("/audit?from=yesterday".to_string()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_batch_transitions() {
+        let repository = create_populated_repository().await;
+        let events = Arc::new(Events::new());
+        let mut subscription = events
+            .subscribe(None, EventFilter::default())
+            .await
+            .unwrap();
+        let app = create_app(AppState::new(
+            repository.clone(),
+            events.clone(),
+            Arc::new(InMemoryWebhookRepository::new()),
+            Arc::new(InMemoryAlertRepository::new()),
+            Arc::new(InMemoryAuditRepository::new()),
+        ));
+        let transitions = |body: &'static str| {
+            Request::builder()
+                .method(Method::POST)
+                .uri("/male/B/1/showerrooms/transitions")
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(transitions(
+                r#"[
+                    {"current_status": "available", "next_status": "occupied"},
+                    {"current_status": "available", "next_status": "disabled"}
+                ]"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let section: Section = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            (section.available, section.occupied, section.disabled_rooms),
+            (3, 1, 1)
+        );
+        // one event per step, each with the counters after it
+        for occupied in [1, 1] {
+            let event = subscription.recv().await.unwrap();
+            assert_eq!(event.topic, "main/shower/male/B/1");
+            let step: Section = serde_json::from_str(&event.data).unwrap();
+            assert_eq!(step.occupied, occupied);
+        }
+
+        // the second stall was never occupied, so the first transition is undone too
+        let response = app
+            .oneshot(transitions(
+                r#"[
+                    {"current_status": "occupied", "next_status": "available"},
+                    {"current_status": "occupied", "next_status": "available"}
+                ]"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(repository.find_by_id(section.id).await.unwrap(), section);
+        assert!(subscription.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_section_lifecycle_notifies() {
+        let repository = create_populated_repository().await;
+        let events = Arc::new(Events::new());
+        let mut subscription = events
+            .subscribe(
+                None,
+                EventFilter {
+                    floor: Some(5),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let app = create_app(AppState::new(
+            repository,
+            events.clone(),
+            Arc::new(InMemoryWebhookRepository::new()),
+            Arc::new(InMemoryAlertRepository::new()),
+            Arc::new(InMemoryAuditRepository::new()),
+        ));
+
+        for (method, uri, body, status) in [
+            (
+                Method::POST,
+                "/female/A/5/showerrooms",
+                r#"{"total": 5}"#,
+                StatusCode::CREATED,
+            ),
+            (
+                Method::PUT,
+                "/female/A/5/showerrooms/capacity",
+                r#"{"total": 8}"#,
+                StatusCode::OK,
+            ),
+            (
+                Method::DELETE,
+                "/female/A/5/showerrooms",
+                "",
+                StatusCode::NO_CONTENT,
+            ),
+        ] {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(Body::from(body))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), status);
+        }
+
+        for (name, total) in [
+            ("section.created", 5),
+            ("section.capacity_changed", 8),
+            ("section.deleted", 8),
+        ] {
+            let event = subscription.recv().await.unwrap();
+            assert_eq!(event.name.as_deref(), Some(name));
+            assert_eq!(event.topic, "main/shower/female/A/5");
+            let section: serde_json::Value = serde_json::from_str(&event.data).unwrap();
+            assert_eq!(section["total"], total);
+        }
+        assert!(subscription.try_recv().is_err());
+
+        let request = Request::builder()
+            .method(Method::DELETE)
+            .uri("/female/A/5/showerrooms")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_alerts() {
+        let repository = create_populated_repository().await;
+        let events = Arc::new(Events::new());
+        let mut subscription = events
+            .subscribe(None, EventFilter::default())
+            .await
+            .unwrap();
+        let app = create_app(AppState::new(
+            repository,
+            events.clone(),
+            Arc::new(InMemoryWebhookRepository::new()),
+            Arc::new(InMemoryAlertRepository::new()),
+            Arc::new(InMemoryAuditRepository::new()),
+        ));
+
+        for (body, status) in [
+            (r#"{"kind": "empty"}"#, StatusCode::UNPROCESSABLE_ENTITY),
+            (
+                r#"{"kind": "full", "gender": "female", "building": "B", "floor": 3}"#,
+                StatusCode::CREATED,
+            ),
+            (
+                r#"{"kind": "freed", "gender": "female", "building": "B"}"#,
+                StatusCode::CREATED,
+            ),
+            (r#"{"kind": "full", "building": "A"}"#, StatusCode::CREATED),
+        ] {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri("/alerts")
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(Body::from(body))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), status);
+        }
+
+        // fill every stall, then free one
+        let transitions = [("available", "occupied"); 5]
+            .into_iter()
+            .chain([("occupied", "available")]);
+        for (current_status, next_status) in transitions {
+            let request = Request::builder()
+                .method(Method::PATCH)
+                .uri("/female/B/3/showerrooms")
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(Body::from(format!(
+                    r#"{{"current_status": "{}", "next_status": "{}"}}"#,
+                    current_status, next_status
+                )))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let mut alerts = vec![];
+        while let Ok(event) = subscription.try_recv() {
+            if event.kind().starts_with("alert.") {
+                let alert: serde_json::Value = serde_json::from_str(&event.data).unwrap();
+                alerts.push((event.kind().to_string(), alert["rule_id"].clone()));
+            }
+        }
+        assert_eq!(
+            alerts,
+            vec![
+                ("alert.full".to_string(), serde_json::json!(1)),
+                ("alert.freed".to_string(), serde_json::json!(2)),
+            ]
+        );
+
+        let request = Request::builder()
+            .method(Method::PATCH)
+            .uri("/alerts/2")
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(r#"{"active": false}"#))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("/alerts")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 3);
+        assert_eq!(body[1]["active"], false);
+
+        let request = Request::builder()
+            .method(Method::DELETE)
+            .uri("/alerts/4")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_cache_stats() {
+        let repository = create_populated_repository().await;
+        let app = create_app(create_state(repository.clone()));
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("/cache/stats")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let repository = CachedSectionRepository::new(repository);
+        let stats = repository.stats();
+        let app = create_app(create_state(repository).with_cache_stats(Some(stats)));
+        for uri in ["/female/showerrooms", "/female/showerrooms", "/cache/stats"] {
+            let request = Request::builder()
+                .method(Method::GET)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            if uri == "/cache/stats" {
+                let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+                assert_eq!(
+                    body,
+                    serde_json::json!({"hits": 1, "misses": 1, "entries": 1})
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_health() {
+        let request = |uri: &str| {
+            Request::builder()
+                .method(Method::GET)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap()
+        };
+        for uri in ["/health", "/showerrooms"] {
+            let response = starting_app().oneshot(request(uri)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        }
+
+        let app = create_app(create_state(create_populated_repository().await));
+        let response = app.oneshot(request("/health")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["status"], "ready");
+    }
+
+    #[tokio::test]
+    async fn test_schema_version() {
+        let request = || {
+            Request::builder()
+                .method(Method::GET)
+                .uri("/schema/version")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let app = create_app(create_state(create_populated_repository().await));
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        #[cfg(feature = "sqlite")]
+        {
+            let repository = SqliteSectionRepository::connect("sqlite::memory:")
+                .await
+                .unwrap();
+            let app = create_app(create_state(repository));
+            let response = app.oneshot(request()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(body["status"], "current");
+            assert_eq!(body["pending"], 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repository_metrics() {
+        let repository = create_populated_repository().await;
+        let app = create_app(create_state(repository.clone()));
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("/metrics/repository")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // the cache answers the second read, only the first one reaches the backend
+        let repository = InstrumentedSectionRepository::new(repository);
+        let metrics = repository.metrics();
+        let repository = CachedSectionRepository::new(repository);
+        let app = create_app(create_state(repository).with_metrics(Some(metrics)));
+        for uri in ["/female/C/showerrooms", "/female/C/showerrooms"] {
+            let request = Request::builder()
+                .method(Method::GET)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("/metrics/repository")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        let find_by_building = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|metrics| metrics["method"] == "find_by_building")
+            .unwrap();
+        assert_eq!(find_by_building["calls"], 1);
+        assert_eq!(find_by_building["errors"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_webhooks() {
+        let repository = create_populated_repository().await;
+        let app = create_app(create_state(repository));
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/webhooks")
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(
+                r#"{"url": "not a url", "secret": "0123456789abcdef"}"#,
+            ))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/webhooks")
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(
+                r#"{"url": "https://office.example/hook", "secret": "0123456789abcdef", "events": ["section.updated"], "building": "C"}"#,
+            ))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["id"], 1);
+        assert!(body.get("secret").is_none());
+
+        let request = Request::builder()
+            .method(Method::PATCH)
+            .uri("/webhooks/1")
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(r#"{"active": false}"#))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        for (uri, status) in [
+            ("/webhooks/1/deliveries", StatusCode::OK),
+            ("/webhooks/2/deliveries", StatusCode::NOT_FOUND),
+            ("/webhooks/dead-letters", StatusCode::OK),
+        ] {
+            let request = Request::builder()
+                .method(Method::GET)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), status);
+        }
+
+        let request = Request::builder()
+            .method(Method::DELETE)
+            .uri("/webhooks/1")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
 }
@@ -1,2 +1,11 @@
+pub mod alert;
+pub mod api_key;
+pub mod audit;
+pub mod client;
 pub mod events;
+#[cfg(feature = "postgres")]
+pub mod pool;
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+pub mod schema;
 pub mod section;
+pub mod webhook;
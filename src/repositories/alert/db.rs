@@ -0,0 +1,135 @@
+use crate::repositories::alert::errors::AlertError;
+use crate::repositories::alert::models::{AlertRule, CreateAlertRule, UpdateAlertRule};
+use crate::repositories::alert::traits::AlertRepository;
+use axum::async_trait;
+use sqlx::PgPool;
+
+#[derive(Clone, Debug)]
+pub struct DBAlertRepository {
+    pool: PgPool,
+}
+
+impl DBAlertRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AlertRepository for DBAlertRepository {
+    async fn find_by_id(&self, id: i32) -> anyhow::Result<AlertRule> {
+        let rule = sqlx::query_as::<_, AlertRule>("select * from alert_rules where id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(AlertError::NotFound(id))?;
+        Ok(rule)
+    }
+
+    async fn find_all(&self) -> anyhow::Result<Vec<AlertRule>> {
+        let rules = sqlx::query_as::<_, AlertRule>("select * from alert_rules order by id asc")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rules)
+    }
+
+    async fn create(&self, payload: CreateAlertRule) -> anyhow::Result<AlertRule> {
+        let rule = sqlx::query_as::<_, AlertRule>(
+            "insert into alert_rules (kind, threshold, site, facility_type, gender, building, floor) values ($1, $2, $3, $4, $5, $6, $7) returning *",
+        )
+        .bind(payload.kind)
+        .bind(payload.threshold)
+        .bind(payload.site)
+        .bind(payload.facility_type)
+        .bind(payload.gender)
+        .bind(payload.building)
+        .bind(payload.floor)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(rule)
+    }
+
+    async fn update(&self, id: i32, payload: UpdateAlertRule) -> anyhow::Result<AlertRule> {
+        let rule = self.find_by_id(id).await?.apply(payload);
+        let rule = sqlx::query_as::<_, AlertRule>(
+            "update alert_rules set kind = $2, threshold = $3, site = $4, facility_type = $5, gender = $6, building = $7, floor = $8, active = $9 where id = $1 returning *",
+        )
+        .bind(id)
+        .bind(rule.kind)
+        .bind(rule.threshold)
+        .bind(rule.site)
+        .bind(rule.facility_type)
+        .bind(rule.gender)
+        .bind(rule.building)
+        .bind(rule.floor)
+        .bind(rule.active)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(AlertError::NotFound(id))?;
+        Ok(rule)
+    }
+
+    async fn delete(&self, id: i32) -> anyhow::Result<()> {
+        let result = sqlx::query("delete from alert_rules where id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(AlertError::NotFound(id).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::alert::models::{DISABLED_RATIO, FULL};
+    use anyhow::Result;
+    use dotenv::dotenv;
+    use std::env;
+
+    async fn setup() -> Result<DBAlertRepository> {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL")?;
+        let pool = PgPool::connect(&database_url).await?;
+        Ok(DBAlertRepository::new(pool))
+    }
+
+    #[tokio::test]
+    async fn test_alert_rule_lifecycle() -> Result<()> {
+        let repository = setup().await?;
+
+        let rule = repository
+            .create(CreateAlertRule {
+                kind: FULL.to_string(),
+                threshold: None,
+                site: None,
+                facility_type: None,
+                gender: Some("female".to_string()),
+                building: Some("C".to_string()),
+                floor: None,
+            })
+            .await?;
+        assert!(rule.active);
+
+        let updated = repository
+            .update(
+                rule.id,
+                UpdateAlertRule {
+                    kind: Some(DISABLED_RATIO.to_string()),
+                    threshold: Some(30),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        assert_eq!(updated.kind, DISABLED_RATIO);
+        assert_eq!(updated.threshold, Some(30));
+        assert_eq!(updated.building, Some("C".to_string()));
+
+        repository.delete(rule.id).await?;
+        assert!(repository.find_by_id(rule.id).await.is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,7 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum AlertError {
+    #[error("not found id is: {0}")]
+    NotFound(i32),
+}
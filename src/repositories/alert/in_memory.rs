@@ -0,0 +1,122 @@
+use crate::repositories::alert::errors::AlertError;
+use crate::repositories::alert::models::{AlertRule, CreateAlertRule, UpdateAlertRule};
+use crate::repositories::alert::traits::AlertRepository;
+use axum::async_trait;
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Default)]
+struct AlertDatas {
+    rules: BTreeMap<i32, AlertRule>,
+    last_id: i32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryAlertRepository {
+    store: Arc<RwLock<AlertDatas>>,
+}
+
+impl InMemoryAlertRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl AlertRepository for InMemoryAlertRepository {
+    async fn find_by_id(&self, id: i32) -> anyhow::Result<AlertRule> {
+        let store = self.store.read().unwrap();
+        let rule = store
+            .rules
+            .get(&id)
+            .cloned()
+            .ok_or(AlertError::NotFound(id))?;
+        Ok(rule)
+    }
+
+    async fn find_all(&self) -> anyhow::Result<Vec<AlertRule>> {
+        let store = self.store.read().unwrap();
+        Ok(store.rules.values().cloned().collect())
+    }
+
+    async fn create(&self, payload: CreateAlertRule) -> anyhow::Result<AlertRule> {
+        let mut store = self.store.write().unwrap();
+        store.last_id += 1;
+        let rule = AlertRule {
+            id: store.last_id,
+            kind: payload.kind,
+            threshold: payload.threshold,
+            site: payload.site,
+            facility_type: payload.facility_type,
+            gender: payload.gender,
+            building: payload.building,
+            floor: payload.floor,
+            active: true,
+            created_at: Utc::now(),
+        };
+        store.rules.insert(rule.id, rule.clone());
+        Ok(rule)
+    }
+
+    async fn update(&self, id: i32, payload: UpdateAlertRule) -> anyhow::Result<AlertRule> {
+        let mut store = self.store.write().unwrap();
+        let rule = store
+            .rules
+            .remove(&id)
+            .ok_or(AlertError::NotFound(id))?
+            .apply(payload);
+        store.rules.insert(id, rule.clone());
+        Ok(rule)
+    }
+
+    async fn delete(&self, id: i32) -> anyhow::Result<()> {
+        let mut store = self.store.write().unwrap();
+        store.rules.remove(&id).ok_or(AlertError::NotFound(id))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod in_memory_tests {
+    use super::*;
+    use crate::repositories::alert::models::{FREED, FULL};
+
+    #[tokio::test]
+    async fn test_alert_repository() {
+        let repo = InMemoryAlertRepository::new();
+
+        let rule = repo
+            .create(CreateAlertRule {
+                kind: FULL.to_string(),
+                threshold: None,
+                site: None,
+                facility_type: None,
+                gender: Some("male".to_string()),
+                building: None,
+                floor: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(rule.id, 1);
+        assert!(rule.active);
+
+        let rule = repo
+            .update(
+                1,
+                UpdateAlertRule {
+                    kind: Some(FREED.to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(rule.kind, FREED);
+        assert_eq!(rule.gender, Some("male".to_string()));
+        assert_eq!(repo.find_all().await.unwrap(), vec![rule]);
+
+        repo.delete(1).await.unwrap();
+        assert!(repo.find_by_id(1).await.is_err());
+        assert!(repo.delete(1).await.is_err());
+    }
+}
@@ -0,0 +1,7 @@
+#[cfg(feature = "postgres")]
+pub mod db;
+pub mod errors;
+#[cfg(feature = "in-memory")]
+pub mod in_memory;
+pub mod models;
+pub mod traits;
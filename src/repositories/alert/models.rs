@@ -0,0 +1,212 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+use crate::repositories::events::models::{topic, EventFilter, EventMessage};
+use crate::repositories::section::models::Section;
+
+// no stall left
+pub const FULL: &str = "full";
+// available went from 0 back to at least one stall
+pub const FREED: &str = "freed";
+// more than `threshold` percent of the stalls are disabled
+pub const DISABLED_RATIO: &str = "disabled_ratio";
+pub const KINDS: [&str; 3] = [FULL, FREED, DISABLED_RATIO];
+const DEFAULT_DISABLED_RATIO: i32 = 50;
+
+fn validate_kind(kind: &str) -> Result<(), ValidationError> {
+    if KINDS.contains(&kind) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("unknown alert kind"))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct AlertRule {
+    pub id: i32,
+    pub kind: String,
+    // percentage, only used by disabled_ratio
+    pub threshold: Option<i32>,
+    pub site: Option<String>,
+    pub facility_type: Option<String>,
+    pub gender: Option<String>,
+    pub building: Option<String>,
+    pub floor: Option<i32>,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct CreateAlertRule {
+    #[validate(custom = "validate_kind")]
+    pub kind: String,
+    #[validate(range(min = 0, max = 100))]
+    pub threshold: Option<i32>,
+    pub site: Option<String>,
+    pub facility_type: Option<String>,
+    pub gender: Option<String>,
+    pub building: Option<String>,
+    pub floor: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, Validate)]
+pub struct UpdateAlertRule {
+    #[validate(custom = "validate_kind")]
+    pub kind: Option<String>,
+    #[validate(range(min = 0, max = 100))]
+    pub threshold: Option<i32>,
+    pub site: Option<String>,
+    pub facility_type: Option<String>,
+    pub gender: Option<String>,
+    pub building: Option<String>,
+    pub floor: Option<i32>,
+    pub active: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Alert {
+    pub rule_id: i32,
+    pub section: Section,
+}
+
+impl AlertRule {
+    pub fn event_name(&self) -> String {
+        format!("alert.{}", self.kind)
+    }
+
+    fn holds(&self, section: &Section) -> bool {
+        match self.kind.as_str() {
+            FULL => section.available == 0,
+            FREED => section.available > 0,
+            DISABLED_RATIO => {
+                let threshold = self.threshold.unwrap_or(DEFAULT_DISABLED_RATIO);
+                section.disabled_rooms * 100 > threshold * section.total
+            }
+            _ => false,
+        }
+    }
+
+    // only the transition into the condition fires, staying full does not repeat the alert
+    pub fn fires(&self, before: &Section, after: &Section) -> bool {
+        let filter = EventFilter {
+            site: self.site.clone(),
+            facility_type: self.facility_type.clone(),
+            gender: self.gender.clone(),
+            building: self.building.clone(),
+            floor: self.floor,
+        };
+        self.active && filter.matches(&topic(after)) && !self.holds(before) && self.holds(after)
+    }
+
+    pub fn message(&self, section: &Section) -> EventMessage {
+        let alert = Alert {
+            rule_id: self.id,
+            section: section.clone(),
+        };
+        EventMessage {
+            name: Some(self.event_name()),
+            topic: topic(section),
+            data: serde_json::to_string(&alert).unwrap_or_default(),
+        }
+    }
+
+    pub fn apply(self, payload: UpdateAlertRule) -> Self {
+        Self {
+            kind: payload.kind.unwrap_or(self.kind),
+            threshold: payload.threshold.or(self.threshold),
+            site: payload.site.or(self.site),
+            facility_type: payload.facility_type.or(self.facility_type),
+            gender: payload.gender.or(self.gender),
+            building: payload.building.or(self.building),
+            floor: payload.floor.or(self.floor),
+            active: payload.active.unwrap_or(self.active),
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::section::models::{SectionInfo, DEFAULT_FACILITY, DEFAULT_SITE};
+
+    fn rule(kind: &str) -> AlertRule {
+        AlertRule {
+            id: 1,
+            kind: kind.to_string(),
+            threshold: None,
+            site: None,
+            facility_type: None,
+            gender: Some("female".to_string()),
+            building: Some("C".to_string()),
+            floor: None,
+            active: true,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn section(available: i32, disabled_rooms: i32) -> Section {
+        let info = SectionInfo {
+            site: DEFAULT_SITE.to_string(),
+            facility_type: DEFAULT_FACILITY.to_string(),
+            gender: "female".to_string(),
+            building: "C".to_string(),
+            floor: 2,
+        };
+        Section {
+            available,
+            occupied: 4 - available - disabled_rooms,
+            disabled_rooms,
+            ..Section::new(1, info, 4)
+        }
+    }
+
+    #[test]
+    fn test_fires_on_transition() {
+        assert!(rule(FULL).fires(&section(1, 0), &section(0, 0)));
+        assert!(!rule(FULL).fires(&section(0, 1), &section(0, 0)));
+        assert!(rule(FREED).fires(&section(0, 0), &section(1, 0)));
+        assert!(!rule(FREED).fires(&section(1, 0), &section(2, 0)));
+        assert!(rule(DISABLED_RATIO).fires(&section(2, 2), &section(1, 3)));
+        assert!(!rule(DISABLED_RATIO).fires(&section(3, 1), &section(2, 2)));
+
+        let mut other_building = rule(FULL);
+        other_building.building = Some("A".to_string());
+        assert!(!other_building.fires(&section(1, 0), &section(0, 0)));
+        let mut other_site = rule(FULL);
+        other_site.site = Some("north".to_string());
+        assert!(!other_site.fires(&section(1, 0), &section(0, 0)));
+        other_site.site = Some(DEFAULT_SITE.to_string());
+        assert!(other_site.fires(&section(1, 0), &section(0, 0)));
+        assert_eq!(
+            other_site.message(&section(0, 0)).topic,
+            "main/shower/female/C/2"
+        );
+        let mut other_facility = rule(FULL);
+        other_facility.facility_type = Some("laundry".to_string());
+        assert!(!other_facility.fires(&section(1, 0), &section(0, 0)));
+        other_facility.facility_type = Some(DEFAULT_FACILITY.to_string());
+        assert!(other_facility.fires(&section(1, 0), &section(0, 0)));
+    }
+
+    #[test]
+    fn test_validate_kind() {
+        let payload = CreateAlertRule {
+            kind: "empty".to_string(),
+            threshold: None,
+            site: None,
+            facility_type: None,
+            gender: None,
+            building: None,
+            floor: None,
+        };
+        assert!(payload.validate().is_err());
+        assert!(CreateAlertRule {
+            kind: FREED.to_string(),
+            ..payload
+        }
+        .validate()
+        .is_ok());
+    }
+}
@@ -0,0 +1,11 @@
+use crate::repositories::alert::models::{AlertRule, CreateAlertRule, UpdateAlertRule};
+use axum::async_trait;
+
+#[async_trait]
+pub trait AlertRepository: std::marker::Send + std::marker::Sync + 'static {
+    async fn find_by_id(&self, id: i32) -> anyhow::Result<AlertRule>;
+    async fn find_all(&self) -> anyhow::Result<Vec<AlertRule>>;
+    async fn create(&self, payload: CreateAlertRule) -> anyhow::Result<AlertRule>;
+    async fn update(&self, id: i32, payload: UpdateAlertRule) -> anyhow::Result<AlertRule>;
+    async fn delete(&self, id: i32) -> anyhow::Result<()>;
+}
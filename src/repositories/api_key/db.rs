@@ -0,0 +1,104 @@
+use crate::repositories::api_key::errors::ApiKeyError;
+use crate::repositories::api_key::models::{ApiKey, NewApiKey};
+use crate::repositories::api_key::traits::ApiKeyRepository;
+use axum::async_trait;
+use sqlx::PgPool;
+
+#[derive(Clone, Debug)]
+pub struct DBApiKeyRepository {
+    pool: PgPool,
+}
+
+impl DBApiKeyRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for DBApiKeyRepository {
+    async fn create(&self, key: NewApiKey) -> anyhow::Result<ApiKey> {
+        let key = sqlx::query_as::<_, ApiKey>(
+            "insert into api_keys (name, key_hash, site, building) values ($1, $2, $3, $4) returning *",
+        )
+        .bind(key.name)
+        .bind(key.key_hash)
+        .bind(key.site)
+        .bind(key.building)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(key)
+    }
+
+    async fn find_all(&self) -> anyhow::Result<Vec<ApiKey>> {
+        let keys = sqlx::query_as::<_, ApiKey>("select * from api_keys order by id asc")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(keys)
+    }
+
+    async fn find_by_hash(&self, key_hash: &str) -> anyhow::Result<Option<ApiKey>> {
+        let key = sqlx::query_as::<_, ApiKey>(
+            "select * from api_keys where key_hash = $1 and revoked_at is null",
+        )
+        .bind(key_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(key)
+    }
+
+    async fn revoke(&self, id: i32) -> anyhow::Result<ApiKey> {
+        let key = sqlx::query_as::<_, ApiKey>(
+            "update api_keys set revoked_at = coalesce(revoked_at, now()) where id = $1 returning *",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(ApiKeyError::NotFound(id))?;
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::api_key::models::{generate_key, hash_key};
+    use anyhow::Result;
+    use dotenv::dotenv;
+    use std::env;
+
+    async fn setup() -> Result<DBApiKeyRepository> {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL")?;
+        let pool = PgPool::connect(&database_url).await?;
+        Ok(DBApiKeyRepository::new(pool))
+    }
+
+    #[tokio::test]
+    async fn test_api_key_lifecycle() -> Result<()> {
+        let repository = setup().await?;
+        let secret = generate_key();
+
+        let key = repository
+            .create(NewApiKey {
+                name: "sensor".to_string(),
+                key_hash: hash_key(&secret),
+                site: Some("main".to_string()),
+                building: Some("A".to_string()),
+            })
+            .await?;
+        assert_eq!(key.building.as_deref(), Some("A"));
+        assert_eq!(
+            repository.find_by_hash(&hash_key(&secret)).await?,
+            Some(key.clone())
+        );
+        assert!(repository.find_all().await?.contains(&key));
+
+        let revoked = repository.revoke(key.id).await?;
+        assert!(revoked.revoked_at.is_some());
+        assert_eq!(repository.find_by_hash(&hash_key(&secret)).await?, None);
+        assert!(repository.revoke(0).await.is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use crate::repositories::api_key::errors::ApiKeyError;
+use crate::repositories::api_key::models::{ApiKey, NewApiKey};
+use crate::repositories::api_key::traits::ApiKeyRepository;
+use axum::async_trait;
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Default)]
+struct ApiKeyDatas {
+    keys: BTreeMap<i32, ApiKey>,
+    last_id: i32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryApiKeyRepository {
+    store: Arc<RwLock<ApiKeyDatas>>,
+}
+
+impl InMemoryApiKeyRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for InMemoryApiKeyRepository {
+    async fn create(&self, key: NewApiKey) -> anyhow::Result<ApiKey> {
+        let mut store = self.store.write().unwrap();
+        store.last_id += 1;
+        let key = ApiKey {
+            id: store.last_id,
+            name: key.name,
+            key_hash: key.key_hash,
+            site: key.site,
+            building: key.building,
+            created_at: Utc::now(),
+            revoked_at: None,
+        };
+        store.keys.insert(key.id, key.clone());
+        Ok(key)
+    }
+
+    async fn find_all(&self) -> anyhow::Result<Vec<ApiKey>> {
+        let store = self.store.read().unwrap();
+        Ok(store.keys.values().cloned().collect())
+    }
+
+    async fn find_by_hash(&self, key_hash: &str) -> anyhow::Result<Option<ApiKey>> {
+        let store = self.store.read().unwrap();
+        let key = store
+            .keys
+            .values()
+            .find(|key| key.key_hash == key_hash && key.revoked_at.is_none());
+        Ok(key.cloned())
+    }
+
+    async fn revoke(&self, id: i32) -> anyhow::Result<ApiKey> {
+        let mut store = self.store.write().unwrap();
+        let key = store.keys.get_mut(&id).ok_or(ApiKeyError::NotFound(id))?;
+        key.revoked_at.get_or_insert_with(Utc::now);
+        Ok(key.clone())
+    }
+}
@@ -0,0 +1,10 @@
+#[cfg(feature = "postgres")]
+pub mod db;
+pub mod errors;
+// keys for the in-memory demo, which can opt out of them with OPEN_WRITES=true
+#[cfg(feature = "in-memory")]
+pub mod in_memory;
+pub mod models;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod traits;
@@ -0,0 +1,102 @@
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// keys are shown once when they are created, only their hash is stored
+pub const KEY_PREFIX: &str = "shw_";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct ApiKey {
+    pub id: i32,
+    pub name: String,
+    #[serde(skip_serializing, default)]
+    pub key_hash: String,
+    // none for every site, or every building of the site
+    pub site: Option<String>,
+    pub building: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    // unscoped keys administer the whole deployment
+    pub fn is_admin(&self) -> bool {
+        self.site.is_none() && self.building.is_none()
+    }
+
+    // a scoped key only writes to the sections of its site and building, the routes that
+    // are not about a building (webhooks, alert rules...) need an unscoped key
+    pub fn allows(&self, site: &str, building: Option<&str>) -> bool {
+        if self.is_admin() {
+            return true;
+        }
+        let Some(building) = building else {
+            return false;
+        };
+        self.site.iter().all(|scope| scope == site)
+            && self.building.iter().all(|scope| scope == building)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewApiKey {
+    pub name: String,
+    pub key_hash: String,
+    pub site: Option<String>,
+    pub building: Option<String>,
+}
+
+pub fn generate_key() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("{}{}", KEY_PREFIX, hex::encode(bytes))
+}
+
+// the keys are random, so a plain sha-256 is enough to keep them out of the database
+pub fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(site: Option<&str>, building: Option<&str>) -> ApiKey {
+        ApiKey {
+            id: 1,
+            name: "sensor".to_string(),
+            key_hash: String::new(),
+            site: site.map(str::to_string),
+            building: building.map(str::to_string),
+            created_at: Utc::now(),
+            revoked_at: None,
+        }
+    }
+
+    #[test]
+    fn test_scope() {
+        assert!(key(None, None).allows("main", None));
+        assert!(key(None, None).allows("north", Some("A")));
+
+        let site = key(Some("main"), None);
+        assert!(site.allows("main", Some("B")));
+        assert!(!site.allows("north", Some("B")));
+        assert!(!site.allows("main", None));
+
+        let building = key(Some("main"), Some("A"));
+        assert!(building.allows("main", Some("A")));
+        assert!(!building.allows("main", Some("B")));
+        assert!(!building.allows("north", Some("A")));
+    }
+
+    #[test]
+    fn test_generate_key() {
+        let (first, second) = (generate_key(), generate_key());
+        assert!(first.starts_with(KEY_PREFIX));
+        assert_ne!(first, second);
+        assert_eq!(hash_key(&first), hash_key(&first));
+        assert_ne!(hash_key(&first), hash_key(&second));
+        assert_eq!(hash_key(&first).len(), 64);
+    }
+}
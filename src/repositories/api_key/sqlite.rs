@@ -0,0 +1,108 @@
+use crate::repositories::api_key::errors::ApiKeyError;
+use crate::repositories::api_key::models::{ApiKey, NewApiKey};
+use crate::repositories::api_key::traits::ApiKeyRepository;
+use axum::async_trait;
+use chrono::Utc;
+use sqlx::SqlitePool;
+
+// shares the pool of the sqlite section repository, its migrations create the table
+#[derive(Clone, Debug)]
+pub struct SqliteApiKeyRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteApiKeyRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for SqliteApiKeyRepository {
+    // the writes commit explicitly, a returning statement left unfinished by fetch_one
+    // is otherwise rolled back when the admin cli exits
+    async fn create(&self, key: NewApiKey) -> anyhow::Result<ApiKey> {
+        let mut tx = self.pool.begin().await?;
+        let key = sqlx::query_as::<_, ApiKey>(
+            "insert into api_keys (name, key_hash, site, building, created_at) values ($1, $2, $3, $4, $5) returning *",
+        )
+        .bind(key.name)
+        .bind(key.key_hash)
+        .bind(key.site)
+        .bind(key.building)
+        .bind(Utc::now())
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(key)
+    }
+
+    async fn find_all(&self) -> anyhow::Result<Vec<ApiKey>> {
+        let keys = sqlx::query_as::<_, ApiKey>("select * from api_keys order by id asc")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(keys)
+    }
+
+    async fn find_by_hash(&self, key_hash: &str) -> anyhow::Result<Option<ApiKey>> {
+        let key = sqlx::query_as::<_, ApiKey>(
+            "select * from api_keys where key_hash = $1 and revoked_at is null",
+        )
+        .bind(key_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(key)
+    }
+
+    async fn revoke(&self, id: i32) -> anyhow::Result<ApiKey> {
+        let mut tx = self.pool.begin().await?;
+        let key = sqlx::query_as::<_, ApiKey>(
+            "update api_keys set revoked_at = coalesce(revoked_at, $2) where id = $1 returning *",
+        )
+        .bind(id)
+        .bind(Utc::now())
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(ApiKeyError::NotFound(id))?;
+        tx.commit().await?;
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::api_key::models::{generate_key, hash_key};
+    use crate::repositories::section::sqlite::SqliteSectionRepository;
+    use anyhow::Result;
+
+    #[tokio::test]
+    async fn test_api_key_lifecycle() -> Result<()> {
+        let sections = SqliteSectionRepository::connect("sqlite::memory:").await?;
+        let repository = SqliteApiKeyRepository::new(sections.pool().clone());
+        let secret = generate_key();
+
+        let key = repository
+            .create(NewApiKey {
+                name: "sensor".to_string(),
+                key_hash: hash_key(&secret),
+                site: Some("main".to_string()),
+                building: Some("A".to_string()),
+            })
+            .await?;
+        assert_eq!(key.building.as_deref(), Some("A"));
+        assert_eq!(
+            repository.find_by_hash(&hash_key(&secret)).await?,
+            Some(key.clone())
+        );
+        assert_eq!(repository.find_all().await?, vec![key.clone()]);
+
+        let revoked = repository.revoke(key.id).await?;
+        assert!(revoked.revoked_at.is_some());
+        assert_eq!(repository.revoke(key.id).await?, revoked);
+        assert_eq!(repository.find_by_hash(&hash_key(&secret)).await?, None);
+        assert!(repository.revoke(0).await.is_err());
+
+        Ok(())
+    }
+}
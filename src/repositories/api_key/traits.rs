@@ -0,0 +1,12 @@
+use crate::repositories::api_key::models::{ApiKey, NewApiKey};
+use axum::async_trait;
+
+#[async_trait]
+pub trait ApiKeyRepository: std::marker::Send + std::marker::Sync + 'static {
+    async fn create(&self, key: NewApiKey) -> anyhow::Result<ApiKey>;
+    // revoked keys included
+    async fn find_all(&self) -> anyhow::Result<Vec<ApiKey>>;
+    // the key a request presented, none when it is unknown or revoked
+    async fn find_by_hash(&self, key_hash: &str) -> anyhow::Result<Option<ApiKey>>;
+    async fn revoke(&self, id: i32) -> anyhow::Result<ApiKey>;
+}
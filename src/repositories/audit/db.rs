@@ -0,0 +1,140 @@
+use crate::repositories::audit::models::{AuditEntry, AuditFilter, Counters, NewAuditEntry};
+use crate::repositories::audit::traits::AuditRepository;
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::PgPool;
+
+// the counters are stored as jsonb, the row unwraps them into the entry
+#[derive(sqlx::FromRow)]
+struct AuditRow {
+    id: i32,
+    section_id: i32,
+    action: String,
+    transition: Option<String>,
+    actor: Option<String>,
+    source_ip: Option<String>,
+    old_counters: Option<Json<Counters>>,
+    new_counters: Option<Json<Counters>>,
+    created_at: DateTime<Utc>,
+}
+
+impl From<AuditRow> for AuditEntry {
+    fn from(row: AuditRow) -> Self {
+        Self {
+            id: row.id,
+            section_id: row.section_id,
+            action: row.action,
+            transition: row.transition,
+            actor: row.actor,
+            source_ip: row.source_ip,
+            old_counters: row.old_counters.map(|Json(counters)| counters),
+            new_counters: row.new_counters.map(|Json(counters)| counters),
+            created_at: row.created_at,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DBAuditRepository {
+    pool: PgPool,
+}
+
+impl DBAuditRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AuditRepository for DBAuditRepository {
+    async fn record(&self, entry: NewAuditEntry) -> anyhow::Result<AuditEntry> {
+        let row = sqlx::query_as::<_, AuditRow>(
+            "insert into audit_log (section_id, action, transition, actor, source_ip, old_counters, new_counters) values ($1, $2, $3, $4, $5, $6, $7) returning *",
+        )
+        .bind(entry.section_id)
+        .bind(entry.action)
+        .bind(entry.transition)
+        .bind(entry.actor)
+        .bind(entry.source_ip)
+        .bind(entry.old_counters.map(Json))
+        .bind(entry.new_counters.map(Json))
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row.into())
+    }
+
+    async fn find(&self, filter: AuditFilter) -> anyhow::Result<Vec<AuditEntry>> {
+        let limit = filter.limit();
+        let rows = sqlx::query_as::<_, AuditRow>(
+            "select * from audit_log where ($1::int is null or section_id = $1) and ($2::text is null or actor = $2) and ($3::timestamptz is null or created_at >= $3) and ($4::timestamptz is null or created_at < $4) order by id desc limit $5",
+        )
+        .bind(filter.section)
+        .bind(filter.actor)
+        .bind(filter.from)
+        .bind(filter.to)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::audit::models::{Counters, CAPACITY_CHANGED};
+    use anyhow::Result;
+    use chrono::Utc;
+    use dotenv::dotenv;
+    use std::env;
+
+    #[tokio::test]
+    async fn test_record_and_find() -> Result<()> {
+        dotenv().ok();
+        let pool = PgPool::connect(&env::var("DATABASE_URL")?).await?;
+        let repository = DBAuditRepository::new(pool);
+        // a fresh actor keeps the entries of earlier runs out of the results
+        let actor = format!("db-test-{}", Utc::now().timestamp_nanos_opt().unwrap());
+        let new_entry = |section_id| NewAuditEntry {
+            section_id,
+            action: CAPACITY_CHANGED.to_string(),
+            transition: None,
+            actor: Some(actor.clone()),
+            source_ip: Some("10.0.0.1".to_string()),
+            old_counters: Some(Counters {
+                total: 10,
+                available: 10,
+                occupied: 0,
+                disabled_rooms: 0,
+            }),
+            new_counters: None,
+        };
+
+        let first = repository.record(new_entry(1)).await?;
+        let second = repository.record(new_entry(2)).await?;
+        assert_eq!(first.old_counters.unwrap().total, 10);
+        assert_eq!(first.new_counters, None);
+
+        let filter = AuditFilter {
+            actor: Some(actor.clone()),
+            ..Default::default()
+        };
+        assert_eq!(
+            repository.find(filter.clone()).await?,
+            vec![second.clone(), first.clone()]
+        );
+        let by_section = AuditFilter {
+            section: Some(1),
+            ..filter.clone()
+        };
+        assert_eq!(repository.find(by_section).await?, vec![first.clone()]);
+        let before = AuditFilter {
+            to: Some(first.created_at),
+            ..filter
+        };
+        assert!(repository.find(before).await?.is_empty());
+
+        Ok(())
+    }
+}
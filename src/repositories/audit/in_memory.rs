@@ -0,0 +1,107 @@
+use crate::repositories::audit::models::{AuditEntry, AuditFilter, NewAuditEntry};
+use crate::repositories::audit::traits::AuditRepository;
+use axum::async_trait;
+use chrono::Utc;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Default)]
+struct AuditDatas {
+    // oldest first, entries are never changed once written
+    entries: Vec<AuditEntry>,
+    last_id: i32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryAuditRepository {
+    store: Arc<RwLock<AuditDatas>>,
+}
+
+impl InMemoryAuditRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl AuditRepository for InMemoryAuditRepository {
+    async fn record(&self, entry: NewAuditEntry) -> anyhow::Result<AuditEntry> {
+        let mut store = self.store.write().unwrap();
+        store.last_id += 1;
+        let entry = AuditEntry {
+            id: store.last_id,
+            section_id: entry.section_id,
+            action: entry.action,
+            transition: entry.transition,
+            actor: entry.actor,
+            source_ip: entry.source_ip,
+            old_counters: entry.old_counters,
+            new_counters: entry.new_counters,
+            created_at: Utc::now(),
+        };
+        store.entries.push(entry.clone());
+        Ok(entry)
+    }
+
+    async fn find(&self, filter: AuditFilter) -> anyhow::Result<Vec<AuditEntry>> {
+        let store = self.store.read().unwrap();
+        Ok(store
+            .entries
+            .iter()
+            .rev()
+            .filter(|entry| filter.matches(entry))
+            .take(filter.limit() as usize)
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod in_memory_tests {
+    use super::*;
+    use crate::repositories::audit::models::{Counters, UPDATED};
+    use chrono::Duration;
+
+    fn entry(section_id: i32, actor: &str) -> NewAuditEntry {
+        NewAuditEntry {
+            section_id,
+            action: UPDATED.to_string(),
+            transition: Some("available->occupied".to_string()),
+            actor: Some(actor.to_string()),
+            source_ip: None,
+            old_counters: Some(Counters::default()),
+            new_counters: Some(Counters::default()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_audit_repository() {
+        let repo = InMemoryAuditRepository::new();
+        let start = Utc::now();
+        for (section_id, actor) in [(1, "alice"), (2, "bob"), (1, "bob")] {
+            repo.record(entry(section_id, actor)).await.unwrap();
+        }
+
+        let ids = |entries: Vec<AuditEntry>| entries.iter().map(|e| e.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(repo.find(AuditFilter::default()).await.unwrap()),
+            [3, 2, 1]
+        );
+        let filter = AuditFilter {
+            section: Some(1),
+            actor: Some("bob".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(repo.find(filter).await.unwrap()), [3]);
+        let filter = AuditFilter {
+            limit: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(ids(repo.find(filter).await.unwrap()), [3, 2]);
+        let filter = AuditFilter {
+            from: Some(start - Duration::minutes(1)),
+            to: Some(start),
+            ..Default::default()
+        };
+        assert!(repo.find(filter).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,6 @@
+#[cfg(feature = "postgres")]
+pub mod db;
+#[cfg(feature = "in-memory")]
+pub mod in_memory;
+pub mod models;
+pub mod traits;
@@ -0,0 +1,86 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::repositories::section::models::Section;
+
+pub const CREATED: &str = "created";
+pub const UPDATED: &str = "updated";
+pub const CAPACITY_CHANGED: &str = "capacity_changed";
+pub const DELETED: &str = "deleted";
+pub const RESTORED: &str = "restored";
+
+// entries returned by one query when the filter sets no limit, and at most
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counters {
+    pub total: i32,
+    pub available: i32,
+    pub occupied: i32,
+    pub disabled_rooms: i32,
+}
+
+impl From<&Section> for Counters {
+    fn from(section: &Section) -> Self {
+        Self {
+            total: section.total,
+            available: section.available,
+            occupied: section.occupied,
+            disabled_rooms: section.disabled_rooms,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub id: i32,
+    pub section_id: i32,
+    pub action: String,
+    // "available->occupied" for counter updates
+    pub transition: Option<String>,
+    pub actor: Option<String>,
+    pub source_ip: Option<String>,
+    // none before a create and after a delete
+    pub old_counters: Option<Counters>,
+    pub new_counters: Option<Counters>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct NewAuditEntry {
+    pub section_id: i32,
+    pub action: String,
+    pub transition: Option<String>,
+    pub actor: Option<String>,
+    pub source_ip: Option<String>,
+    pub old_counters: Option<Counters>,
+    pub new_counters: Option<Counters>,
+}
+
+// every field narrows the result, entries come newest first
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct AuditFilter {
+    pub section: Option<i32>,
+    pub actor: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+impl AuditFilter {
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(0, MAX_LIMIT)
+    }
+
+    #[cfg(feature = "in-memory")]
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.section.iter().all(|&id| entry.section_id == id)
+            && self
+                .actor
+                .iter()
+                .all(|actor| entry.actor.as_ref() == Some(actor))
+            && self.from.iter().all(|&from| entry.created_at >= from)
+            && self.to.iter().all(|&to| entry.created_at < to)
+    }
+}
@@ -0,0 +1,8 @@
+use crate::repositories::audit::models::{AuditEntry, AuditFilter, NewAuditEntry};
+use axum::async_trait;
+
+#[async_trait]
+pub trait AuditRepository: std::marker::Send + std::marker::Sync + 'static {
+    async fn record(&self, entry: NewAuditEntry) -> anyhow::Result<AuditEntry>;
+    async fn find(&self, filter: AuditFilter) -> anyhow::Result<Vec<AuditEntry>>;
+}
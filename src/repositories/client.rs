@@ -0,0 +1,90 @@
+use std::future::Future;
+#[cfg(feature = "postgres")]
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+tokio::task_local! {
+    // the client the current request comes from, when it says who it is
+    static CLIENT: Option<String>;
+    // set while the reads must not go to a replica
+    static PRIMARY: bool;
+}
+
+pub async fn scope<F: Future>(client: Option<String>, call: F) -> F::Output {
+    CLIENT.scope(client, call).await
+}
+
+#[cfg(feature = "postgres")]
+pub fn current() -> Option<String> {
+    CLIENT.try_with(Clone::clone).ok().flatten()
+}
+
+// what the cache keeps is served to every client, so it is filled from the primary
+pub async fn on_primary<F: Future>(call: F) -> F::Output {
+    PRIMARY.scope(true, call).await
+}
+
+#[cfg(feature = "postgres")]
+pub fn is_on_primary() -> bool {
+    PRIMARY.try_with(|primary| *primary).unwrap_or(false)
+}
+
+// clients that wrote within the window, their reads skip a replica that may lag behind
+#[cfg(feature = "postgres")]
+#[derive(Debug)]
+pub struct RecentWrites {
+    window: Duration,
+    writes: Mutex<HashMap<String, Instant>>,
+}
+
+#[cfg(feature = "postgres")]
+impl RecentWrites {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            writes: Mutex::default(),
+        }
+    }
+
+    pub fn record(&self) {
+        if let Some(client) = current() {
+            let now = Instant::now();
+            let mut writes = self.writes.lock().unwrap();
+            writes.retain(|_, at| now.duration_since(*at) < self.window);
+            writes.insert(client, now);
+        }
+    }
+
+    pub fn is_recent(&self) -> bool {
+        current().is_some_and(|client| {
+            self.writes
+                .lock()
+                .unwrap()
+                .get(&client)
+                .is_some_and(|at| at.elapsed() < self.window)
+        })
+    }
+}
+
+#[cfg(all(test, feature = "postgres"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_recent_writes() {
+        let recent = RecentWrites::new(Duration::from_millis(50));
+        // anonymous requests are never tracked
+        recent.record();
+        assert!(!recent.is_recent());
+
+        scope(Some("a".to_string()), async { recent.record() }).await;
+        assert!(scope(Some("a".to_string()), async { recent.is_recent() }).await);
+        assert!(!scope(Some("b".to_string()), async { recent.is_recent() }).await);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(!scope(Some("a".to_string()), async { recent.is_recent() }).await);
+    }
+}
@@ -0,0 +1,121 @@
+use axum::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::repositories::events::models::{Event, EventFilter, EventMessage, Events, Subscription};
+use crate::repositories::events::traits::EventTrait;
+
+const CHANNEL: &str = "section_events";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Notification {
+    id: u64,
+    message: EventMessage,
+}
+
+// publishes through pg_notify so every replica listening on the channel delivers the event
+pub struct DBEvents {
+    pool: PgPool,
+    local: Arc<Events>,
+}
+
+impl DBEvents {
+    pub async fn new(pool: PgPool, local: Events) -> anyhow::Result<Self> {
+        let local = Arc::new(local);
+        let mut listener = PgListener::connect_with(&pool).await?;
+        listener.listen(CHANNEL).await?;
+        tokio::spawn(listen(listener, Arc::clone(&local)));
+        Ok(Self { pool, local })
+    }
+}
+
+async fn listen(mut listener: PgListener, local: Arc<Events>) {
+    loop {
+        match listener.try_recv().await {
+            Ok(Some(notification)) => {
+                match serde_json::from_str::<Notification>(notification.payload()) {
+                    Ok(Notification { id, message }) => {
+                        local.publish(Event::from_message(id, message))
+                    }
+                    Err(e) => tracing::warn!("invalid {} payload: {}", CHANNEL, e),
+                }
+            }
+            // the listener reconnects on the next call, notifications sent meanwhile are lost
+            Ok(None) => tracing::warn!("lost connection listening on {}", CHANNEL),
+            Err(e) => {
+                tracing::error!("failed to listen on {}: {}", CHANNEL, e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl EventTrait for DBEvents {
+    async fn subscribe(
+        &self,
+        last_event_id: Option<u64>,
+        filter: EventFilter,
+    ) -> anyhow::Result<Subscription> {
+        self.local.subscribe(last_event_id, filter).await
+    }
+
+    async fn notify(&self, msg: EventMessage) -> anyhow::Result<()> {
+        // ids come from a shared sequence so Last-Event-ID means the same on every replica
+        sqlx::query(
+            "select pg_notify($1, json_build_object('id', nextval('section_event_ids'), 'message', $2::json)::text)",
+        )
+        .bind(CHANNEL)
+        .bind(serde_json::to_string(&msg)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn subscribers(&self) -> usize {
+        self.local.subscribers().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use dotenv::dotenv;
+    use std::env;
+
+    async fn setup() -> Result<PgPool> {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL")?;
+        let pool = PgPool::connect(&database_url).await?;
+        Ok(pool)
+    }
+
+    #[tokio::test]
+    async fn test_notify_reaches_other_replicas() -> Result<()> {
+        let pool = setup().await?;
+        // two buses on one database behave like two api containers
+        let first = DBEvents::new(pool.clone(), Events::new()).await?;
+        let second = DBEvents::new(pool, Events::new()).await?;
+        let filter = EventFilter {
+            site: None,
+            facility_type: None,
+            gender: Some("female".to_string()),
+            building: Some("B".to_string()),
+            floor: Some(4),
+        };
+        let mut subscription = second.subscribe(None, filter).await?;
+
+        first.notify("female/B/4".to_string().into()).await?;
+        let event = tokio::time::timeout(Duration::from_secs(5), subscription.recv())
+            .await?
+            .unwrap();
+        assert_eq!(event.data, "female/B/4");
+        assert!(event.id > 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,7 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum EventError {
+    #[error("too many subscribers, limit is: {0}")]
+    TooManySubscribers(usize),
+}
@@ -1,2 +1,7 @@
+#[cfg(feature = "postgres")]
+pub mod db;
+pub mod errors;
 pub mod models;
+#[cfg(feature = "postgres")]
+pub mod outbox;
 pub mod traits;
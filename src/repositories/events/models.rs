@@ -1,39 +1,312 @@
 use axum::async_trait;
-use std::collections::HashMap;
-use std::sync::{atomic::AtomicU64, atomic::Ordering, Arc};
-use tokio::sync::mpsc::UnboundedReceiver;
-use tokio::sync::{mpsc::unbounded_channel, mpsc::UnboundedSender, Mutex};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::sync::{atomic::AtomicU64, atomic::Ordering, Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_stream::Stream;
 
+use crate::repositories::events::errors::EventError;
 use crate::repositories::events::traits::EventTrait;
+use crate::repositories::section::models::Section;
+
+// number of recent events kept for Last-Event-ID replay
+const HISTORY_CAPACITY: usize = 256;
+// number of concurrent SSE clients accepted before answering 503
+const MAX_SUBSCRIBERS: usize = 1024;
+
+pub const SECTION_UPDATED: &str = "section.updated";
+pub const SECTION_CREATED: &str = "section.created";
+pub const SECTION_DELETED: &str = "section.deleted";
+pub const SECTION_RESTORED: &str = "section.restored";
+pub const SECTION_CAPACITY_CHANGED: &str = "section.capacity_changed";
+
+// what gets published on the bus, the id is assigned on delivery
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct EventMessage {
+    pub name: Option<String>,
+    // "site/facility_type/gender/building/floor" of the section, matched against subscriber
+    // filters
+    pub topic: String,
+    pub data: String,
+}
+
+impl EventMessage {
+    // counter updates stay plain SSE messages, their data is the section with its new counters
+    pub fn updated(section: &Section) -> Self {
+        Self {
+            name: None,
+            ..Self::section(SECTION_UPDATED, section)
+        }
+    }
+
+    // a bare location, what outbox rows written before the section was sent along hold
+    pub fn location(topic: String) -> Self {
+        Self {
+            name: None,
+            data: topic.clone(),
+            topic,
+        }
+    }
+
+    // carries the whole section so clients can mirror it without refetching
+    pub fn section(name: &str, section: &Section) -> Self {
+        Self {
+            name: Some(name.to_string()),
+            topic: topic(section),
+            data: serde_json::to_string(section).unwrap_or_default(),
+        }
+    }
+}
+
+pub fn topic(section: &Section) -> String {
+    format!(
+        "{}/{}/{}/{}/{}",
+        section.site, section.facility_type, section.gender, section.building, section.floor
+    )
+}
+
+impl From<String> for EventMessage {
+    fn from(topic: String) -> Self {
+        Self::location(topic)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub id: u64,
+    pub name: Option<String>,
+    pub topic: String,
+    pub data: String,
+}
+
+impl Event {
+    pub fn new(id: u64, data: String) -> Self {
+        Self::from_message(id, EventMessage::location(data))
+    }
+
+    pub fn from_message(id: u64, message: EventMessage) -> Self {
+        Self {
+            id,
+            name: message.name,
+            topic: message.topic,
+            data: message.data,
+        }
+    }
+
+    // unnamed events are counter updates, which SSE clients receive as plain messages
+    pub fn kind(&self) -> &str {
+        self.name.as_deref().unwrap_or(SECTION_UPDATED)
+    }
+
+    // sent when the client is too far behind to be replayed and must refetch
+    pub fn resync(id: u64) -> Self {
+        Self {
+            id,
+            name: Some("resync".to_string()),
+            topic: String::new(),
+            data: "resync required".to_string(),
+        }
+    }
+}
+
+// location a subscriber is interested in, unset fields match everything
+#[derive(Debug, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    pub site: Option<String>,
+    pub facility_type: Option<String>,
+    pub gender: Option<String>,
+    pub building: Option<String>,
+    pub floor: Option<i32>,
+}
+
+impl EventFilter {
+    // topics are "site/facility_type/gender/building/floor" paths, read from the floor up
+    // since older outbox rows hold "site/gender/building/floor" or "gender/building/floor"
+    pub fn matches(&self, topic: &str) -> bool {
+        let mut parts = topic.rsplitn(4, '/');
+        let floor = parts.next().and_then(|floor| floor.parse::<i32>().ok());
+        let (building, gender) = (parts.next(), parts.next());
+        let (site, facility_type) = match parts.next().map(|scope| (scope, scope.split_once('/'))) {
+            Some((_, Some((site, facility_type)))) => (Some(site), Some(facility_type)),
+            Some((site, None)) => (Some(site), None),
+            None => (None, None),
+        };
+        field_matches(self.site.as_deref(), site)
+            && field_matches(self.facility_type.as_deref(), facility_type)
+            && field_matches(self.gender.as_deref(), gender)
+            && field_matches(self.building.as_deref(), building)
+            && field_matches(self.floor, floor)
+    }
+}
+
+fn field_matches<T: PartialEq>(expected: Option<T>, actual: Option<T>) -> bool {
+    expected.is_none() || expected == actual
+}
+
+type Clients = HashMap<u64, (EventFilter, UnboundedSender<Event>)>;
+
+// receiving end of a subscription, the client is unregistered as soon as it is dropped
+pub struct Subscription {
+    rx: UnboundedReceiver<Event>,
+    _guard: Option<SubscriberGuard>,
+}
+
+impl Subscription {
+    pub fn new(rx: UnboundedReceiver<Event>) -> Self {
+        Self { rx, _guard: None }
+    }
+
+    pub async fn recv(&mut self) -> Option<Event> {
+        self.rx.recv().await
+    }
+
+    pub fn try_recv(&mut self) -> Result<Event, TryRecvError> {
+        self.rx.try_recv()
+    }
+}
+
+impl Stream for Subscription {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+struct SubscriberGuard {
+    id: u64,
+    clients: Weak<Mutex<Clients>>,
+}
+
+impl Drop for SubscriberGuard {
+    fn drop(&mut self) {
+        if let Some(clients) = self.clients.upgrade() {
+            if let Ok(mut clients) = clients.lock() {
+                clients.remove(&self.id);
+            }
+        }
+    }
+}
 
 pub struct Events {
-    clients: Arc<Mutex<HashMap<u64, UnboundedSender<String>>>>,
+    clients: Arc<Mutex<Clients>>,
     last_id: AtomicU64,
+    history: Mutex<VecDeque<Event>>,
+    capacity: usize,
+    last_event_id: AtomicU64,
+    max_subscribers: usize,
 }
 
 impl Events {
     pub fn new() -> Self {
+        Self::with_capacity(HISTORY_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
         Self {
             clients: Arc::new(Mutex::new(HashMap::new())),
             last_id: AtomicU64::new(0),
+            history: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity: capacity.max(1),
+            last_event_id: AtomicU64::new(0),
+            max_subscribers: MAX_SUBSCRIBERS,
+        }
+    }
+
+    pub fn with_max_subscribers(self, max_subscribers: usize) -> Self {
+        Self {
+            max_subscribers,
+            ..self
+        }
+    }
+
+    // events the client missed since `last_event_id`, or a resync event if they are gone
+    fn missed_events(
+        &self,
+        history: &VecDeque<Event>,
+        last_event_id: u64,
+        filter: &EventFilter,
+    ) -> Vec<Event> {
+        let latest = self.last_event_id.load(Ordering::SeqCst);
+        if last_event_id == latest {
+            return vec![];
+        }
+        match history.front() {
+            Some(oldest) if last_event_id < latest && last_event_id + 1 >= oldest.id => history
+                .iter()
+                .filter(|event| event.id > last_event_id && filter.matches(&event.topic))
+                .cloned()
+                .collect(),
+            _ => vec![Event::resync(latest)],
+        }
+    }
+
+    // delivers an event whose id was assigned elsewhere, e.g. by another replica
+    #[cfg(feature = "postgres")]
+    pub fn publish(&self, event: Event) {
+        let mut clients = self.clients.lock().unwrap();
+        self.last_event_id.fetch_max(event.id, Ordering::SeqCst);
+        self.broadcast(&mut clients, event);
+    }
+
+    fn broadcast(&self, clients: &mut Clients, event: Event) {
+        {
+            let mut history = self.history.lock().unwrap();
+            if history.len() == self.capacity {
+                history.pop_front();
+            }
+            history.push_back(event.clone());
         }
+        clients.retain(|_, (filter, sender)| {
+            !filter.matches(&event.topic) || sender.send(event.clone()).is_ok()
+        });
     }
 }
 
 #[async_trait]
 impl EventTrait for Events {
-    async fn subscribe(&self) -> UnboundedReceiver<String> {
+    async fn subscribe(
+        &self,
+        last_event_id: Option<u64>,
+        filter: EventFilter,
+    ) -> anyhow::Result<Subscription> {
         let (tx, rx) = unbounded_channel();
+        // hold the clients lock so no event is published between replay and registration
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= self.max_subscribers {
+            return Err(EventError::TooManySubscribers(self.max_subscribers).into());
+        }
         let id = self.last_id.fetch_add(1, Ordering::SeqCst);
-        self.clients.lock().await.insert(id, tx);
-        rx
+        if let Some(last_event_id) = last_event_id {
+            let history = self.history.lock().unwrap();
+            for event in self.missed_events(&history, last_event_id, &filter) {
+                let _ = tx.send(event);
+            }
+        }
+        clients.insert(id, (filter, tx));
+        tracing::debug!("subscribers: {}", clients.len());
+        Ok(Subscription {
+            rx,
+            _guard: Some(SubscriberGuard {
+                id,
+                clients: Arc::downgrade(&self.clients),
+            }),
+        })
     }
 
-    async fn notify(&self, msg: String) -> anyhow::Result<()> {
-        let mut clients = self.clients.lock().await;
-        clients.retain(|_, sender| sender.send(msg.clone()).is_ok());
+    async fn notify(&self, msg: EventMessage) -> anyhow::Result<()> {
+        let mut clients = self.clients.lock().unwrap();
+        let event = Event::from_message(self.last_event_id.fetch_add(1, Ordering::SeqCst) + 1, msg);
+        self.broadcast(&mut clients, event);
         Ok(())
     }
+
+    async fn subscribers(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
 }
 
 #[cfg(test)]
@@ -44,8 +317,9 @@ mod events_test {
     #[tokio::test]
     async fn test_new() {
         let events = Events::new();
-        assert_eq!(events.clients.lock().await.len(), 0);
+        assert_eq!(events.subscribers().await, 0);
         assert_eq!(events.last_id.load(Ordering::SeqCst), 0);
+        assert_eq!(events.last_event_id.load(Ordering::SeqCst), 0);
     }
 
     #[test]
@@ -53,9 +327,16 @@ mod events_test {
         let events = Events::new();
         let rt = Runtime::new().unwrap();
         rt.block_on(async {
-            events.subscribe().await;
-            assert_eq!(events.clients.lock().await.len(), 1);
+            let subscription = events
+                .subscribe(None, EventFilter::default())
+                .await
+                .unwrap();
+            assert_eq!(events.subscribers().await, 1);
             assert_eq!(events.last_id.load(Ordering::SeqCst), 1);
+
+            // dropping the stream unregisters the client right away
+            drop(subscription);
+            assert_eq!(events.subscribers().await, 0);
         });
     }
 
@@ -64,9 +345,195 @@ mod events_test {
         let events = Events::new();
         let rt = Runtime::new().unwrap();
         rt.block_on(async {
-            let mut rx = events.subscribe().await;
-            events.notify("test".to_string()).await.unwrap();
-            assert_eq!(rx.recv().await.unwrap(), "test");
+            let mut rx = events
+                .subscribe(None, EventFilter::default())
+                .await
+                .unwrap();
+            events.notify("test".to_string().into()).await.unwrap();
+            events.notify("test2".to_string().into()).await.unwrap();
+            assert_eq!(rx.recv().await.unwrap(), Event::new(1, "test".to_string()));
+            assert_eq!(rx.recv().await.unwrap(), Event::new(2, "test2".to_string()));
         });
     }
+
+    #[tokio::test]
+    async fn test_replay_missed_events() {
+        let events = Events::new();
+        for msg in ["a", "b", "c"] {
+            events.notify(msg.to_string().into()).await.unwrap();
+        }
+
+        let mut rx = events
+            .subscribe(Some(1), EventFilter::default())
+            .await
+            .unwrap();
+        events.notify("d".to_string().into()).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), Event::new(2, "b".to_string()));
+        assert_eq!(rx.recv().await.unwrap(), Event::new(3, "c".to_string()));
+        assert_eq!(rx.recv().await.unwrap(), Event::new(4, "d".to_string()));
+
+        // up to date clients get nothing replayed
+        let mut rx = events
+            .subscribe(Some(4), EventFilter::default())
+            .await
+            .unwrap();
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resync_when_gap_is_too_old() {
+        let events = Events::with_capacity(2);
+        for msg in ["a", "b", "c", "d"] {
+            events.notify(msg.to_string().into()).await.unwrap();
+        }
+
+        // 2 is the last id still in the buffer's reach (3 and 4 are kept)
+        let mut rx = events
+            .subscribe(Some(2), EventFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap().id, 3);
+
+        let mut rx = events
+            .subscribe(Some(1), EventFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap(), Event::resync(4));
+        assert!(rx.try_recv().is_err());
+
+        // ids from before a restart are ahead of ours
+        let mut rx = events
+            .subscribe(Some(10), EventFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap(), Event::resync(4));
+    }
+
+    #[tokio::test]
+    async fn test_notify_filtered() {
+        let events = Events::new();
+        let filter = EventFilter {
+            site: Some("main".to_string()),
+            facility_type: None,
+            gender: Some("female".to_string()),
+            building: Some("C".to_string()),
+            floor: Some(2),
+        };
+        let mut floor_rx = events.subscribe(None, filter).await.unwrap();
+        let building_filter = EventFilter {
+            site: None,
+            facility_type: None,
+            gender: Some("female".to_string()),
+            building: Some("C".to_string()),
+            floor: None,
+        };
+        let mut building_rx = events
+            .subscribe(None, building_filter.clone())
+            .await
+            .unwrap();
+
+        for msg in [
+            "main/shower/female/C/1",
+            "main/shower/male/C/2",
+            "north/shower/female/C/2",
+        ] {
+            events.notify(msg.to_string().into()).await.unwrap();
+        }
+        events
+            .notify("main/shower/female/C/2".to_string().into())
+            .await
+            .unwrap();
+        assert_eq!(
+            floor_rx.recv().await.unwrap().data,
+            "main/shower/female/C/2"
+        );
+        assert!(floor_rx.try_recv().is_err());
+        assert_eq!(
+            building_rx.recv().await.unwrap().data,
+            "main/shower/female/C/1"
+        );
+        assert_eq!(
+            building_rx.recv().await.unwrap().data,
+            "north/shower/female/C/2"
+        );
+        assert_eq!(
+            building_rx.recv().await.unwrap().data,
+            "main/shower/female/C/2"
+        );
+
+        // replay honours the filter too
+        let mut rx = events.subscribe(Some(0), building_filter).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap().id, 1);
+        assert_eq!(rx.recv().await.unwrap().id, 3);
+        assert_eq!(rx.recv().await.unwrap().id, 4);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let filter = EventFilter {
+            site: None,
+            facility_type: None,
+            gender: Some("male".to_string()),
+            building: None,
+            floor: Some(3),
+        };
+        assert!(filter.matches("main/shower/male/A/3"));
+        assert!(filter.matches("north/shower/male/B/3"));
+        assert!(!filter.matches("main/shower/male/A/4"));
+        assert!(!filter.matches("main/shower/female/A/3"));
+        assert!(EventFilter::default().matches("main/shower/female/A/3"));
+
+        let north = EventFilter {
+            site: Some("north".to_string()),
+            ..filter
+        };
+        assert!(north.matches("north/shower/male/A/3"));
+        assert!(!north.matches("main/shower/male/A/3"));
+        // the topics of older outbox rows name no site
+        assert!(!north.matches("male/A/3"));
+        assert!(EventFilter::default().matches("male/A/3"));
+
+        let laundry = EventFilter {
+            facility_type: Some("laundry".to_string()),
+            ..north.clone()
+        };
+        assert!(laundry.matches("north/laundry/male/A/3"));
+        assert!(!laundry.matches("north/shower/male/A/3"));
+        assert!(!laundry.matches("main/laundry/male/A/3"));
+        assert!(north.matches("north/shower/male/A/3"));
+        // nor a facility type
+        assert!(!laundry.matches("north/male/A/3"));
+        assert!(north.matches("north/male/A/3"));
+    }
+
+    #[tokio::test]
+    async fn test_max_subscribers() {
+        let events = Events::new().with_max_subscribers(1);
+        let subscription = events.subscribe(None, EventFilter::default()).await;
+        assert!(subscription.is_ok());
+        assert!(events
+            .subscribe(None, EventFilter::default())
+            .await
+            .is_err());
+
+        drop(subscription);
+        assert!(events.subscribe(None, EventFilter::default()).await.is_ok());
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "postgres")]
+    async fn test_publish() {
+        let events = Events::new();
+        let mut rx = events
+            .subscribe(None, EventFilter::default())
+            .await
+            .unwrap();
+        events.publish(Event::new(42, "male/A/1".to_string()));
+        assert_eq!(rx.recv().await.unwrap().id, 42);
+
+        // local ids continue after the highest published one
+        events.notify("male/A/2".to_string().into()).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap().id, 43);
+    }
 }
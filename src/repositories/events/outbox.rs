@@ -0,0 +1,142 @@
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::repositories::events::models::EventMessage;
+use crate::repositories::events::traits::EventTrait;
+
+const BATCH_SIZE: i64 = 100;
+
+// publishes outbox rows on the event bus, a row is only marked delivered after notify succeeds
+pub struct OutboxDispatcher {
+    pool: PgPool,
+    events: Arc<dyn EventTrait>,
+    interval: Duration,
+}
+
+impl OutboxDispatcher {
+    pub fn new(pool: PgPool, events: Arc<dyn EventTrait>, interval: Duration) -> Self {
+        Self {
+            pool,
+            events,
+            interval,
+        }
+    }
+
+    pub async fn run(self) {
+        loop {
+            match self.dispatch().await {
+                // keep draining while there is a backlog
+                Ok(delivered) if delivered as i64 == BATCH_SIZE => continue,
+                Ok(_) => {}
+                Err(e) => tracing::error!("failed to dispatch outbox: {}", e),
+            }
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+
+    // returns the number of rows delivered
+    pub async fn dispatch(&self) -> anyhow::Result<usize> {
+        let mut tx = self.pool.begin().await?;
+        // skip locked rows so several replicas can dispatch side by side
+        let rows = sqlx::query_as::<_, (i64, String)>(
+            "select id, payload from outbox where delivered_at is null order by id limit $1 for update skip locked",
+        )
+        .bind(BATCH_SIZE)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut delivered = vec![];
+        for (id, payload) in rows {
+            // rows written before typed events hold the bare location
+            let message = serde_json::from_str::<EventMessage>(&payload)
+                .unwrap_or_else(|_| EventMessage::location(payload));
+            if let Err(e) = self.events.notify(message).await {
+                tracing::warn!("failed to publish outbox row {}: {}", id, e);
+                break;
+            }
+            delivered.push(id);
+        }
+
+        sqlx::query("update outbox set delivered_at = now() where id = any($1)")
+            .bind(&delivered)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(delivered.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::events::models::{EventFilter, Events};
+    use crate::repositories::section::{
+        db::DBSectionRepository,
+        models::{Section, UpdateSection, DEFAULT_FACILITY, DEFAULT_SITE},
+        traits::SectionRepository,
+    };
+    use anyhow::Result;
+    use dotenv::dotenv;
+    use std::env;
+
+    async fn setup() -> Result<PgPool> {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL")?;
+        let pool = PgPool::connect(&database_url).await?;
+        Ok(pool)
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_update() -> Result<()> {
+        let pool = setup().await?;
+        let repository = DBSectionRepository::new(pool.clone());
+        let section = repository
+            .find_by_floor(
+                DEFAULT_SITE.to_string(),
+                DEFAULT_FACILITY.to_string(),
+                "female".to_string(),
+                "C".to_string(),
+                4,
+            )
+            .await?[0]
+            .clone();
+        let events = Arc::new(Events::new());
+        let filter = EventFilter {
+            site: None,
+            facility_type: None,
+            gender: Some("female".to_string()),
+            building: Some("C".to_string()),
+            floor: Some(4),
+        };
+        let mut subscription = events.subscribe(None, filter).await?;
+        let dispatcher = OutboxDispatcher::new(pool.clone(), events, Duration::from_secs(1));
+
+        for (current_status, next_status) in [("available", "occupied"), ("occupied", "available")]
+        {
+            repository
+                .update(UpdateSection {
+                    id: section.id,
+                    current_status: current_status.to_string(),
+                    next_status: next_status.to_string(),
+                })
+                .await?;
+        }
+        while dispatcher.dispatch().await? > 0 {}
+
+        for occupied in [section.occupied + 1, section.occupied] {
+            let event = subscription.recv().await.unwrap();
+            assert_eq!(event.topic, "main/shower/female/C/4");
+            let updated: Section = serde_json::from_str(&event.data)?;
+            assert_eq!(updated.occupied, occupied);
+        }
+        let (undelivered,) = sqlx::query_as::<_, (i64,)>(
+            "select count(*) from outbox where delivered_at is null and payload like '%female/C/4%'",
+        )
+        .fetch_one(&pool)
+        .await?;
+        assert_eq!(undelivered, 0);
+
+        Ok(())
+    }
+}
@@ -1,8 +1,14 @@
 use axum::async_trait;
-use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::repositories::events::models::{EventFilter, EventMessage, Subscription};
 
 #[async_trait]
-pub trait EventTrait {
-    async fn subscribe(&self) -> UnboundedReceiver<String>;
-    async fn notify(&self, msg: String) -> anyhow::Result<()>;
+pub trait EventTrait: Send + Sync {
+    async fn subscribe(
+        &self,
+        last_event_id: Option<u64>,
+        filter: EventFilter,
+    ) -> anyhow::Result<Subscription>;
+    async fn notify(&self, msg: EventMessage) -> anyhow::Result<()>;
+    async fn subscribers(&self) -> usize;
 }
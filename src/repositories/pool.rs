@@ -0,0 +1,166 @@
+use anyhow::Context;
+use sqlx::postgres::{PgConnectOptions, PgPool, PgPoolOptions};
+use sqlx::{ConnectOptions, Connection};
+use std::{env, str::FromStr, time::Duration};
+
+// longest wait between two connection attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+fn env_u64(name: &str) -> Option<u64> {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+}
+
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout: Duration,
+    pub idle_timeout: Option<Duration>,
+    pub statement_timeout: Option<Duration>,
+    pub connect_attempts: u32,
+    pub backoff: Duration,
+}
+
+// the pool sizes and timeouts are the sqlx defaults
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 10,
+            min_connections: 0,
+            acquire_timeout: Duration::from_secs(30),
+            idle_timeout: Some(Duration::from_secs(600)),
+            statement_timeout: None,
+            connect_attempts: 10,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+impl PoolConfig {
+    // a timeout of 0 ms turns it off
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let timeout = |name, default| match env_u64(name) {
+            Some(0) => None,
+            Some(ms) => Some(Duration::from_millis(ms)),
+            None => default,
+        };
+        Self {
+            max_connections: env_u64("DB_MAX_CONNECTIONS")
+                .map_or(default.max_connections, |n| n as u32),
+            min_connections: env_u64("DB_MIN_CONNECTIONS")
+                .map_or(default.min_connections, |n| n as u32),
+            acquire_timeout: env_u64("DB_ACQUIRE_TIMEOUT_MS")
+                .map_or(default.acquire_timeout, Duration::from_millis),
+            idle_timeout: timeout("DB_IDLE_TIMEOUT_MS", default.idle_timeout),
+            statement_timeout: timeout("DB_STATEMENT_TIMEOUT_MS", default.statement_timeout),
+            connect_attempts: env_u64("DB_CONNECT_ATTEMPTS")
+                .map_or(default.connect_attempts, |n| n as u32),
+            backoff: env_u64("DB_CONNECT_BACKOFF_MS")
+                .map_or(default.backoff, Duration::from_millis),
+        }
+    }
+
+    // doubles after every failed attempt
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(MAX_BACKOFF)
+    }
+
+    // postgres is often still starting when the api comes up, so retry before giving up
+    pub async fn connect(&self, database_url: &str) -> anyhow::Result<PgPool> {
+        let mut options = PgConnectOptions::from_str(database_url)?;
+        if let Some(timeout) = self.statement_timeout {
+            options = options.options([("statement_timeout", timeout.as_millis())]);
+        }
+        let pool_options = PgPoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(self.acquire_timeout)
+            .idle_timeout(self.idle_timeout);
+
+        let mut attempt = 1;
+        loop {
+            // a single connection fails fast with the real cause, the pool would only time out
+            let connected = match options.connect().await {
+                Ok(conn) => {
+                    let _ = conn.close().await;
+                    pool_options.clone().connect_with(options.clone()).await
+                }
+                Err(e) => Err(e),
+            };
+            match connected {
+                Ok(pool) => {
+                    tracing::info!("connected to the database on attempt {}", attempt);
+                    return Ok(pool);
+                }
+                Err(e) if attempt < self.connect_attempts => {
+                    let delay = self.backoff(attempt);
+                    tracing::warn!(
+                        "database not reachable (attempt {}/{}), retrying in {:?}: {}",
+                        attempt,
+                        self.connect_attempts,
+                        delay,
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("gave up connecting after {} attempts", attempt))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dotenv::dotenv;
+
+    #[test]
+    fn test_backoff() {
+        let config = PoolConfig::default();
+        let delays = (1..=8).map(|attempt| config.backoff(attempt).as_millis());
+        assert_eq!(
+            delays.collect::<Vec<_>>(),
+            vec![500, 1000, 2000, 4000, 8000, 16000, 30000, 30000]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect() -> anyhow::Result<()> {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL")?;
+        let config = PoolConfig {
+            max_connections: 2,
+            statement_timeout: Some(Duration::from_millis(1500)),
+            ..Default::default()
+        };
+        let pool = config.connect(&database_url).await?;
+        let (timeout,): (String,) = sqlx::query_as("show statement_timeout")
+            .fetch_one(&pool)
+            .await?;
+        assert_eq!(timeout, "1500ms");
+        assert_eq!(pool.options().get_max_connections(), 2);
+
+        // nothing listens on port 1
+        let config = PoolConfig {
+            connect_attempts: 2,
+            backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let err = config
+            .connect("postgres://admin@localhost:1/showerrooms")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("2 attempts"));
+
+        Ok(())
+    }
+}
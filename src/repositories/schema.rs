@@ -0,0 +1,43 @@
+use crate::repositories::section::models::{SchemaStatus, SchemaVersion};
+use sqlx::migrate::{Migrate, Migrator};
+use std::collections::BTreeSet;
+
+// fails when the database has a migration this binary does not know, an older binary
+// must not run against a schema a newer one already changed. only reads: without the
+// migrations table (`migrated` false) nothing was applied yet and the table is not created
+pub async fn schema_version<C: Migrate>(
+    conn: &mut C,
+    migrator: &Migrator,
+    migrated: bool,
+) -> anyhow::Result<SchemaVersion> {
+    let applied = match migrated {
+        true => conn.list_applied_migrations().await?,
+        false => vec![],
+    };
+    let embedded = migrator
+        .iter()
+        .map(|migration| migration.version)
+        .collect::<BTreeSet<_>>();
+    if let Some(unknown) = applied
+        .iter()
+        .find(|migration| !embedded.contains(&migration.version))
+    {
+        anyhow::bail!(
+            "the database schema is ahead of this binary, migration {} is unknown",
+            unknown.version
+        );
+    }
+
+    let pending = embedded.len() - applied.len();
+    let status = match (migrated, pending) {
+        (false, _) => SchemaStatus::Unmigrated,
+        (true, 0) => SchemaStatus::Current,
+        (true, _) => SchemaStatus::Pending,
+    };
+    Ok(SchemaVersion {
+        status,
+        version: applied.iter().map(|migration| migration.version).max(),
+        latest: embedded.iter().max().copied().unwrap_or_default(),
+        pending,
+    })
+}
@@ -0,0 +1,437 @@
+use crate::repositories::client;
+use crate::repositories::events::models::EventFilter;
+use crate::repositories::events::traits::EventTrait;
+use crate::repositories::section::models::{
+    CacheStats, CreateSection, SchemaVersion, Section, SectionInfo, UpdateSection,
+};
+use crate::repositories::section::traits::{SectionRepository, SectionTransaction};
+use axum::async_trait;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum CacheKey {
+    Id(i32),
+    Gender(String, String, String),
+    Building(String, String, String, String),
+    Floor(String, String, String, String, i32),
+    All,
+    Archived,
+}
+
+#[derive(Debug, Default)]
+struct SectionCache {
+    entries: RwLock<HashMap<CacheKey, (Instant, Vec<Section>)>>,
+    // bumped on every invalidation so a read that raced a write does not store stale rows
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl SectionCache {
+    fn invalidate(&self) {
+        let mut entries = self.entries.write().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        entries.clear();
+    }
+}
+
+// hit and miss counters of a cache, kept by the app state for GET /cache/stats
+#[derive(Clone, Debug)]
+pub struct SectionCacheStats(Arc<SectionCache>);
+
+impl SectionCacheStats {
+    pub fn snapshot(&self) -> CacheStats {
+        CacheStats {
+            hits: self.0.hits.load(Ordering::Relaxed),
+            misses: self.0.misses.load(Ordering::Relaxed),
+            entries: self.0.entries.read().unwrap().len(),
+        }
+    }
+}
+
+// nothing is visible to readers before commit, so only a commit needs to invalidate
+pub struct CachedSectionTransaction {
+    inner: Box<dyn SectionTransaction>,
+    cache: Arc<SectionCache>,
+}
+
+#[async_trait]
+impl SectionTransaction for CachedSectionTransaction {
+    async fn find_by_id(&mut self, id: i32) -> anyhow::Result<Section> {
+        self.inner.find_by_id(id).await
+    }
+
+    async fn create(
+        &mut self,
+        section: CreateSection,
+        info: SectionInfo,
+    ) -> anyhow::Result<Section> {
+        self.inner.create(section, info).await
+    }
+
+    async fn update(&mut self, section: UpdateSection) -> anyhow::Result<Section> {
+        self.inner.update(section).await
+    }
+
+    async fn update_capacity(&mut self, id: i32, total: i32) -> anyhow::Result<Section> {
+        self.inner.update_capacity(id, total).await
+    }
+
+    async fn delete(&mut self, id: i32) -> anyhow::Result<()> {
+        self.inner.delete(id).await
+    }
+
+    async fn restore(&mut self, id: i32) -> anyhow::Result<Section> {
+        self.inner.restore(id).await
+    }
+
+    async fn commit(self: Box<Self>) -> anyhow::Result<()> {
+        let result = self.inner.commit().await;
+        self.cache.invalidate();
+        result
+    }
+
+    async fn rollback(self: Box<Self>) -> anyhow::Result<()> {
+        self.inner.rollback().await
+    }
+}
+
+// read-through cache, any write or bus event drops every entry since the table is small.
+// entries are loaded from the primary when reads otherwise go to a replica
+#[derive(Clone, Debug)]
+pub struct CachedSectionRepository<R: SectionRepository> {
+    inner: R,
+    cache: Arc<SectionCache>,
+    ttl: Option<Duration>,
+}
+
+impl<R: SectionRepository> CachedSectionRepository<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            cache: Arc::default(),
+            ttl: None,
+        }
+    }
+
+    // entries expire after the ttl, for the writes of replicas whose events never reach us
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        Self {
+            ttl: Some(ttl),
+            ..self
+        }
+    }
+
+    pub fn invalidate(&self) {
+        self.cache.invalidate();
+    }
+
+    pub fn stats(&self) -> SectionCacheStats {
+        SectionCacheStats(Arc::clone(&self.cache))
+    }
+
+    // other replicas write to the same database, their changes reach us through the bus
+    pub async fn invalidate_on(self, events: Arc<dyn EventTrait>) -> anyhow::Result<()> {
+        let mut subscription = events.subscribe(None, EventFilter::default()).await?;
+        while let Some(event) = subscription.recv().await {
+            if !event.kind().starts_with("alert.") {
+                self.invalidate();
+            }
+        }
+        Ok(())
+    }
+
+    async fn cached<F>(&self, key: CacheKey, load: F) -> anyhow::Result<Vec<Section>>
+    where
+        F: Future<Output = anyhow::Result<Vec<Section>>>,
+    {
+        if let Some((loaded_at, sections)) = self.cache.entries.read().unwrap().get(&key) {
+            let fresh = match self.ttl {
+                Some(ttl) => loaded_at.elapsed() < ttl,
+                None => true,
+            };
+            if fresh {
+                self.cache.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(sections.clone());
+            }
+        }
+        self.cache.misses.fetch_add(1, Ordering::Relaxed);
+
+        let generation = self.cache.generation.load(Ordering::SeqCst);
+        // a replica that lags behind would leave a stale entry until the next write
+        let sections = client::on_primary(load).await?;
+        // nothing lives at an unknown site or location, and keeping those answers would let
+        // any path grow the map until the next write
+        if sections.is_empty() {
+            return Ok(sections);
+        }
+        let mut entries = self.cache.entries.write().unwrap();
+        if self.cache.generation.load(Ordering::SeqCst) == generation {
+            entries.insert(key, (Instant::now(), sections.clone()));
+        }
+        Ok(sections)
+    }
+}
+
+#[async_trait]
+impl<R: SectionRepository> SectionRepository for CachedSectionRepository<R> {
+    async fn find_by_id(&self, id: i32) -> anyhow::Result<Section> {
+        let sections = self
+            .cached(CacheKey::Id(id), async {
+                Ok(vec![self.inner.find_by_id(id).await?])
+            })
+            .await?;
+        Ok(sections[0].clone())
+    }
+
+    async fn find_by_gender(
+        &self,
+        site: String,
+        facility_type: String,
+        gender: String,
+    ) -> anyhow::Result<Vec<Section>> {
+        self.cached(
+            CacheKey::Gender(site.clone(), facility_type.clone(), gender.clone()),
+            self.inner.find_by_gender(site, facility_type, gender),
+        )
+        .await
+    }
+
+    async fn find_by_building(
+        &self,
+        site: String,
+        facility_type: String,
+        gender: String,
+        building: String,
+    ) -> anyhow::Result<Vec<Section>> {
+        self.cached(
+            CacheKey::Building(
+                site.clone(),
+                facility_type.clone(),
+                gender.clone(),
+                building.clone(),
+            ),
+            self.inner
+                .find_by_building(site, facility_type, gender, building),
+        )
+        .await
+    }
+
+    async fn find_by_floor(
+        &self,
+        site: String,
+        facility_type: String,
+        gender: String,
+        building: String,
+        floor: i32,
+    ) -> anyhow::Result<Vec<Section>> {
+        self.cached(
+            CacheKey::Floor(
+                site.clone(),
+                facility_type.clone(),
+                gender.clone(),
+                building.clone(),
+                floor,
+            ),
+            self.inner
+                .find_by_floor(site, facility_type, gender, building, floor),
+        )
+        .await
+    }
+
+    async fn find_all(&self) -> anyhow::Result<Vec<Section>> {
+        self.cached(CacheKey::All, self.inner.find_all()).await
+    }
+
+    async fn find_archived(&self) -> anyhow::Result<Vec<Section>> {
+        self.cached(CacheKey::Archived, self.inner.find_archived())
+            .await
+    }
+
+    async fn create(&self, section: CreateSection, info: SectionInfo) -> anyhow::Result<Section> {
+        let result = self.inner.create(section, info).await;
+        self.invalidate();
+        result
+    }
+
+    async fn update(&self, section: UpdateSection) -> anyhow::Result<Section> {
+        let result = self.inner.update(section).await;
+        self.invalidate();
+        result
+    }
+
+    async fn update_capacity(&self, id: i32, total: i32) -> anyhow::Result<Section> {
+        let result = self.inner.update_capacity(id, total).await;
+        self.invalidate();
+        result
+    }
+
+    async fn delete(&self, id: i32) -> anyhow::Result<()> {
+        let result = self.inner.delete(id).await;
+        self.invalidate();
+        result
+    }
+
+    async fn restore(&self, id: i32) -> anyhow::Result<Section> {
+        let result = self.inner.restore(id).await;
+        self.invalidate();
+        result
+    }
+
+    async fn begin(&self) -> anyhow::Result<Box<dyn SectionTransaction>> {
+        Ok(Box::new(CachedSectionTransaction {
+            inner: self.inner.begin().await?,
+            cache: self.cache.clone(),
+        }))
+    }
+
+    fn has_outbox(&self) -> bool {
+        self.inner.has_outbox()
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        self.inner.ping().await
+    }
+
+    async fn schema_version(&self) -> anyhow::Result<Option<SchemaVersion>> {
+        self.inner.schema_version().await
+    }
+}
+
+#[cfg(all(test, feature = "in-memory"))]
+mod tests {
+    use super::*;
+    use crate::repositories::events::models::Events;
+    use crate::repositories::section::contract;
+    use crate::repositories::section::in_memory::InMemorySectionRepository;
+    use crate::repositories::section::models::{DEFAULT_FACILITY, DEFAULT_SITE};
+    use std::time::Duration;
+
+    fn stats<R: SectionRepository>(repository: &CachedSectionRepository<R>) -> (u64, u64) {
+        let stats = repository.stats().snapshot();
+        (stats.hits, stats.misses)
+    }
+
+    #[tokio::test]
+    async fn test_contract() -> anyhow::Result<()> {
+        let repository = CachedSectionRepository::new(InMemorySectionRepository::seeded());
+        contract::find(&repository).await?;
+        contract::update(&repository).await?;
+        contract::transaction(&repository).await?;
+        contract::archive(&repository).await
+    }
+
+    #[tokio::test]
+    async fn test_hits_and_invalidation() {
+        let repository = CachedSectionRepository::new(InMemorySectionRepository::seeded());
+
+        let sections = repository
+            .find_by_gender(
+                DEFAULT_SITE.to_string(),
+                DEFAULT_FACILITY.to_string(),
+                "male".to_string(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            repository
+                .find_by_gender(
+                    DEFAULT_SITE.to_string(),
+                    DEFAULT_FACILITY.to_string(),
+                    "male".to_string()
+                )
+                .await
+                .unwrap(),
+            sections
+        );
+        assert_eq!(stats(&repository), (1, 1));
+        // errors are not cached
+        assert!(repository.find_by_id(100).await.is_err());
+        assert!(repository.find_by_id(100).await.is_err());
+        assert_eq!(stats(&repository), (1, 3));
+
+        let updated = repository
+            .update(UpdateSection {
+                id: sections[0].id,
+                current_status: "available".to_string(),
+                next_status: "occupied".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(repository.stats().snapshot().entries, 0);
+        assert_eq!(
+            repository
+                .find_by_gender(
+                    DEFAULT_SITE.to_string(),
+                    DEFAULT_FACILITY.to_string(),
+                    "male".to_string()
+                )
+                .await
+                .unwrap()[0],
+            updated
+        );
+        assert_eq!(stats(&repository), (1, 4));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_locations() {
+        let repository = CachedSectionRepository::new(InMemorySectionRepository::seeded());
+        for site in ["nowhere", "elsewhere"] {
+            let sections = repository
+                .find_by_gender(
+                    site.to_string(),
+                    DEFAULT_FACILITY.to_string(),
+                    "male".to_string(),
+                )
+                .await
+                .unwrap();
+            assert!(sections.is_empty());
+        }
+        assert_eq!(repository.stats().snapshot().entries, 0);
+    }
+
+    #[tokio::test]
+    async fn test_ttl() {
+        let inner = InMemorySectionRepository::seeded();
+        let repository =
+            CachedSectionRepository::new(inner.clone()).with_ttl(Duration::from_millis(50));
+
+        let section = repository.find_by_id(1).await.unwrap();
+        // a write that only another replica saw, and whose event never arrives
+        let updated = inner.update_capacity(1, section.total + 1).await.unwrap();
+        assert_eq!(repository.find_by_id(1).await.unwrap(), section);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(repository.find_by_id(1).await.unwrap(), updated);
+        assert_eq!(stats(&repository), (1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_on_events() {
+        let inner = InMemorySectionRepository::seeded();
+        let repository = CachedSectionRepository::new(inner.clone());
+        let events: Arc<dyn EventTrait> = Arc::new(Events::new());
+        tokio::spawn(repository.clone().invalidate_on(events.clone()));
+        while events.subscribers().await == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let section = repository.find_by_id(1).await.unwrap();
+        // a write that only another replica saw
+        let updated = inner.update_capacity(1, section.total + 1).await.unwrap();
+        assert_eq!(repository.find_by_id(1).await.unwrap(), section);
+
+        events.notify("male/A/1".to_string().into()).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while repository.find_by_id(1).await.unwrap() != updated {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
@@ -0,0 +1,519 @@
+// behaviour every SectionRepository backend must share, run against the seeded
+// 2 genders x 3 buildings x 4 floors layout
+use anyhow::Result;
+
+use crate::repositories::section::errors::RepositoryError;
+use crate::repositories::section::models::{
+    CreateSection, SectionInfo, UpdateSection, DEFAULT_FACILITY, DEFAULT_SITE,
+};
+use crate::repositories::section::traits::SectionRepository;
+
+pub async fn find<R: SectionRepository>(repository: &R) -> Result<()> {
+    let section = repository.find_by_id(1).await?;
+    assert_eq!(section.id, 1);
+    assert_eq!(
+        (
+            section.gender.as_str(),
+            section.building.as_str(),
+            section.floor
+        ),
+        ("male", "A", 1)
+    );
+    let err = repository.find_by_id(0).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RepositoryError>(),
+        Some(RepositoryError::NotFound(0))
+    ));
+
+    let sections = repository.find_all().await?;
+    assert_eq!(sections.len(), 24);
+    assert!(sections.windows(2).all(|pair| pair[0].id < pair[1].id));
+
+    let sections = repository
+        .find_by_gender(
+            DEFAULT_SITE.to_string(),
+            DEFAULT_FACILITY.to_string(),
+            "male".to_string(),
+        )
+        .await?;
+    assert_eq!(sections.len(), 12);
+    assert!(sections.iter().all(|section| section.gender == "male"));
+    assert!(sections.windows(2).all(|pair| pair[0].id < pair[1].id));
+
+    let sections = repository
+        .find_by_building(
+            DEFAULT_SITE.to_string(),
+            DEFAULT_FACILITY.to_string(),
+            "female".to_string(),
+            "B".to_string(),
+        )
+        .await?;
+    assert_eq!(sections.len(), 4);
+    assert!(sections
+        .iter()
+        .all(|section| section.gender == "female" && section.building == "B"));
+
+    let sections = repository
+        .find_by_floor(
+            DEFAULT_SITE.to_string(),
+            DEFAULT_FACILITY.to_string(),
+            "female".to_string(),
+            "C".to_string(),
+            3,
+        )
+        .await?;
+    assert_eq!(sections.len(), 1);
+    assert_eq!(sections[0].floor, 3);
+    // a location without a section is not an error, the handlers answer 404 for it
+    assert!(repository
+        .find_by_floor(
+            DEFAULT_SITE.to_string(),
+            DEFAULT_FACILITY.to_string(),
+            "female".to_string(),
+            "C".to_string(),
+            5
+        )
+        .await?
+        .is_empty());
+
+    Ok(())
+}
+
+// leaves male/B/2 as it found it
+pub async fn update<R: SectionRepository>(repository: &R) -> Result<()> {
+    let section = repository
+        .find_by_floor(
+            DEFAULT_SITE.to_string(),
+            DEFAULT_FACILITY.to_string(),
+            "male".to_string(),
+            "B".to_string(),
+            2,
+        )
+        .await?[0]
+        .clone();
+
+    for (current_status, next_status) in [
+        ("available", "occupied"),
+        ("occupied", "disabled"),
+        ("disabled", "available"),
+    ] {
+        let old_section = repository.find_by_id(section.id).await?;
+        let updated_section = repository
+            .update(UpdateSection {
+                id: section.id,
+                current_status: current_status.to_string(),
+                next_status: next_status.to_string(),
+            })
+            .await?;
+        assert_eq!(
+            updated_section.total,
+            updated_section.available + updated_section.occupied + updated_section.disabled_rooms
+        );
+        assert_ne!(updated_section, old_section);
+    }
+    assert_eq!(repository.find_by_id(section.id).await?, section);
+
+    assert!(repository
+        .update(UpdateSection {
+            id: section.id,
+            current_status: "available".to_string(),
+            next_status: "broken".to_string(),
+        })
+        .await
+        .is_err());
+    // none of the seeded stalls is disabled, and the counters never go below zero
+    let err = repository
+        .update(UpdateSection {
+            id: section.id,
+            current_status: "disabled".to_string(),
+            next_status: "available".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RepositoryError>(),
+        Some(RepositoryError::InvalidTransition(..))
+    ));
+
+    let updated_section = repository
+        .update_capacity(section.id, section.total + 2)
+        .await?;
+    assert_eq!(updated_section.available, section.available + 2);
+    let err = repository
+        .update_capacity(section.id, section.total - section.available - 1)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RepositoryError>(),
+        Some(RepositoryError::InsufficientCapacity(..))
+    ));
+    let restored_section = repository
+        .update_capacity(section.id, section.total)
+        .await?;
+    assert_eq!(restored_section, section);
+
+    Ok(())
+}
+
+pub async fn transaction<R: SectionRepository>(repository: &R) -> Result<()> {
+    let section = repository
+        .find_by_floor(
+            DEFAULT_SITE.to_string(),
+            DEFAULT_FACILITY.to_string(),
+            "male".to_string(),
+            "C".to_string(),
+            3,
+        )
+        .await?[0]
+        .clone();
+    let occupy = || UpdateSection {
+        id: section.id,
+        current_status: "available".to_string(),
+        next_status: "occupied".to_string(),
+    };
+
+    // rolled back, explicitly or by dropping the handle
+    let mut tx = repository.begin().await?;
+    let updated_section = tx.update(occupy()).await?;
+    assert_eq!(updated_section.occupied, section.occupied + 1);
+    assert_eq!(tx.find_by_id(section.id).await?, updated_section);
+    tx.rollback().await?;
+    assert_eq!(repository.find_by_id(section.id).await?, section);
+
+    let mut tx = repository.begin().await?;
+    tx.update(occupy()).await?;
+    drop(tx);
+    assert_eq!(repository.find_by_id(section.id).await?, section);
+
+    // committed writes land together
+    let mut tx = repository.begin().await?;
+    tx.update(occupy()).await?;
+    let updated_section = tx.update_capacity(section.id, section.total + 1).await?;
+    tx.commit().await?;
+    assert_eq!(repository.find_by_id(section.id).await?, updated_section);
+    assert_eq!(
+        (updated_section.available, updated_section.occupied),
+        (section.available, section.occupied + 1)
+    );
+
+    repository
+        .update(UpdateSection {
+            id: section.id,
+            current_status: "occupied".to_string(),
+            next_status: "available".to_string(),
+        })
+        .await?;
+    let restored_section = repository
+        .update_capacity(section.id, section.total)
+        .await?;
+    assert_eq!(restored_section, section);
+
+    Ok(())
+}
+
+pub async fn duplicate<R: SectionRepository>(repository: &R) -> Result<()> {
+    let info = SectionInfo {
+        site: DEFAULT_SITE.to_string(),
+        facility_type: DEFAULT_FACILITY.to_string(),
+        gender: "female".to_string(),
+        building: "A".to_string(),
+        floor: 1,
+    };
+    let err = repository
+        .create(CreateSection { total: 3 }, info.clone())
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RepositoryError>(),
+        Some(RepositoryError::DuplicateLocation(..))
+    ));
+
+    let sections = repository
+        .find_by_building(
+            DEFAULT_SITE.to_string(),
+            DEFAULT_FACILITY.to_string(),
+            info.gender,
+            info.building,
+        )
+        .await?;
+    assert_eq!(
+        sections.iter().filter(|section| section.floor == 1).count(),
+        1
+    );
+
+    Ok(())
+}
+
+// none of these ever reaches the store, so the shared postgres database can test them as is
+pub async fn invalid<R: SectionRepository>(repository: &R) -> Result<()> {
+    let info = SectionInfo {
+        site: DEFAULT_SITE.to_string(),
+        facility_type: DEFAULT_FACILITY.to_string(),
+        gender: "female".to_string(),
+        building: "D".to_string(),
+        floor: 1,
+    };
+    let invalid = [
+        (
+            SectionInfo {
+                gender: "other".to_string(),
+                ..info.clone()
+            },
+            3,
+        ),
+        (
+            SectionInfo {
+                building: String::new(),
+                ..info.clone()
+            },
+            3,
+        ),
+        (
+            SectionInfo {
+                floor: 0,
+                ..info.clone()
+            },
+            3,
+        ),
+        (info, -1),
+    ];
+    for (info, total) in invalid {
+        let err = repository
+            .create(CreateSection { total }, info)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::InvalidSection(..))
+        ));
+    }
+
+    Ok(())
+}
+
+// archives a seeded section for a while, so it needs a store of its own and the shared
+// postgres database tests it in a rolled back transaction instead
+#[cfg(feature = "in-memory")]
+pub async fn archive<R: SectionRepository>(repository: &R) -> Result<()> {
+    let section = repository
+        .find_by_floor(
+            DEFAULT_SITE.to_string(),
+            DEFAULT_FACILITY.to_string(),
+            "male".to_string(),
+            "B".to_string(),
+            4,
+        )
+        .await?[0]
+        .clone();
+    let info = SectionInfo::from(section.clone());
+    let is_not_found = |err: anyhow::Error| {
+        matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(..))
+        )
+    };
+
+    repository.delete(section.id).await?;
+    assert!(is_not_found(
+        repository.find_by_id(section.id).await.unwrap_err()
+    ));
+    assert!(repository
+        .find_by_floor(
+            DEFAULT_SITE.to_string(),
+            DEFAULT_FACILITY.to_string(),
+            info.gender.clone(),
+            info.building.clone(),
+            info.floor
+        )
+        .await?
+        .is_empty());
+    assert_eq!(repository.find_all().await?.len(), 23);
+    let archived = repository.find_archived().await?;
+    assert_eq!(archived.len(), 1);
+    assert_eq!(archived[0].id, section.id);
+    assert!(archived[0].deleted_at.is_some());
+    // archived sections take no writes
+    assert!(is_not_found(
+        repository.delete(section.id).await.unwrap_err()
+    ));
+    assert!(is_not_found(
+        repository
+            .update_capacity(section.id, section.total)
+            .await
+            .unwrap_err()
+    ));
+
+    assert_eq!(repository.restore(section.id).await?, section);
+    assert!(is_not_found(
+        repository.restore(section.id).await.unwrap_err()
+    ));
+    assert_eq!(repository.find_by_id(section.id).await?, section);
+    assert!(repository.find_archived().await?.is_empty());
+
+    // the location is free while archived, so restoring can collide
+    repository.delete(section.id).await?;
+    let replacement = repository
+        .create(CreateSection { total: 2 }, info.clone())
+        .await?;
+    let err = repository.restore(section.id).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RepositoryError>(),
+        Some(RepositoryError::DuplicateLocation(..))
+    ));
+    repository.delete(replacement.id).await?;
+    assert_eq!(repository.restore(section.id).await?, section);
+
+    Ok(())
+}
+
+// adds sections to a second site, so the shared postgres database runs it in a rolled
+// back transaction
+pub async fn sites<R: SectionRepository>(repository: &R) -> Result<()> {
+    let info = SectionInfo {
+        site: "north".to_string(),
+        facility_type: DEFAULT_FACILITY.to_string(),
+        gender: "male".to_string(),
+        building: "A".to_string(),
+        floor: 1,
+    };
+    let north = repository
+        .create(CreateSection { total: 3 }, info.clone())
+        .await?;
+    assert_eq!(north.site, "north");
+    let main = repository
+        .find_by_floor(
+            DEFAULT_SITE.to_string(),
+            DEFAULT_FACILITY.to_string(),
+            info.gender.clone(),
+            info.building.clone(),
+            1,
+        )
+        .await?;
+    assert_ne!(main[0].id, north.id);
+
+    // every find stays on its site
+    let found = repository
+        .find_by_floor(
+            info.site.clone(),
+            info.facility_type.clone(),
+            info.gender.clone(),
+            info.building.clone(),
+            1,
+        )
+        .await?;
+    assert_eq!(found, vec![north.clone()]);
+    let found = repository
+        .find_by_building(
+            info.site.clone(),
+            info.facility_type.clone(),
+            info.gender.clone(),
+            info.building.clone(),
+        )
+        .await?;
+    assert_eq!(found, vec![north.clone()]);
+    let found = repository
+        .find_by_gender(
+            info.site.clone(),
+            info.facility_type.clone(),
+            "female".to_string(),
+        )
+        .await?;
+    assert!(found.is_empty());
+    let found = repository
+        .find_by_gender(
+            DEFAULT_SITE.to_string(),
+            DEFAULT_FACILITY.to_string(),
+            info.gender.clone(),
+        )
+        .await?;
+    assert!(found.iter().all(|section| section.site == DEFAULT_SITE));
+
+    // the site has buildings and floors the seeded one does not
+    let tower = SectionInfo {
+        building: "Tower".to_string(),
+        floor: 12,
+        ..info.clone()
+    };
+    let section = repository
+        .create(CreateSection { total: 3 }, tower.clone())
+        .await?;
+    assert_eq!(SectionInfo::from(section), tower);
+
+    // last, a failed statement aborts the postgres transaction the test runs in
+    let err = repository
+        .create(CreateSection { total: 3 }, info)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RepositoryError>(),
+        Some(RepositoryError::DuplicateLocation(..))
+    ));
+
+    Ok(())
+}
+
+// adds a laundry section next to a seeded shower one, rolled back like sites
+pub async fn facilities<R: SectionRepository>(repository: &R) -> Result<()> {
+    let info = SectionInfo {
+        site: DEFAULT_SITE.to_string(),
+        facility_type: "laundry".to_string(),
+        gender: "male".to_string(),
+        building: "A".to_string(),
+        floor: 1,
+    };
+    let laundry = repository
+        .create(CreateSection { total: 2 }, info.clone())
+        .await?;
+    assert_eq!(laundry.facility_type, "laundry");
+
+    // the floor keeps its shower section, and every find stays on its facility type
+    let showers = repository
+        .find_by_floor(
+            info.site.clone(),
+            DEFAULT_FACILITY.to_string(),
+            info.gender.clone(),
+            info.building.clone(),
+            1,
+        )
+        .await?;
+    assert_ne!(showers[0].id, laundry.id);
+    let found = repository
+        .find_by_floor(
+            info.site.clone(),
+            info.facility_type.clone(),
+            info.gender.clone(),
+            info.building.clone(),
+            1,
+        )
+        .await?;
+    assert_eq!(found, vec![laundry.clone()]);
+    let found = repository
+        .find_by_gender(
+            info.site.clone(),
+            info.facility_type.clone(),
+            info.gender.clone(),
+        )
+        .await?;
+    assert_eq!(found, vec![laundry]);
+    let found = repository
+        .find_by_gender(
+            info.site.clone(),
+            DEFAULT_FACILITY.to_string(),
+            info.gender.clone(),
+        )
+        .await?;
+    assert!(found
+        .iter()
+        .all(|section| section.facility_type == DEFAULT_FACILITY));
+
+    let err = repository
+        .create(CreateSection { total: 2 }, info)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RepositoryError>(),
+        Some(RepositoryError::DuplicateLocation(..))
+    ));
+
+    Ok(())
+}
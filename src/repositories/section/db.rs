@@ -1,123 +1,441 @@
-use crate::repositories::section::models::{CreateSection, Section, SectionInfo, UpdateSection};
-use crate::repositories::section::traits::SectionRepository;
+use crate::repositories::client::{self, RecentWrites};
+use crate::repositories::events::models::{
+    EventMessage, SECTION_CAPACITY_CHANGED, SECTION_CREATED, SECTION_DELETED, SECTION_RESTORED,
+};
+use crate::repositories::schema::schema_version;
+use crate::repositories::section::errors::RepositoryError;
+use crate::repositories::section::models::{
+    CreateSection, SchemaVersion, Section, SectionInfo, UpdateSection,
+};
+use crate::repositories::section::traits::{SectionRepository, SectionTransaction};
 use axum::async_trait;
-use sqlx::PgPool;
+use chrono::Utc;
+use sqlx::migrate::Migrator;
+use sqlx::{PgConnection, PgExecutor, PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::utils::{
+    capacity_conflict, location_conflict, not_found, query_switch_usage, transition_conflict,
+};
+
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+// the outbox row commits or rolls back together with the change it announces
+async fn enqueue(conn: &mut PgConnection, message: EventMessage) -> anyhow::Result<()> {
+    sqlx::query("insert into outbox (payload) values ($1)")
+        .bind(serde_json::to_string(&message)?)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+// the writes below are shared by the repository and its transactions, each must run
+// inside a transaction so the section and its outbox row commit together
+async fn find_section(conn: &mut PgConnection, id: i32) -> anyhow::Result<Section> {
+    let section =
+        sqlx::query_as::<_, Section>("SELECT * FROM sections WHERE id = $1 AND deleted_at IS NULL")
+            .bind(id)
+            .fetch_one(conn)
+            .await
+            .map_err(|e| not_found(e, id))?;
+    Ok(section)
+}
+
+async fn find_archived_section(conn: &mut PgConnection, id: i32) -> anyhow::Result<Section> {
+    let section = sqlx::query_as::<_, Section>(
+        "SELECT * FROM sections WHERE id = $1 AND deleted_at IS NOT NULL",
+    )
+    .bind(id)
+    .fetch_optional(conn)
+    .await?
+    .ok_or(RepositoryError::NotFound(id))?;
+    Ok(section)
+}
+
+// the reads run on the pool of the repository, or on a transaction in the tests
+async fn find_by_gender<'c>(
+    executor: impl PgExecutor<'c>,
+    site: String,
+    facility_type: String,
+    gender: String,
+) -> anyhow::Result<Vec<Section>> {
+    let sections = sqlx::query_as::<_, Section>(
+        "SELECT * FROM sections WHERE site = $1 AND facility_type = $2 AND gender = $3 AND deleted_at IS NULL order by id asc",
+    )
+    .bind(site)
+    .bind(facility_type)
+    .bind(gender)
+    .fetch_all(executor)
+    .await?;
+    Ok(sections)
+}
+
+async fn find_by_building<'c>(
+    executor: impl PgExecutor<'c>,
+    site: String,
+    facility_type: String,
+    gender: String,
+    building: String,
+) -> anyhow::Result<Vec<Section>> {
+    let sections = sqlx::query_as::<_, Section>(
+        "SELECT * FROM sections WHERE site = $1 AND facility_type = $2 AND gender = $3 AND building = $4 AND deleted_at IS NULL order by id asc",
+    )
+    .bind(site)
+    .bind(facility_type)
+    .bind(gender)
+    .bind(building)
+    .fetch_all(executor)
+    .await?;
+    Ok(sections)
+}
+
+async fn find_by_floor<'c>(
+    executor: impl PgExecutor<'c>,
+    info: SectionInfo,
+) -> anyhow::Result<Vec<Section>> {
+    let sections = sqlx::query_as::<_, Section>(
+        "SELECT * FROM sections WHERE site = $1 AND facility_type = $2 AND gender = $3 AND building = $4 AND floor = $5 AND deleted_at IS NULL",
+    )
+    .bind(info.site)
+    .bind(info.facility_type)
+    .bind(info.gender)
+    .bind(info.building)
+    .bind(info.floor)
+    .fetch_all(executor)
+    .await?;
+    Ok(sections)
+}
+
+// the live sections, or the archived ones
+async fn find_all<'c>(
+    executor: impl PgExecutor<'c>,
+    archived: bool,
+) -> anyhow::Result<Vec<Section>> {
+    let sections = sqlx::query_as::<_, Section>(
+        "SELECT * FROM sections WHERE (deleted_at IS NOT NULL) = $1 order by id asc",
+    )
+    .bind(archived)
+    .fetch_all(executor)
+    .await?;
+    Ok(sections)
+}
+
+async fn create_section(
+    conn: &mut PgConnection,
+    section: CreateSection,
+    info: SectionInfo,
+) -> anyhow::Result<Section> {
+    let section = sqlx::query_as::<_, Section>(
+        "insert into sections (building, floor, gender, total, available, occupied, disabled_rooms, site, facility_type) values ($1, $2, $3, $4, $4, 0, 0, $5, $6) returning *"
+    )
+    .bind(&info.building)
+    .bind(info.floor)
+    .bind(&info.gender)
+    .bind(section.total)
+    .bind(&info.site)
+    .bind(&info.facility_type)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| location_conflict(e, &info))?;
+    enqueue(conn, EventMessage::section(SECTION_CREATED, &section)).await?;
+    Ok(section)
+}
+
+async fn update_section(
+    conn: &mut PgConnection,
+    section: UpdateSection,
+) -> anyhow::Result<Section> {
+    let query = query_switch_usage(section.current_status.clone(), section.next_status.clone())?;
+    let section = sqlx::query_as::<_, Section>(query)
+        .bind(section.id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| transition_conflict(e, &section))?;
+    enqueue(conn, EventMessage::updated(&section)).await?;
+    Ok(section)
+}
+
+async fn update_section_capacity(
+    conn: &mut PgConnection,
+    id: i32,
+    total: i32,
+) -> anyhow::Result<Section> {
+    // stalls are added or removed as available ones, the check constraints reject the rest
+    let section = sqlx::query_as::<_, Section>(
+        "update sections set available = available + ($2 - total), total = $2 where id = $1 and deleted_at is null returning *",
+    )
+    .bind(id)
+    .bind(total)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| capacity_conflict(e, id, total))?;
+    enqueue(
+        conn,
+        EventMessage::section(SECTION_CAPACITY_CHANGED, &section),
+    )
+    .await?;
+    Ok(section)
+}
 
-use super::utils::query_switch_usage;
+// the row stays for usage_history, only deleted_at marks it archived
+async fn delete_section(conn: &mut PgConnection, id: i32) -> anyhow::Result<()> {
+    let section = sqlx::query_as::<_, Section>(
+        "update sections set deleted_at = $2 where id = $1 and deleted_at is null returning *",
+    )
+    .bind(id)
+    .bind(Utc::now())
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| not_found(e, id))?;
+    enqueue(conn, EventMessage::section(SECTION_DELETED, &section)).await?;
+    Ok(())
+}
+
+async fn restore_section(conn: &mut PgConnection, id: i32) -> anyhow::Result<Section> {
+    let archived = find_archived_section(&mut *conn, id).await?;
+    let section = sqlx::query_as::<_, Section>(
+        "update sections set deleted_at = null where id = $1 returning *",
+    )
+    .bind(id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| location_conflict(e, &archived.into()))?;
+    enqueue(conn, EventMessage::section(SECTION_RESTORED, &section)).await?;
+    Ok(section)
+}
 
+// writes and transactions always go to the primary, reads to the replica when there is one
 #[derive(Clone, Debug)]
 pub struct DBSectionRepository {
     pool: PgPool,
+    read_pool: Option<PgPool>,
+    recent_writes: Option<Arc<RecentWrites>>,
 }
 
 impl DBSectionRepository {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            read_pool: None,
+            recent_writes: None,
+        }
+    }
+
+    pub fn with_read_pool(self, read_pool: PgPool) -> Self {
+        Self {
+            read_pool: Some(read_pool),
+            ..self
+        }
+    }
+
+    // a client that just wrote reads from the primary for the window, so it sees its write
+    pub fn with_read_your_writes(self, window: Duration) -> Self {
+        Self {
+            recent_writes: Some(Arc::new(RecentWrites::new(window))),
+            ..self
+        }
+    }
+
+    fn reader(&self) -> &PgPool {
+        match &self.read_pool {
+            Some(read_pool) if !self.needs_primary() => read_pool,
+            _ => &self.pool,
+        }
+    }
+
+    // a cache fill, or a client that just wrote and must see its write
+    fn needs_primary(&self) -> bool {
+        client::is_on_primary()
+            || self
+                .recent_writes
+                .as_ref()
+                .is_some_and(|recent_writes| recent_writes.is_recent())
+    }
+
+    fn record_write(&self) {
+        if let Some(recent_writes) = &self.recent_writes {
+            recent_writes.record();
+        }
+    }
+}
+
+pub struct DBSectionTransaction {
+    tx: Transaction<'static, Postgres>,
+    repository: DBSectionRepository,
+}
+
+#[async_trait]
+impl SectionTransaction for DBSectionTransaction {
+    async fn find_by_id(&mut self, id: i32) -> anyhow::Result<Section> {
+        find_section(&mut self.tx, id).await
+    }
+
+    async fn create(
+        &mut self,
+        section: CreateSection,
+        info: SectionInfo,
+    ) -> anyhow::Result<Section> {
+        create_section(&mut self.tx, section, info).await
+    }
+
+    async fn update(&mut self, section: UpdateSection) -> anyhow::Result<Section> {
+        update_section(&mut self.tx, section).await
+    }
+
+    async fn update_capacity(&mut self, id: i32, total: i32) -> anyhow::Result<Section> {
+        update_section_capacity(&mut self.tx, id, total).await
+    }
+
+    async fn delete(&mut self, id: i32) -> anyhow::Result<()> {
+        delete_section(&mut self.tx, id).await
+    }
+
+    async fn restore(&mut self, id: i32) -> anyhow::Result<Section> {
+        restore_section(&mut self.tx, id).await
+    }
+
+    async fn commit(self: Box<Self>) -> anyhow::Result<()> {
+        self.tx.commit().await?;
+        self.repository.record_write();
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> anyhow::Result<()> {
+        self.tx.rollback().await?;
+        Ok(())
     }
 }
 
 #[async_trait]
 impl SectionRepository for DBSectionRepository {
-    // use todo!()
     async fn find_by_id(&self, id: i32) -> anyhow::Result<Section> {
-        let section = sqlx::query_as::<_, Section>("SELECT * FROM sections WHERE id = $1")
-            .bind(id)
-            .fetch_one(&self.pool)
-            .await?;
-        Ok(section)
+        let mut conn = self.reader().acquire().await?;
+        find_section(&mut conn, id).await
     }
 
-    async fn find_by_gender(&self, gender: String) -> anyhow::Result<Vec<Section>> {
-        let sections = sqlx::query_as::<_, Section>(
-            "SELECT * FROM sections WHERE gender = $1 order by id asc",
-        )
-        .bind(gender)
-        .fetch_all(&self.pool)
-        .await?;
-        Ok(sections)
+    async fn find_by_gender(
+        &self,
+        site: String,
+        facility_type: String,
+        gender: String,
+    ) -> anyhow::Result<Vec<Section>> {
+        find_by_gender(self.reader(), site, facility_type, gender).await
     }
 
     async fn find_by_building(
         &self,
+        site: String,
+        facility_type: String,
         gender: String,
         building: String,
     ) -> anyhow::Result<Vec<Section>> {
-        let sections = sqlx::query_as::<_, Section>(
-            "SELECT * FROM sections WHERE gender = $1 AND building = $2 order by id asc",
-        )
-        .bind(gender)
-        .bind(building)
-        .fetch_all(&self.pool)
-        .await?;
-        Ok(sections)
+        find_by_building(self.reader(), site, facility_type, gender, building).await
     }
 
     async fn find_by_floor(
         &self,
+        site: String,
+        facility_type: String,
         gender: String,
         building: String,
         floor: i32,
     ) -> anyhow::Result<Vec<Section>> {
-        let sections = sqlx::query_as::<_, Section>(
-            "SELECT * FROM sections WHERE gender = $1 AND building = $2 AND floor = $3",
-        )
-        .bind(gender)
-        .bind(building)
-        .bind(floor)
-        .fetch_one(&self.pool)
-        .await?;
-        Ok(vec![sections])
+        let info = SectionInfo {
+            site,
+            facility_type,
+            gender,
+            building,
+            floor,
+        };
+        find_by_floor(self.reader(), info).await
     }
 
     async fn find_all(&self) -> anyhow::Result<Vec<Section>> {
-        let sections = sqlx::query_as::<_, Section>("SELECT * FROM sections order by id asc")
-            .fetch_all(&self.pool)
-            .await?;
-        Ok(sections)
+        find_all(self.reader(), false).await
     }
 
-    async fn create(&self, section: CreateSection, info: SectionInfo) -> anyhow::Result<Section> {
-        let section = sqlx::query_as::<_, Section>(
-            "insert into sections (building, floor, gender, total, available, occupied, disabled_rooms) values ($1, $2, $3, $4, $4, 0, 0) returning *)"
-        )
-        .bind(info.building)
-        .bind(info.floor)
-        .bind(info.gender)
-        .bind(section.total)
-        .fetch_one(&self.pool)
-        .await?;
+    async fn find_archived(&self) -> anyhow::Result<Vec<Section>> {
+        find_all(self.reader(), true).await
+    }
 
-        let section = self.find_by_id(section.id).await?;
+    async fn create(&self, section: CreateSection, info: SectionInfo) -> anyhow::Result<Section> {
+        let mut tx = self.pool.begin().await?;
+        let section = create_section(&mut tx, section, info).await?;
+        tx.commit().await?;
+        self.record_write();
         Ok(section)
     }
 
     async fn update(&self, section: UpdateSection) -> anyhow::Result<Section> {
-        let query = query_switch_usage(section.current_status, section.next_status).unwrap();
-        let section = sqlx::query_as::<_, Section>(query)
-            .bind(section.id)
-            .fetch_one(&self.pool)
-            .await?;
+        let mut tx = self.pool.begin().await?;
+        let section = update_section(&mut tx, section).await?;
+        tx.commit().await?;
+        self.record_write();
+        Ok(section)
+    }
 
-        let section = self.find_by_id(section.id).await?;
+    async fn update_capacity(&self, id: i32, total: i32) -> anyhow::Result<Section> {
+        let mut tx = self.pool.begin().await?;
+        let section = update_section_capacity(&mut tx, id, total).await?;
+        tx.commit().await?;
+        self.record_write();
         Ok(section)
     }
 
     async fn delete(&self, id: i32) -> anyhow::Result<()> {
-        let _ = sqlx::query_as::<_, Section>("delete from sections where id = $1")
-            .bind(id)
-            .fetch_one(&self.pool)
-            .await?;
+        let mut tx = self.pool.begin().await?;
+        delete_section(&mut tx, id).await?;
+        tx.commit().await?;
+        self.record_write();
+        Ok(())
+    }
+
+    async fn restore(&self, id: i32) -> anyhow::Result<Section> {
+        let mut tx = self.pool.begin().await?;
+        let section = restore_section(&mut tx, id).await?;
+        tx.commit().await?;
+        self.record_write();
+        Ok(section)
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        sqlx::query("select 1").execute(&self.pool).await?;
         Ok(())
     }
+
+    async fn schema_version(&self) -> anyhow::Result<Option<SchemaVersion>> {
+        let mut conn = self.pool.acquire().await?;
+        let (migrated,) =
+            sqlx::query_as::<_, (bool,)>("select to_regclass('_sqlx_migrations') is not null")
+                .fetch_one(&mut *conn)
+                .await?;
+        Ok(Some(schema_version(&mut *conn, &MIGRATOR, migrated).await?))
+    }
+
+    async fn begin(&self) -> anyhow::Result<Box<dyn SectionTransaction>> {
+        let tx = self.pool.begin().await?;
+        Ok(Box::new(DBSectionTransaction {
+            tx,
+            repository: self.clone(),
+        }))
+    }
+
+    fn has_outbox(&self) -> bool {
+        true
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::repositories::section::models::UpdateSection;
+    use crate::repositories::section::cached::CachedSectionRepository;
+    use crate::repositories::section::contract;
+    use crate::repositories::section::models::{UpdateSection, DEFAULT_FACILITY, DEFAULT_SITE};
     use crate::repositories::section::traits::SectionRepository;
     use anyhow::Result;
     use dotenv::dotenv;
+    use sqlx::postgres::PgPoolOptions;
     use sqlx::PgPool;
     use std::env;
 
@@ -129,6 +447,143 @@ mod tests {
         Ok(repository)
     }
 
+    #[tokio::test]
+    async fn test_contract_find() -> Result<()> {
+        contract::find(&setup().await?).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_update() -> Result<()> {
+        contract::update(&setup().await?).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_transaction() -> Result<()> {
+        contract::transaction(&setup().await?).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_duplicate() -> Result<()> {
+        contract::duplicate(&setup().await?).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_invalid() -> Result<()> {
+        contract::invalid(&setup().await?).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_sites() -> Result<()> {
+        let repository = RolledBack::begin(&setup().await?).await?;
+        contract::sites(&repository).await?;
+        repository.rollback().await
+    }
+
+    #[tokio::test]
+    async fn test_contract_facilities() -> Result<()> {
+        let repository = RolledBack::begin(&setup().await?).await?;
+        contract::facilities(&repository).await?;
+        repository.rollback().await
+    }
+
+    // the repository seen from inside one transaction, for the contracts that add sections
+    // the other tests sharing the database must never see
+    #[derive(Clone)]
+    struct RolledBack(Arc<tokio::sync::Mutex<Transaction<'static, Postgres>>>);
+
+    impl RolledBack {
+        async fn begin(repository: &DBSectionRepository) -> Result<Self> {
+            let tx = repository.pool.begin().await?;
+            Ok(Self(Arc::new(tokio::sync::Mutex::new(tx))))
+        }
+
+        async fn rollback(self) -> Result<()> {
+            let tx = Arc::try_unwrap(self.0)
+                .map_err(|_| anyhow::anyhow!("transaction still shared"))?
+                .into_inner();
+            tx.rollback().await?;
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl SectionRepository for RolledBack {
+        async fn find_by_id(&self, id: i32) -> Result<Section> {
+            find_section(&mut **self.0.lock().await, id).await
+        }
+
+        async fn find_by_gender(
+            &self,
+            site: String,
+            facility_type: String,
+            gender: String,
+        ) -> Result<Vec<Section>> {
+            let mut tx = self.0.lock().await;
+            find_by_gender(&mut **tx, site, facility_type, gender).await
+        }
+
+        async fn find_by_building(
+            &self,
+            site: String,
+            facility_type: String,
+            gender: String,
+            building: String,
+        ) -> Result<Vec<Section>> {
+            let mut tx = self.0.lock().await;
+            find_by_building(&mut **tx, site, facility_type, gender, building).await
+        }
+
+        async fn find_by_floor(
+            &self,
+            site: String,
+            facility_type: String,
+            gender: String,
+            building: String,
+            floor: i32,
+        ) -> Result<Vec<Section>> {
+            let info = SectionInfo {
+                site,
+                facility_type,
+                gender,
+                building,
+                floor,
+            };
+            find_by_floor(&mut **self.0.lock().await, info).await
+        }
+
+        async fn find_all(&self) -> Result<Vec<Section>> {
+            find_all(&mut **self.0.lock().await, false).await
+        }
+
+        async fn find_archived(&self) -> Result<Vec<Section>> {
+            find_all(&mut **self.0.lock().await, true).await
+        }
+
+        async fn create(&self, section: CreateSection, info: SectionInfo) -> Result<Section> {
+            create_section(&mut **self.0.lock().await, section, info).await
+        }
+
+        async fn update(&self, section: UpdateSection) -> Result<Section> {
+            update_section(&mut **self.0.lock().await, section).await
+        }
+
+        async fn update_capacity(&self, id: i32, total: i32) -> Result<Section> {
+            update_section_capacity(&mut **self.0.lock().await, id, total).await
+        }
+
+        async fn delete(&self, id: i32) -> Result<()> {
+            delete_section(&mut **self.0.lock().await, id).await
+        }
+
+        async fn restore(&self, id: i32) -> Result<Section> {
+            restore_section(&mut **self.0.lock().await, id).await
+        }
+
+        async fn begin(&self) -> Result<Box<dyn SectionTransaction>> {
+            Err(anyhow::anyhow!("already in a transaction"))
+        }
+    }
+
     #[tokio::test]
     async fn test_find_by_id() -> Result<()> {
         let repository = setup().await?;
@@ -144,7 +599,13 @@ mod tests {
     async fn test_find_by_gender() -> Result<()> {
         let repository = setup().await?;
 
-        let sections = repository.find_by_gender("male".to_string()).await?;
+        let sections = repository
+            .find_by_gender(
+                DEFAULT_SITE.to_string(),
+                DEFAULT_FACILITY.to_string(),
+                "male".to_string(),
+            )
+            .await?;
         // Assert based on your known test data
         assert_eq!(sections[0].gender, "male");
 
@@ -156,7 +617,12 @@ mod tests {
         let repository = setup().await?;
 
         let sections = repository
-            .find_by_building("male".to_string(), "A".to_string())
+            .find_by_building(
+                DEFAULT_SITE.to_string(),
+                DEFAULT_FACILITY.to_string(),
+                "male".to_string(),
+                "A".to_string(),
+            )
             .await?;
         // Assert based on your known test data
         assert_eq!(sections[0].building, "A");
@@ -169,7 +635,13 @@ mod tests {
         let repository = setup().await?;
 
         let sections = repository
-            .find_by_floor("male".to_string(), "A".to_string(), 1)
+            .find_by_floor(
+                DEFAULT_SITE.to_string(),
+                DEFAULT_FACILITY.to_string(),
+                "male".to_string(),
+                "A".to_string(),
+                1,
+            )
             .await?;
         // Assert based on your known test data
         assert_eq!(sections[0].floor, 1);
@@ -177,6 +649,38 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_update_capacity() -> Result<()> {
+        let repository = setup().await?;
+
+        let section = repository
+            .find_by_floor(
+                DEFAULT_SITE.to_string(),
+                DEFAULT_FACILITY.to_string(),
+                "female".to_string(),
+                "A".to_string(),
+                4,
+            )
+            .await?[0]
+            .clone();
+        let updated_section = repository
+            .update_capacity(section.id, section.total + 2)
+            .await?;
+        assert_eq!(updated_section.total, section.total + 2);
+        assert_eq!(updated_section.available, section.available + 2);
+        assert!(repository
+            .update_capacity(section.id, section.total - section.available - 1)
+            .await
+            .is_err());
+
+        let restored_section = repository
+            .update_capacity(section.id, section.total)
+            .await?;
+        assert_eq!(restored_section, section);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_update_available() -> Result<()> {
         let repository = setup().await?;
@@ -219,4 +723,180 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_schema_version() -> Result<()> {
+        let repository = setup().await?;
+        let schema = repository.schema_version().await?.unwrap();
+        assert_eq!(
+            schema.latest,
+            MIGRATOR
+                .iter()
+                .map(|migration| migration.version)
+                .max()
+                .unwrap()
+        );
+        assert!(schema.version.unwrap_or_default() <= schema.latest);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_replica_routing() -> Result<()> {
+        // a replica that never answers shows which reads were routed to it
+        let replica = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(200))
+            .connect_lazy("postgres://admin@localhost:1/showerrooms")?;
+        let primary = setup().await?;
+        let section = primary
+            .find_by_floor(
+                DEFAULT_SITE.to_string(),
+                DEFAULT_FACILITY.to_string(),
+                "male".to_string(),
+                "C".to_string(),
+                4,
+            )
+            .await?[0]
+            .clone();
+        let repository = primary
+            .with_read_pool(replica)
+            .with_read_your_writes(Duration::from_secs(60));
+        assert!(repository.find_by_id(section.id).await.is_err());
+
+        let client = |name: &str| Some(name.to_string());
+        client::scope(client("writer"), async {
+            assert!(repository.find_by_id(section.id).await.is_err());
+            // writes go to the primary, and so do the writer's reads after it
+            repository
+                .update_capacity(section.id, section.total)
+                .await?;
+            assert_eq!(repository.find_by_id(section.id).await?, section);
+            assert!(repository.find_all().await.is_ok());
+            Ok::<_, anyhow::Error>(())
+        })
+        .await?;
+        client::scope(client("reader"), async {
+            assert!(repository.find_by_id(section.id).await.is_err());
+        })
+        .await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cache_over_read_replica() -> Result<()> {
+        // a replica that never answers shows which reads were routed to it
+        let replica = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(200))
+            .connect_lazy("postgres://admin@localhost:1/showerrooms")?;
+        let primary = setup().await?;
+        let section = primary
+            .find_by_floor(
+                DEFAULT_SITE.to_string(),
+                DEFAULT_FACILITY.to_string(),
+                "female".to_string(),
+                "A".to_string(),
+                3,
+            )
+            .await?[0]
+            .clone();
+        let repository = CachedSectionRepository::new(
+            primary
+                .with_read_pool(replica)
+                .with_read_your_writes(Duration::from_secs(60)),
+        );
+
+        let client = |name: &str| Some(name.to_string());
+        client::scope(client("writer"), async {
+            repository
+                .update_capacity(section.id, section.total)
+                .await?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await?;
+        // what other clients fill the cache with comes from the primary, so the writer
+        // is never served an entry from before its write
+        client::scope(client("reader"), async {
+            assert_eq!(repository.find_by_id(section.id).await?, section);
+            Ok::<_, anyhow::Error>(())
+        })
+        .await?;
+        client::scope(client("writer"), async {
+            assert_eq!(repository.find_by_id(section.id).await?, section);
+            Ok::<_, anyhow::Error>(())
+        })
+        .await?;
+        let stats = repository.stats().snapshot();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_archive() -> Result<()> {
+        let repository = setup().await?;
+        let section = repository
+            .find_by_floor(
+                DEFAULT_SITE.to_string(),
+                DEFAULT_FACILITY.to_string(),
+                "female".to_string(),
+                "B".to_string(),
+                4,
+            )
+            .await?[0]
+            .clone();
+
+        // inside a transaction that is rolled back, the other tests never see the archive
+        let mut tx = repository.begin().await?;
+        tx.delete(section.id).await?;
+        assert!(tx.find_by_id(section.id).await.is_err());
+        assert!(tx.delete(section.id).await.is_err());
+        tx.rollback().await?;
+
+        let mut tx = repository.begin().await?;
+        tx.delete(section.id).await?;
+        let restored = tx.restore(section.id).await?;
+        assert_eq!(restored, section);
+        assert!(tx.restore(section.id).await.is_err());
+        tx.rollback().await?;
+
+        let mut tx = repository.begin().await?;
+        tx.delete(section.id).await?;
+        tx.create(CreateSection { total: 2 }, section.clone().into())
+            .await?;
+        let err = tx.restore(section.id).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::DuplicateLocation(..))
+        ));
+        tx.rollback().await?;
+
+        assert_eq!(repository.find_by_id(section.id).await?, section);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_outbox_row() -> Result<()> {
+        let repository = setup().await?;
+        // every event bus is fed from the outbox, so the row is written whatever the bus
+        assert!(repository.has_outbox());
+
+        let section = repository.find_by_id(1).await?;
+        let outbox_rows = "select count(*) from outbox";
+        let mut tx = DBSectionTransaction {
+            tx: repository.pool.begin().await?,
+            repository: repository.clone(),
+        };
+        let (before,) = sqlx::query_as::<_, (i64,)>(outbox_rows)
+            .fetch_one(&mut *tx.tx)
+            .await?;
+        tx.update_capacity(section.id, section.total + 1).await?;
+        let (after,) = sqlx::query_as::<_, (i64,)>(outbox_rows)
+            .fetch_one(&mut *tx.tx)
+            .await?;
+        assert_eq!(after, before + 1);
+        Box::new(tx).rollback().await?;
+
+        Ok(())
+    }
 }
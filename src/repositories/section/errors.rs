@@ -1,7 +1,20 @@
 use thiserror::Error;
 
+use crate::repositories::section::models::SectionInfo;
+
 #[derive(Debug, Error)]
 pub enum RepositoryError {
     #[error("not found id is: {0}")]
     NotFound(i32),
+    #[error("a section already exists at {0}")]
+    DuplicateLocation(SectionInfo),
+    // the location or the counters break the rules of the schema, e.g. a negative total
+    #[error("invalid section: {0}")]
+    InvalidSection(String),
+    // the counters don't allow it, e.g. freeing a stall when none is occupied
+    #[error("no stall is {0}")]
+    InvalidTransition(String),
+    // stalls are only removed while available, the others are in use
+    #[error("a total of {0} can't hold the stalls in use")]
+    InsufficientCapacity(i32),
 }
@@ -1,153 +1,420 @@
 use crate::repositories::section::errors::RepositoryError;
-use crate::repositories::section::models::{CreateSection, Section, SectionInfo, UpdateSection};
-use crate::repositories::section::traits::SectionRepository;
+use crate::repositories::section::models::{
+    CreateSection, Section, SectionInfo, UpdateSection, DEFAULT_FACILITY, DEFAULT_SITE,
+};
+use crate::repositories::section::traits::{SectionRepository, SectionTransaction};
 use crate::repositories::section::utils::inmemory_switch_usage;
-use anyhow::Context;
 use axum::async_trait;
-use std::collections::HashMap;
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicI32, Ordering};
 use std::sync::Arc;
 use std::sync::RwLock;
 use std::sync::RwLockReadGuard;
 use std::sync::RwLockWriteGuard;
 
-type SecctionDatas = HashMap<i32, Section>;
+#[derive(Debug, Clone)]
+enum Write {
+    Insert(Section),
+    Update(UpdateSection),
+    UpdateCapacity(i32, i32),
+    Delete(i32),
+    Restore(i32),
+}
+
+#[derive(Debug, Clone, Default)]
+struct SectionDatas {
+    // keyed by id so every listing comes back in id order like the postgres queries
+    sections: BTreeMap<i32, Section>,
+    // one active section per location
+    locations: BTreeMap<SectionInfo, i32>,
+}
+
+// the check constraints of the sections table, so a section this backend accepts is one the
+// databases accept too
+fn validate(section: &Section) -> Result<(), RepositoryError> {
+    let invalid = if !["male", "female"].contains(&section.gender.as_str()) {
+        format!("gender {} is neither male nor female", section.gender)
+    } else if section.building.is_empty() {
+        "the building has no name".to_string()
+    } else if section.floor < 1 {
+        format!("floor {} is below 1", section.floor)
+    } else if section.available < 0 || section.occupied < 0 || section.disabled_rooms < 0 {
+        format!("a total of {} is negative", section.total)
+    } else {
+        return Ok(());
+    };
+    Err(RepositoryError::InvalidSection(invalid))
+}
+
+impl SectionDatas {
+    // archived sections stay in the map so they can be restored
+    fn active(&self) -> impl Iterator<Item = &Section> {
+        self.sections
+            .values()
+            .filter(|section| section.deleted_at.is_none())
+    }
+
+    fn find(&self, id: i32) -> anyhow::Result<Section> {
+        let section = self
+            .sections
+            .get(&id)
+            .filter(|section| section.deleted_at.is_none())
+            .cloned()
+            .ok_or(RepositoryError::NotFound(id))?;
+        Ok(section)
+    }
+
+    // returns the section as the write left it, or as it was before a delete
+    fn apply(&mut self, write: Write) -> anyhow::Result<Section> {
+        match write {
+            Write::Insert(section) => {
+                validate(&section)?;
+                let location = SectionInfo::from(section.clone());
+                if self.locations.contains_key(&location) {
+                    return Err(RepositoryError::DuplicateLocation(location).into());
+                }
+                self.locations.insert(location, section.id);
+                self.sections.insert(section.id, section.clone());
+                Ok(section)
+            }
+            Write::Update(payload) => {
+                let section = self.find(payload.id)?;
+                let usage = inmemory_switch_usage(
+                    payload.current_status,
+                    payload.next_status,
+                    section.clone(),
+                )?;
+                let section = Section {
+                    available: usage.available,
+                    occupied: usage.occupied,
+                    disabled_rooms: usage.disabled_rooms,
+                    ..section
+                };
+                self.sections.insert(payload.id, section.clone());
+                Ok(section)
+            }
+            Write::UpdateCapacity(id, total) => {
+                let section = self.find(id)?;
+                // stalls are added or removed as available ones
+                let available = section.available + total - section.total;
+                if available < 0 {
+                    return Err(RepositoryError::InsufficientCapacity(total).into());
+                }
+                let section = Section {
+                    total,
+                    available,
+                    ..section
+                };
+                self.sections.insert(id, section.clone());
+                Ok(section)
+            }
+            Write::Delete(id) => {
+                let section = self.find(id)?;
+                self.locations.remove(&section.clone().into());
+                self.sections.insert(
+                    id,
+                    Section {
+                        deleted_at: Some(Utc::now()),
+                        ..section.clone()
+                    },
+                );
+                Ok(section)
+            }
+            Write::Restore(id) => {
+                let section = self
+                    .sections
+                    .get(&id)
+                    .filter(|section| section.deleted_at.is_some())
+                    .ok_or(RepositoryError::NotFound(id))?;
+                let section = Section {
+                    deleted_at: None,
+                    ..section.clone()
+                };
+                // another section may have taken the location in the meantime
+                self.apply(Write::Insert(section))
+            }
+        }
+    }
+}
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct InMemorySectionRepository {
-    pub store: Arc<RwLock<SecctionDatas>>,
+    store: Arc<RwLock<SectionDatas>>,
+    last_id: Arc<AtomicI32>,
 }
 
 impl InMemorySectionRepository {
-    pub fn write_store_ref(&self) -> RwLockWriteGuard<SecctionDatas> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the same sections, in the same id order, as the database migrations seed
+    pub fn seeded() -> Self {
+        let repository = Self::new();
+        {
+            let mut store = repository.write_store_ref();
+            for building in ["A", "B", "C"] {
+                for floor in 1..=4 {
+                    for gender in ["male", "female"] {
+                        let info = SectionInfo {
+                            site: DEFAULT_SITE.to_string(),
+                            facility_type: DEFAULT_FACILITY.to_string(),
+                            gender: gender.to_string(),
+                            building: building.to_string(),
+                            floor,
+                        };
+                        let section = Section::new(repository.next_id(), info, 10);
+                        store.apply(Write::Insert(section)).unwrap();
+                    }
+                }
+            }
+        }
+        repository
+    }
+
+    // like a serial column, an id taken by a failed insert is not handed out again
+    fn next_id(&self) -> i32 {
+        self.last_id.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn write_store_ref(&self) -> RwLockWriteGuard<'_, SectionDatas> {
         self.store.write().unwrap()
     }
 
-    pub fn read_store_ref(&self) -> RwLockReadGuard<SecctionDatas> {
+    fn read_store_ref(&self) -> RwLockReadGuard<'_, SectionDatas> {
         self.store.read().unwrap()
     }
 }
 
+// works on a copy of the store and replays its writes on the live one at commit, so
+// concurrent counter changes are kept like with the relative updates in postgres
+pub struct InMemorySectionTransaction {
+    repository: InMemorySectionRepository,
+    working: SectionDatas,
+    writes: Vec<Write>,
+}
+
+impl InMemorySectionTransaction {
+    fn apply(&mut self, write: Write) -> anyhow::Result<Section> {
+        let section = self.working.apply(write.clone())?;
+        self.writes.push(write);
+        Ok(section)
+    }
+}
+
+#[async_trait]
+impl SectionTransaction for InMemorySectionTransaction {
+    async fn find_by_id(&mut self, id: i32) -> anyhow::Result<Section> {
+        self.working.find(id)
+    }
+
+    async fn create(
+        &mut self,
+        payload: CreateSection,
+        info: SectionInfo,
+    ) -> anyhow::Result<Section> {
+        let id = self.repository.next_id();
+        let section = Section::new(id, info, payload.total);
+        self.apply(Write::Insert(section))
+    }
+
+    async fn update(&mut self, payload: UpdateSection) -> anyhow::Result<Section> {
+        self.apply(Write::Update(payload))
+    }
+
+    async fn update_capacity(&mut self, id: i32, total: i32) -> anyhow::Result<Section> {
+        self.apply(Write::UpdateCapacity(id, total))
+    }
+
+    async fn delete(&mut self, id: i32) -> anyhow::Result<()> {
+        self.apply(Write::Delete(id))?;
+        Ok(())
+    }
+
+    async fn restore(&mut self, id: i32) -> anyhow::Result<Section> {
+        self.apply(Write::Restore(id))
+    }
+
+    async fn commit(self: Box<Self>) -> anyhow::Result<()> {
+        let mut store = self.repository.write_store_ref();
+        let mut committed = store.clone();
+        for write in self.writes {
+            committed.apply(write)?;
+        }
+        *store = committed;
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl SectionRepository for InMemorySectionRepository {
-    // using todo!() instead of implementing
     async fn find_by_id(&self, id: i32) -> anyhow::Result<Section> {
-        let store = self.read_store_ref();
-        let section = store.get(&id).unwrap().clone();
-        Ok(section)
+        self.read_store_ref().find(id)
     }
-    async fn find_by_gender(&self, gender: String) -> anyhow::Result<Vec<Section>> {
+    async fn find_by_gender(
+        &self,
+        site: String,
+        facility_type: String,
+        gender: String,
+    ) -> anyhow::Result<Vec<Section>> {
         let store = self.read_store_ref();
         let sections = Vec::from_iter(
             store
-                .values()
-                .filter(|section| section.gender == gender)
+                .active()
+                .filter(|section| {
+                    section.site == site
+                        && section.facility_type == facility_type
+                        && section.gender == gender
+                })
                 .cloned(),
         );
-
-        if sections.is_empty() {
-            Err(anyhow::Error::msg("No section found for the given gender"))
-        } else {
-            Ok(sections)
-        }
+        Ok(sections)
     }
     async fn find_by_building(
         &self,
+        site: String,
+        facility_type: String,
         gender: String,
         building: String,
     ) -> anyhow::Result<Vec<Section>> {
         let store = self.read_store_ref();
         let sections = Vec::from_iter(
             store
-                .values()
-                .filter(|section| section.gender == gender && section.building == building)
+                .active()
+                .filter(|section| {
+                    section.site == site
+                        && section.facility_type == facility_type
+                        && section.gender == gender
+                        && section.building == building
+                })
                 .cloned(),
         );
-
-        if sections.is_empty() {
-            Err(anyhow::Error::msg(
-                "No section found for the given gender or building",
-            ))
-        } else {
-            Ok(sections)
-        }
+        Ok(sections)
     }
     async fn find_by_floor(
         &self,
+        site: String,
+        facility_type: String,
         gender: String,
         building: String,
         floor: i32,
     ) -> anyhow::Result<Vec<Section>> {
         let store = self.read_store_ref();
-        let sections = Vec::from_iter(
-            store
-                .values()
-                .filter(|section| {
-                    section.gender == gender
-                        && section.building == building
-                        && section.floor == floor
-                })
-                .cloned(),
-        );
-
-        if sections.is_empty() {
-            Err(anyhow::Error::msg(
-                "No section found for the given gender or building",
-            ))
-        } else {
-            Ok(sections.clone())
-        }
+        let location = SectionInfo {
+            site,
+            facility_type,
+            gender,
+            building,
+            floor,
+        };
+        // like the queries, a location without a section is an empty list, not an error
+        let sections = store
+            .locations
+            .get(&location)
+            .and_then(|id| store.sections.get(id))
+            .cloned();
+        Ok(Vec::from_iter(sections))
     }
     async fn find_all(&self) -> anyhow::Result<Vec<Section>> {
         let store = self.read_store_ref();
-        Ok(Vec::from_iter(store.values().map(|sec| sec.clone())))
+        Ok(Vec::from_iter(store.active().cloned()))
+    }
+    async fn find_archived(&self) -> anyhow::Result<Vec<Section>> {
+        let store = self.read_store_ref();
+        Ok(Vec::from_iter(
+            store
+                .sections
+                .values()
+                .filter(|section| section.deleted_at.is_some())
+                .cloned(),
+        ))
     }
     async fn create(&self, payload: CreateSection, info: SectionInfo) -> anyhow::Result<Section> {
-        let mut store = self.write_store_ref();
-        let id = (store.len() + 1) as i32;
-        let section = Section::new(id, info.gender, info.building, info.floor, payload.total);
-        store.insert(id, section.clone());
-        Ok(section)
+        let section = Section::new(self.next_id(), info, payload.total);
+        self.write_store_ref().apply(Write::Insert(section))
     }
     async fn update(&self, payload: UpdateSection) -> anyhow::Result<Section> {
-        let mut store = self.write_store_ref();
-        let section = store
-            .get(&payload.id)
-            .context(RepositoryError::NotFound(payload.id))?;
-        let usage =
-            inmemory_switch_usage(payload.current_status, payload.next_status, section.clone())?;
-        let section = Section {
-            id: payload.id,
-            gender: section.gender.clone(),
-            building: section.building.clone(),
-            floor: section.floor,
-            total: section.total,
-            available: usage.available,
-            occupied: usage.occupied,
-            disabled_rooms: usage.disabled_rooms,
-        };
-        store.insert(payload.id, section.clone());
-        Ok(section)
+        self.write_store_ref().apply(Write::Update(payload))
+    }
+    async fn update_capacity(&self, id: i32, total: i32) -> anyhow::Result<Section> {
+        self.write_store_ref()
+            .apply(Write::UpdateCapacity(id, total))
     }
     async fn delete(&self, id: i32) -> anyhow::Result<()> {
-        let mut store = self.write_store_ref();
-        store.remove(&id).ok_or(RepositoryError::NotFound(id))?;
+        self.write_store_ref().apply(Write::Delete(id))?;
         Ok(())
     }
+    async fn restore(&self, id: i32) -> anyhow::Result<Section> {
+        self.write_store_ref().apply(Write::Restore(id))
+    }
+    async fn begin(&self) -> anyhow::Result<Box<dyn SectionTransaction>> {
+        Ok(Box::new(InMemorySectionTransaction {
+            repository: self.clone(),
+            working: self.read_store_ref().clone(),
+            writes: vec![],
+        }))
+    }
 }
 
 #[cfg(test)]
 mod in_memory_tests {
     use super::*;
+    use crate::repositories::section::contract;
+
+    #[tokio::test]
+    async fn test_contract_find() -> anyhow::Result<()> {
+        contract::find(&InMemorySectionRepository::seeded()).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_update() -> anyhow::Result<()> {
+        contract::update(&InMemorySectionRepository::seeded()).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_transaction() -> anyhow::Result<()> {
+        contract::transaction(&InMemorySectionRepository::seeded()).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_duplicate() -> anyhow::Result<()> {
+        contract::duplicate(&InMemorySectionRepository::seeded()).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_invalid() -> anyhow::Result<()> {
+        contract::invalid(&InMemorySectionRepository::seeded()).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_archive() -> anyhow::Result<()> {
+        contract::archive(&InMemorySectionRepository::seeded()).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_sites() -> anyhow::Result<()> {
+        contract::sites(&InMemorySectionRepository::seeded()).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_facilities() -> anyhow::Result<()> {
+        contract::facilities(&InMemorySectionRepository::seeded()).await
+    }
 
     #[tokio::test]
     async fn test_section_repository() {
-        let repo = InMemorySectionRepository {
-            store: Arc::default(),
-        };
+        let repo = InMemorySectionRepository::new();
 
         // 1. Sectionの作成
         let create_section = CreateSection { total: 10 };
         let section_info = SectionInfo {
+            site: DEFAULT_SITE.to_string(),
+            facility_type: DEFAULT_FACILITY.to_string(),
             gender: "male".to_string(),
             building: "A".to_string(),
             floor: 1,
@@ -191,8 +458,115 @@ mod in_memory_tests {
         assert_eq!(updated_section.available, 10);
         assert_eq!(updated_section.occupied, 0);
 
-        // 5. deleteで1で作成したSectionを削除
+        // 5. update_capacityで総数を変更
+        let updated_section = repo.update_capacity(1, 12).await.unwrap();
+        assert_eq!(updated_section.total, 12);
+        assert_eq!(updated_section.available, 12);
+        assert!(repo.update_capacity(1, -1).await.is_err());
+
+        // 6. deleteで1で作成したSectionを削除
         let res = repo.delete(1).await;
         assert!(res.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_ids_and_locations() {
+        let repo = InMemorySectionRepository::new();
+        for (building, floor) in [("B", 1), ("A", 2), ("A", 1)] {
+            let section_info = SectionInfo {
+                site: DEFAULT_SITE.to_string(),
+                facility_type: DEFAULT_FACILITY.to_string(),
+                gender: "female".to_string(),
+                building: building.to_string(),
+                floor,
+            };
+            repo.create(CreateSection { total: 3 }, section_info)
+                .await
+                .unwrap();
+        }
+
+        // ids are never reused after a delete
+        repo.delete(2).await.unwrap();
+        let section_info = SectionInfo {
+            site: DEFAULT_SITE.to_string(),
+            facility_type: DEFAULT_FACILITY.to_string(),
+            gender: "female".to_string(),
+            building: "A".to_string(),
+            floor: 2,
+        };
+        let section = repo
+            .create(CreateSection { total: 4 }, section_info.clone())
+            .await
+            .unwrap();
+        assert_eq!(section.id, 4);
+        assert_eq!(repo.find_by_id(1).await.unwrap().building, "B");
+
+        // one section per location
+        let err = repo
+            .create(CreateSection { total: 4 }, section_info)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::DuplicateLocation(..))
+        ));
+
+        let ids = |sections: Vec<Section>| sections.iter().map(|s| s.id).collect::<Vec<_>>();
+        assert_eq!(ids(repo.find_all().await.unwrap()), vec![1, 3, 4]);
+        assert_eq!(
+            ids(repo
+                .find_by_building(
+                    DEFAULT_SITE.to_string(),
+                    DEFAULT_FACILITY.to_string(),
+                    "female".to_string(),
+                    "A".to_string()
+                )
+                .await
+                .unwrap()),
+            vec![3, 4]
+        );
+        assert_eq!(
+            ids(repo
+                .find_by_floor(
+                    DEFAULT_SITE.to_string(),
+                    DEFAULT_FACILITY.to_string(),
+                    "female".to_string(),
+                    "A".to_string(),
+                    2
+                )
+                .await
+                .unwrap()),
+            vec![4]
+        );
+        assert!(repo.find_by_id(2).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_transaction_commit_replays_writes() {
+        let repo = InMemorySectionRepository::seeded();
+        let section = repo.find_by_id(1).await.unwrap();
+        let disable = || UpdateSection {
+            id: 1,
+            current_status: "available".to_string(),
+            next_status: "disabled".to_string(),
+        };
+
+        // a write made outside the transaction is kept, not overwritten by its copy
+        let mut tx = repo.begin().await.unwrap();
+        tx.update(disable()).await.unwrap();
+        repo.update(disable()).await.unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(
+            repo.find_by_id(1).await.unwrap().disabled_rooms,
+            section.disabled_rooms + 2
+        );
+
+        // a write that no longer applies fails the commit and nothing of it lands
+        let mut tx = repo.begin().await.unwrap();
+        tx.update_capacity(1, section.total + 1).await.unwrap();
+        tx.delete(2).await.unwrap();
+        repo.delete(2).await.unwrap();
+        assert!(tx.commit().await.is_err());
+        assert_eq!(repo.find_by_id(1).await.unwrap().total, section.total);
+    }
 }
@@ -0,0 +1,341 @@
+use crate::repositories::section::models::{
+    CreateSection, LatencyBucket, MethodMetrics, SchemaVersion, Section, SectionInfo, UpdateSection,
+};
+use crate::repositories::section::traits::{SectionRepository, SectionTransaction};
+use axum::async_trait;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{Instrument, Span};
+
+// upper bounds of the latency buckets, anything slower lands in the last one
+const BUCKETS_MS: [u64; 10] = [1, 2, 5, 10, 25, 50, 100, 250, 500, 1000];
+const METHODS: [&str; 12] = [
+    "find_by_id",
+    "find_by_gender",
+    "find_by_building",
+    "find_by_floor",
+    "find_all",
+    "find_archived",
+    "create",
+    "update",
+    "update_capacity",
+    "delete",
+    "restore",
+    "begin",
+];
+
+#[derive(Debug, Default)]
+struct Histogram {
+    calls: AtomicU64,
+    errors: AtomicU64,
+    total_micros: AtomicU64,
+    buckets: [AtomicU64; BUCKETS_MS.len() + 1],
+}
+
+impl Histogram {
+    fn record(&self, elapsed: Duration, failed: bool) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.total_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        let bucket = BUCKETS_MS
+            .iter()
+            .position(|&le| elapsed <= Duration::from_millis(le))
+            .unwrap_or(BUCKETS_MS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self, method: &str) -> MethodMetrics {
+        // cumulative like prometheus, the last bucket has no bound and equals calls
+        let mut count = 0;
+        let buckets = self
+            .buckets
+            .iter()
+            .enumerate()
+            .map(|(i, bucket)| {
+                count += bucket.load(Ordering::Relaxed);
+                LatencyBucket {
+                    le_ms: BUCKETS_MS.get(i).copied(),
+                    count,
+                }
+            })
+            .collect();
+        MethodMetrics {
+            method: method.to_string(),
+            calls: self.calls.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            total_micros: self.total_micros.load(Ordering::Relaxed),
+            buckets,
+        }
+    }
+}
+
+// the histograms of an instrumented repository, kept by the app state for GET /metrics/repository
+#[derive(Clone, Debug)]
+pub struct SectionMetrics(Arc<BTreeMap<&'static str, Histogram>>);
+
+impl SectionMetrics {
+    pub fn snapshot(&self) -> Vec<MethodMetrics> {
+        self.0
+            .iter()
+            .map(|(method, histogram)| histogram.snapshot(method))
+            .collect()
+    }
+}
+
+// a span per call with its location arguments, plus latency and error counts per method
+#[derive(Clone, Debug)]
+pub struct InstrumentedSectionRepository<R: SectionRepository> {
+    inner: R,
+    histograms: Arc<BTreeMap<&'static str, Histogram>>,
+}
+
+impl<R: SectionRepository> InstrumentedSectionRepository<R> {
+    pub fn new(inner: R) -> Self {
+        let histograms = METHODS
+            .iter()
+            .map(|&method| (method, Histogram::default()))
+            .collect();
+        Self {
+            inner,
+            histograms: Arc::new(histograms),
+        }
+    }
+
+    pub fn metrics(&self) -> SectionMetrics {
+        SectionMetrics(Arc::clone(&self.histograms))
+    }
+
+    async fn observe<T, F>(&self, method: &'static str, span: Span, call: F) -> anyhow::Result<T>
+    where
+        F: Future<Output = anyhow::Result<T>>,
+    {
+        let start = Instant::now();
+        let result = call.instrument(span.clone()).await;
+        let elapsed = start.elapsed();
+        self.histograms[method].record(elapsed, result.is_err());
+
+        let _entered = span.enter();
+        match &result {
+            Ok(_) => tracing::debug!(elapsed_ms = elapsed.as_secs_f64() * 1000.0, "done"),
+            Err(e) => tracing::warn!(
+                elapsed_ms = elapsed.as_secs_f64() * 1000.0,
+                error = %e,
+                "failed"
+            ),
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl<R: SectionRepository> SectionRepository for InstrumentedSectionRepository<R> {
+    async fn find_by_id(&self, id: i32) -> anyhow::Result<Section> {
+        let span = tracing::info_span!("section_repository", method = "find_by_id", id);
+        self.observe("find_by_id", span, self.inner.find_by_id(id))
+            .await
+    }
+
+    async fn find_by_gender(
+        &self,
+        site: String,
+        facility_type: String,
+        gender: String,
+    ) -> anyhow::Result<Vec<Section>> {
+        let span = tracing::info_span!(
+            "section_repository",
+            method = "find_by_gender",
+            site = %site,
+            facility_type = %facility_type,
+            gender = %gender
+        );
+        self.observe(
+            "find_by_gender",
+            span,
+            self.inner.find_by_gender(site, facility_type, gender),
+        )
+        .await
+    }
+
+    async fn find_by_building(
+        &self,
+        site: String,
+        facility_type: String,
+        gender: String,
+        building: String,
+    ) -> anyhow::Result<Vec<Section>> {
+        let span = tracing::info_span!(
+            "section_repository",
+            method = "find_by_building",
+            site = %site,
+            facility_type = %facility_type,
+            gender = %gender,
+            building = %building
+        );
+        self.observe(
+            "find_by_building",
+            span,
+            self.inner
+                .find_by_building(site, facility_type, gender, building),
+        )
+        .await
+    }
+
+    async fn find_by_floor(
+        &self,
+        site: String,
+        facility_type: String,
+        gender: String,
+        building: String,
+        floor: i32,
+    ) -> anyhow::Result<Vec<Section>> {
+        let span = tracing::info_span!(
+            "section_repository",
+            method = "find_by_floor",
+            site = %site,
+            facility_type = %facility_type,
+            gender = %gender,
+            building = %building,
+            floor
+        );
+        self.observe(
+            "find_by_floor",
+            span,
+            self.inner
+                .find_by_floor(site, facility_type, gender, building, floor),
+        )
+        .await
+    }
+
+    async fn find_all(&self) -> anyhow::Result<Vec<Section>> {
+        let span = tracing::info_span!("section_repository", method = "find_all");
+        self.observe("find_all", span, self.inner.find_all()).await
+    }
+
+    async fn find_archived(&self) -> anyhow::Result<Vec<Section>> {
+        let span = tracing::info_span!("section_repository", method = "find_archived");
+        self.observe("find_archived", span, self.inner.find_archived())
+            .await
+    }
+
+    async fn create(&self, section: CreateSection, info: SectionInfo) -> anyhow::Result<Section> {
+        let span = tracing::info_span!(
+            "section_repository",
+            method = "create",
+            site = %info.site,
+            facility_type = %info.facility_type,
+            gender = %info.gender,
+            building = %info.building,
+            floor = info.floor
+        );
+        self.observe("create", span, self.inner.create(section, info))
+            .await
+    }
+
+    async fn update(&self, section: UpdateSection) -> anyhow::Result<Section> {
+        let span = tracing::info_span!(
+            "section_repository",
+            method = "update",
+            id = section.id,
+            current_status = %section.current_status,
+            next_status = %section.next_status
+        );
+        self.observe("update", span, self.inner.update(section))
+            .await
+    }
+
+    async fn update_capacity(&self, id: i32, total: i32) -> anyhow::Result<Section> {
+        let span = tracing::info_span!("section_repository", method = "update_capacity", id, total);
+        self.observe(
+            "update_capacity",
+            span,
+            self.inner.update_capacity(id, total),
+        )
+        .await
+    }
+
+    async fn delete(&self, id: i32) -> anyhow::Result<()> {
+        let span = tracing::info_span!("section_repository", method = "delete", id);
+        self.observe("delete", span, self.inner.delete(id)).await
+    }
+
+    async fn restore(&self, id: i32) -> anyhow::Result<Section> {
+        let span = tracing::info_span!("section_repository", method = "restore", id);
+        self.observe("restore", span, self.inner.restore(id)).await
+    }
+
+    // only opening the transaction is timed, the work inside it belongs to the caller
+    async fn begin(&self) -> anyhow::Result<Box<dyn SectionTransaction>> {
+        let span = tracing::info_span!("section_repository", method = "begin");
+        self.observe("begin", span, self.inner.begin()).await
+    }
+
+    fn has_outbox(&self) -> bool {
+        self.inner.has_outbox()
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        self.inner.ping().await
+    }
+
+    async fn schema_version(&self) -> anyhow::Result<Option<SchemaVersion>> {
+        self.inner.schema_version().await
+    }
+}
+
+#[cfg(all(test, feature = "in-memory"))]
+mod tests {
+    use super::*;
+    use crate::repositories::section::contract;
+    use crate::repositories::section::in_memory::InMemorySectionRepository;
+    use crate::repositories::section::models::{DEFAULT_FACILITY, DEFAULT_SITE};
+
+    #[tokio::test]
+    async fn test_contract() -> anyhow::Result<()> {
+        let repository = InstrumentedSectionRepository::new(InMemorySectionRepository::seeded());
+        contract::find(&repository).await?;
+        contract::update(&repository).await?;
+        contract::transaction(&repository).await?;
+        contract::archive(&repository).await
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let repository = InstrumentedSectionRepository::new(InMemorySectionRepository::seeded());
+        repository.find_by_id(1).await.unwrap();
+        repository.find_by_id(2).await.unwrap();
+        assert!(repository.find_by_id(0).await.is_err());
+        // an empty location is an answer, not an error
+        assert!(repository
+            .find_by_floor(
+                DEFAULT_SITE.to_string(),
+                DEFAULT_FACILITY.to_string(),
+                "female".to_string(),
+                "C".to_string(),
+                5
+            )
+            .await
+            .unwrap()
+            .is_empty());
+
+        let metrics = repository.metrics().snapshot();
+        assert_eq!(metrics.len(), METHODS.len());
+        let find_by_id = metrics.iter().find(|m| m.method == "find_by_id").unwrap();
+        assert_eq!((find_by_id.calls, find_by_id.errors), (3, 1));
+        assert_eq!(find_by_id.buckets.len(), BUCKETS_MS.len() + 1);
+        assert_eq!(find_by_id.buckets.last().unwrap().le_ms, None);
+        assert_eq!(find_by_id.buckets.last().unwrap().count, 3);
+        let find_by_floor = metrics
+            .iter()
+            .find(|m| m.method == "find_by_floor")
+            .unwrap();
+        assert_eq!((find_by_floor.calls, find_by_floor.errors), (1, 0));
+        let delete = metrics.iter().find(|m| m.method == "delete").unwrap();
+        assert_eq!(delete.calls, 0);
+    }
+}
@@ -1,6 +1,14 @@
+pub mod cached;
+#[cfg(test)]
+pub mod contract;
+#[cfg(feature = "postgres")]
 pub mod db;
 pub mod errors;
+#[cfg(feature = "in-memory")]
 pub mod in_memory;
+pub mod instrumented;
 pub mod models;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 pub mod traits;
 pub mod utils;
@@ -1,8 +1,21 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// the site sections created before sites existed were moved to
+pub const DEFAULT_SITE: &str = "main";
+
+// the facility type of sections created before there were others, served by the /showerrooms routes
+pub const DEFAULT_FACILITY: &str = "shower";
+
+// every stall is counted under one of these, a facility type uses some or all of them
+pub const STATUSES: [&str; 3] = ["available", "occupied", "disabled"];
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, sqlx::FromRow)]
 pub struct Section {
     pub id: i32,
+    pub site: String,
+    pub facility_type: String,
     pub gender: String,
     pub building: String,
     pub floor: i32,
@@ -10,20 +23,53 @@ pub struct Section {
     pub available: i32,
     pub occupied: i32,
     pub disabled_rooms: i32,
+    // set while the section is archived, archived sections are left out of every find
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+// where a section is, at most one active section per location
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SectionInfo {
+    pub site: String,
+    pub facility_type: String,
     pub gender: String,
     pub building: String,
     pub floor: i32,
 }
 
+impl fmt::Display for SectionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}/{}/{}/{}",
+            self.site, self.facility_type, self.gender, self.building, self.floor
+        )
+    }
+}
+
+impl From<Section> for SectionInfo {
+    fn from(section: Section) -> Self {
+        Self {
+            site: section.site,
+            facility_type: section.facility_type,
+            gender: section.gender,
+            building: section.building,
+            floor: section.floor,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct CreateSection {
     pub total: i32,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct UpdateCapacity {
+    pub total: i32,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct UpdateSection {
     pub id: i32,
@@ -31,12 +77,73 @@ pub struct UpdateSection {
     pub next_status: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct ArchiveQuery {
+    #[serde(default)]
+    pub include_archived: bool,
+}
+
+// narrows the routes without a site in their path to another site than the default
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct SiteQuery {
+    pub site: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct UpdatePayload {
     pub current_status: String,
     pub next_status: String,
 }
 
+impl UpdatePayload {
+    // how the audit log names the change, e.g. "available->occupied"
+    pub fn transition(&self) -> String {
+        format!("{}->{}", self.current_status, self.next_status)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SchemaStatus {
+    // no migration table yet, nothing was ever applied
+    Unmigrated,
+    Pending,
+    Current,
+}
+
+// version is the newest applied migration, latest the newest one built into the binary
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SchemaVersion {
+    pub status: SchemaStatus,
+    pub version: Option<i64>,
+    pub latest: i64,
+    pub pending: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LatencyBucket {
+    // none for the last, unbounded bucket
+    pub le_ms: Option<u64>,
+    pub count: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MethodMetrics {
+    pub method: String,
+    pub calls: u64,
+    pub errors: u64,
+    pub total_micros: u64,
+    pub buckets: Vec<LatencyBucket>,
+}
+
+#[cfg(feature = "in-memory")]
 pub struct Usage {
     pub available: i32,
     pub occupied: i32,
@@ -44,16 +151,19 @@ pub struct Usage {
 }
 
 impl Section {
-    pub fn new(id: i32, gender: String, building: String, floor: i32, total: i32) -> Self {
+    pub fn new(id: i32, info: SectionInfo, total: i32) -> Self {
         Self {
             id,
-            gender,
-            building,
-            floor,
+            site: info.site,
+            facility_type: info.facility_type,
+            gender: info.gender,
+            building: info.building,
+            floor: info.floor,
             total,
             available: total,
             occupied: 0,
             disabled_rooms: 0,
+            deleted_at: None,
         }
     }
 }
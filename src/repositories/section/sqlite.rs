@@ -0,0 +1,457 @@
+use crate::repositories::schema::schema_version;
+use crate::repositories::section::errors::RepositoryError;
+use crate::repositories::section::models::{
+    CreateSection, SchemaVersion, Section, SectionInfo, UpdateSection,
+};
+use crate::repositories::section::traits::{SectionRepository, SectionTransaction};
+use axum::async_trait;
+use chrono::Utc;
+use sqlx::migrate::Migrator;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection, SqlitePool, SqlitePoolOptions};
+use sqlx::{Sqlite, Transaction};
+use std::str::FromStr;
+
+use super::utils::{
+    capacity_conflict, location_conflict, not_found, query_switch_usage, transition_conflict,
+};
+
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations_sqlite");
+
+// shared by the repository and its transactions
+async fn find_section(conn: &mut SqliteConnection, id: i32) -> anyhow::Result<Section> {
+    let section =
+        sqlx::query_as::<_, Section>("SELECT * FROM sections WHERE id = $1 AND deleted_at IS NULL")
+            .bind(id)
+            .fetch_one(conn)
+            .await
+            .map_err(|e| not_found(e, id))?;
+    Ok(section)
+}
+
+async fn find_archived_section(conn: &mut SqliteConnection, id: i32) -> anyhow::Result<Section> {
+    let section = sqlx::query_as::<_, Section>(
+        "SELECT * FROM sections WHERE id = $1 AND deleted_at IS NOT NULL",
+    )
+    .bind(id)
+    .fetch_optional(conn)
+    .await?
+    .ok_or(RepositoryError::NotFound(id))?;
+    Ok(section)
+}
+
+async fn create_section(
+    conn: &mut SqliteConnection,
+    section: CreateSection,
+    info: SectionInfo,
+) -> anyhow::Result<Section> {
+    let section = sqlx::query_as::<_, Section>(
+        "insert into sections (building, floor, gender, total, available, occupied, disabled_rooms, site, facility_type) values ($1, $2, $3, $4, $4, 0, 0, $5, $6) returning *"
+    )
+    .bind(&info.building)
+    .bind(info.floor)
+    .bind(&info.gender)
+    .bind(section.total)
+    .bind(&info.site)
+    .bind(&info.facility_type)
+    .fetch_one(conn)
+    .await
+    .map_err(|e| location_conflict(e, &info))?;
+    Ok(section)
+}
+
+async fn update_section(
+    conn: &mut SqliteConnection,
+    section: UpdateSection,
+) -> anyhow::Result<Section> {
+    let query = query_switch_usage(section.current_status.clone(), section.next_status.clone())?;
+    let section = sqlx::query_as::<_, Section>(query)
+        .bind(section.id)
+        .fetch_one(conn)
+        .await
+        .map_err(|e| transition_conflict(e, &section))?;
+    Ok(section)
+}
+
+async fn update_section_capacity(
+    conn: &mut SqliteConnection,
+    id: i32,
+    total: i32,
+) -> anyhow::Result<Section> {
+    let section = sqlx::query_as::<_, Section>(
+        "update sections set available = available + ($2 - total), total = $2 where id = $1 and deleted_at is null returning *",
+    )
+    .bind(id)
+    .bind(total)
+    .fetch_one(conn)
+    .await
+    .map_err(|e| capacity_conflict(e, id, total))?;
+    Ok(section)
+}
+
+// the row stays for usage_history, only deleted_at marks it archived
+async fn delete_section(conn: &mut SqliteConnection, id: i32) -> anyhow::Result<()> {
+    let _ = sqlx::query_as::<_, Section>(
+        "update sections set deleted_at = $2 where id = $1 and deleted_at is null returning *",
+    )
+    .bind(id)
+    .bind(Utc::now())
+    .fetch_one(conn)
+    .await
+    .map_err(|e| not_found(e, id))?;
+    Ok(())
+}
+
+async fn restore_section(conn: &mut SqliteConnection, id: i32) -> anyhow::Result<Section> {
+    let archived = find_archived_section(&mut *conn, id).await?;
+    let section = sqlx::query_as::<_, Section>(
+        "update sections set deleted_at = null where id = $1 returning *",
+    )
+    .bind(id)
+    .fetch_one(conn)
+    .await
+    .map_err(|e| location_conflict(e, &archived.into()))?;
+    Ok(section)
+}
+
+// single file deployments, there is no outbox so the handlers publish events themselves
+#[derive(Clone, Debug)]
+pub struct SqliteSectionRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteSectionRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    // the other sqlite stores share the database file and its migrations
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
+    // creates the database file when missing and brings its schema up to date, unless
+    // a newer binary already migrated it
+    pub async fn connect(database_url: &str) -> anyhow::Result<Self> {
+        let options = SqliteConnectOptions::from_str(database_url)?
+            .create_if_missing(true)
+            .foreign_keys(true);
+        // the connections to an in-memory database share its cache and can miss each
+        // other's writes after a failed statement, one connection keeps reads consistent
+        let in_memory = database_url.contains(":memory:") || database_url.contains("mode=memory");
+        let pool = SqlitePoolOptions::new()
+            .max_connections(if in_memory { 1 } else { 10 })
+            .connect_with(options)
+            .await?;
+        let mut conn = pool.acquire().await?;
+        let migrated = migrated(&mut conn).await?;
+        schema_version(&mut *conn, &MIGRATOR, migrated).await?;
+        drop(conn);
+        MIGRATOR.run(&pool).await?;
+        Ok(Self::new(pool))
+    }
+}
+
+// whether the migrations table exists, looked up without creating it
+async fn migrated(conn: &mut SqliteConnection) -> anyhow::Result<bool> {
+    let (migrated,) = sqlx::query_as::<_, (bool,)>(
+        "select exists (select 1 from sqlite_master where type = 'table' and name = '_sqlx_migrations')",
+    )
+    .fetch_one(conn)
+    .await?;
+    Ok(migrated)
+}
+
+pub struct SqliteSectionTransaction {
+    tx: Transaction<'static, Sqlite>,
+}
+
+#[async_trait]
+impl SectionTransaction for SqliteSectionTransaction {
+    async fn find_by_id(&mut self, id: i32) -> anyhow::Result<Section> {
+        find_section(&mut self.tx, id).await
+    }
+
+    async fn create(
+        &mut self,
+        section: CreateSection,
+        info: SectionInfo,
+    ) -> anyhow::Result<Section> {
+        create_section(&mut self.tx, section, info).await
+    }
+
+    async fn update(&mut self, section: UpdateSection) -> anyhow::Result<Section> {
+        update_section(&mut self.tx, section).await
+    }
+
+    async fn update_capacity(&mut self, id: i32, total: i32) -> anyhow::Result<Section> {
+        update_section_capacity(&mut self.tx, id, total).await
+    }
+
+    async fn delete(&mut self, id: i32) -> anyhow::Result<()> {
+        delete_section(&mut self.tx, id).await
+    }
+
+    async fn restore(&mut self, id: i32) -> anyhow::Result<Section> {
+        restore_section(&mut self.tx, id).await
+    }
+
+    async fn commit(self: Box<Self>) -> anyhow::Result<()> {
+        self.tx.commit().await?;
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> anyhow::Result<()> {
+        self.tx.rollback().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SectionRepository for SqliteSectionRepository {
+    async fn find_by_id(&self, id: i32) -> anyhow::Result<Section> {
+        let mut conn = self.pool.acquire().await?;
+        find_section(&mut conn, id).await
+    }
+
+    async fn find_by_gender(
+        &self,
+        site: String,
+        facility_type: String,
+        gender: String,
+    ) -> anyhow::Result<Vec<Section>> {
+        let sections = sqlx::query_as::<_, Section>(
+            "SELECT * FROM sections WHERE site = $1 AND facility_type = $2 AND gender = $3 AND deleted_at IS NULL order by id asc",
+        )
+        .bind(site)
+        .bind(facility_type)
+        .bind(gender)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(sections)
+    }
+
+    async fn find_by_building(
+        &self,
+        site: String,
+        facility_type: String,
+        gender: String,
+        building: String,
+    ) -> anyhow::Result<Vec<Section>> {
+        let sections = sqlx::query_as::<_, Section>(
+            "SELECT * FROM sections WHERE site = $1 AND facility_type = $2 AND gender = $3 AND building = $4 AND deleted_at IS NULL order by id asc",
+        )
+        .bind(site)
+        .bind(facility_type)
+        .bind(gender)
+        .bind(building)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(sections)
+    }
+
+    async fn find_by_floor(
+        &self,
+        site: String,
+        facility_type: String,
+        gender: String,
+        building: String,
+        floor: i32,
+    ) -> anyhow::Result<Vec<Section>> {
+        let sections = sqlx::query_as::<_, Section>(
+            "SELECT * FROM sections WHERE site = $1 AND facility_type = $2 AND gender = $3 AND building = $4 AND floor = $5 AND deleted_at IS NULL",
+        )
+        .bind(site)
+        .bind(facility_type)
+        .bind(gender)
+        .bind(building)
+        .bind(floor)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(sections)
+    }
+
+    async fn find_all(&self) -> anyhow::Result<Vec<Section>> {
+        let sections = sqlx::query_as::<_, Section>(
+            "SELECT * FROM sections WHERE deleted_at IS NULL order by id asc",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(sections)
+    }
+
+    async fn find_archived(&self) -> anyhow::Result<Vec<Section>> {
+        let sections = sqlx::query_as::<_, Section>(
+            "SELECT * FROM sections WHERE deleted_at IS NOT NULL order by id asc",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(sections)
+    }
+
+    async fn create(&self, section: CreateSection, info: SectionInfo) -> anyhow::Result<Section> {
+        let mut conn = self.pool.acquire().await?;
+        create_section(&mut conn, section, info).await
+    }
+
+    async fn update(&self, section: UpdateSection) -> anyhow::Result<Section> {
+        let mut conn = self.pool.acquire().await?;
+        update_section(&mut conn, section).await
+    }
+
+    async fn update_capacity(&self, id: i32, total: i32) -> anyhow::Result<Section> {
+        let mut conn = self.pool.acquire().await?;
+        update_section_capacity(&mut conn, id, total).await
+    }
+
+    async fn delete(&self, id: i32) -> anyhow::Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        delete_section(&mut conn, id).await
+    }
+
+    async fn restore(&self, id: i32) -> anyhow::Result<Section> {
+        let mut conn = self.pool.acquire().await?;
+        restore_section(&mut conn, id).await
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        sqlx::query("select 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn schema_version(&self) -> anyhow::Result<Option<SchemaVersion>> {
+        let mut conn = self.pool.acquire().await?;
+        let migrated = migrated(&mut conn).await?;
+        Ok(Some(schema_version(&mut *conn, &MIGRATOR, migrated).await?))
+    }
+
+    async fn begin(&self) -> anyhow::Result<Box<dyn SectionTransaction>> {
+        let tx = self.pool.begin().await?;
+        Ok(Box::new(SqliteSectionTransaction { tx }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::section::contract;
+    use crate::repositories::section::models::{SchemaStatus, DEFAULT_FACILITY, DEFAULT_SITE};
+    use anyhow::Result;
+
+    // a private in-memory database per test, already migrated and seeded
+    async fn setup() -> Result<SqliteSectionRepository> {
+        SqliteSectionRepository::connect("sqlite::memory:").await
+    }
+
+    #[tokio::test]
+    async fn test_contract_find() -> Result<()> {
+        contract::find(&setup().await?).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_update() -> Result<()> {
+        contract::update(&setup().await?).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_transaction() -> Result<()> {
+        contract::transaction(&setup().await?).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_duplicate() -> Result<()> {
+        contract::duplicate(&setup().await?).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_invalid() -> Result<()> {
+        contract::invalid(&setup().await?).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_archive() -> Result<()> {
+        contract::archive(&setup().await?).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_sites() -> Result<()> {
+        contract::sites(&setup().await?).await
+    }
+
+    #[tokio::test]
+    async fn test_contract_facilities() -> Result<()> {
+        contract::facilities(&setup().await?).await
+    }
+
+    #[tokio::test]
+    async fn test_create_and_delete() -> Result<()> {
+        let repository = setup().await?;
+        let section = repository.find_by_id(24).await?;
+        repository.delete(section.id).await?;
+        assert!(repository.delete(section.id).await.is_err());
+
+        let info = SectionInfo {
+            site: DEFAULT_SITE.to_string(),
+            facility_type: DEFAULT_FACILITY.to_string(),
+            gender: section.gender.clone(),
+            building: section.building.clone(),
+            floor: section.floor,
+        };
+        let created_section = repository.create(CreateSection { total: 6 }, info).await?;
+        assert_eq!(created_section.id, 25);
+        assert_eq!(created_section.available, 6);
+        // any building, but floors count from 1
+        let err = repository
+            .create(
+                CreateSection { total: 6 },
+                SectionInfo {
+                    site: DEFAULT_SITE.to_string(),
+                    facility_type: DEFAULT_FACILITY.to_string(),
+                    gender: "female".to_string(),
+                    building: "D".to_string(),
+                    floor: 0,
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::InvalidSection(..))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_schema_version() -> Result<()> {
+        let repository = setup().await?;
+        let schema = repository.schema_version().await?.unwrap();
+        assert_eq!(schema.version, Some(schema.latest));
+        assert_eq!(schema.pending, 0);
+
+        // as if a newer binary had migrated the database
+        sqlx::query(
+            "insert into _sqlx_migrations (version, description, success, checksum, execution_time) values ($1, 'from the future', true, x'00', 0)",
+        )
+        .bind(schema.latest + 1)
+        .execute(&repository.pool)
+        .await?;
+        assert!(repository.schema_version().await.is_err());
+
+        // an empty database is reported, and left, unmigrated
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await?;
+        let repository = SqliteSectionRepository::new(pool);
+        for _ in 0..2 {
+            let schema = repository.schema_version().await?.unwrap();
+            assert_eq!(schema.status, SchemaStatus::Unmigrated);
+            assert_eq!(
+                (schema.version, schema.pending),
+                (None, MIGRATOR.iter().count())
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -1,23 +1,69 @@
-use crate::repositories::section::models::{CreateSection, Section, SectionInfo, UpdateSection};
+use crate::repositories::section::models::{
+    CreateSection, SchemaVersion, Section, SectionInfo, UpdateSection,
+};
 use axum::async_trait;
 
+// writes made through the handle become visible together on commit, dropping it rolls back
+#[async_trait]
+pub trait SectionTransaction: std::marker::Send {
+    async fn find_by_id(&mut self, id: i32) -> anyhow::Result<Section>;
+    async fn create(
+        &mut self,
+        section: CreateSection,
+        info: SectionInfo,
+    ) -> anyhow::Result<Section>;
+    async fn update(&mut self, section: UpdateSection) -> anyhow::Result<Section>;
+    async fn update_capacity(&mut self, id: i32, total: i32) -> anyhow::Result<Section>;
+    async fn delete(&mut self, id: i32) -> anyhow::Result<()>;
+    async fn restore(&mut self, id: i32) -> anyhow::Result<Section>;
+    async fn commit(self: Box<Self>) -> anyhow::Result<()>;
+    async fn rollback(self: Box<Self>) -> anyhow::Result<()>;
+}
+
 #[async_trait]
 pub trait SectionRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
     async fn find_by_id(&self, id: i32) -> anyhow::Result<Section>;
-    async fn find_by_gender(&self, gender: String) -> anyhow::Result<Vec<Section>>;
+    async fn find_by_gender(
+        &self,
+        site: String,
+        facility_type: String,
+        gender: String,
+    ) -> anyhow::Result<Vec<Section>>;
     async fn find_by_building(
         &self,
+        site: String,
+        facility_type: String,
         gender: String,
         building: String,
     ) -> anyhow::Result<Vec<Section>>;
     async fn find_by_floor(
         &self,
+        site: String,
+        facility_type: String,
         gender: String,
         building: String,
         floor: i32,
     ) -> anyhow::Result<Vec<Section>>;
+    // every site
     async fn find_all(&self) -> anyhow::Result<Vec<Section>>;
+    async fn find_archived(&self) -> anyhow::Result<Vec<Section>>;
     async fn create(&self, section: CreateSection, info: SectionInfo) -> anyhow::Result<Section>;
     async fn update(&self, section: UpdateSection) -> anyhow::Result<Section>;
+    async fn update_capacity(&self, id: i32, total: i32) -> anyhow::Result<Section>;
+    // archives the section, it keeps its row and history but leaves every find
     async fn delete(&self, id: i32) -> anyhow::Result<()>;
+    async fn restore(&self, id: i32) -> anyhow::Result<Section>;
+    async fn begin(&self) -> anyhow::Result<Box<dyn SectionTransaction>>;
+    // true when changes are published from an outbox rather than by the handlers
+    fn has_outbox(&self) -> bool {
+        false
+    }
+    // whether the backing store answers, behind the health check
+    async fn ping(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+    // applied migrations when the repository is backed by a database
+    async fn schema_version(&self) -> anyhow::Result<Option<SchemaVersion>> {
+        Ok(None)
+    }
 }
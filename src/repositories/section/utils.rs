@@ -1,5 +1,10 @@
+use crate::repositories::section::errors::RepositoryError;
+#[cfg(feature = "in-memory")]
 use crate::repositories::section::models::{Section, Usage};
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+use crate::repositories::section::models::{SectionInfo, UpdateSection};
 
+#[cfg(feature = "in-memory")]
 pub fn inmemory_switch_usage(
     current_status: String,
     next_status: String,
@@ -15,7 +20,7 @@ pub fn inmemory_switch_usage(
                         disabled_rooms: section.disabled_rooms,
                     })
                 } else {
-                    Err(anyhow::anyhow!("No more sections are available"))
+                    Err(RepositoryError::InvalidTransition(current_status).into())
                 }
             } else if next_status == "disabled" {
                 if section.available > 0 {
@@ -25,7 +30,7 @@ pub fn inmemory_switch_usage(
                         disabled_rooms: section.disabled_rooms + 1,
                     })
                 } else {
-                    Err(anyhow::anyhow!("No more sections are available to disable"))
+                    Err(RepositoryError::InvalidTransition(current_status).into())
                 }
             } else {
                 Err(anyhow::anyhow!("invalid status"))
@@ -40,7 +45,7 @@ pub fn inmemory_switch_usage(
                         disabled_rooms: section.disabled_rooms,
                     })
                 } else {
-                    Err(anyhow::anyhow!("No more sections are occupied"))
+                    Err(RepositoryError::InvalidTransition(current_status).into())
                 }
             } else if next_status == "disabled" {
                 if section.occupied > 0 {
@@ -50,7 +55,7 @@ pub fn inmemory_switch_usage(
                         disabled_rooms: section.disabled_rooms + 1,
                     })
                 } else {
-                    Err(anyhow::anyhow!("No more sections are occupied to disable"))
+                    Err(RepositoryError::InvalidTransition(current_status).into())
                 }
             } else {
                 Err(anyhow::anyhow!("invalid status"))
@@ -65,7 +70,7 @@ pub fn inmemory_switch_usage(
                         disabled_rooms: section.disabled_rooms - 1,
                     })
                 } else {
-                    Err(anyhow::anyhow!("No more sections are disabled"))
+                    Err(RepositoryError::InvalidTransition(current_status).into())
                 }
             } else if next_status == "occupied" {
                 if section.disabled_rooms > 0 {
@@ -75,7 +80,7 @@ pub fn inmemory_switch_usage(
                         disabled_rooms: section.disabled_rooms - 1,
                     })
                 } else {
-                    Err(anyhow::anyhow!("No more sections are disabled to occupy"))
+                    Err(RepositoryError::InvalidTransition(current_status).into())
                 }
             } else {
                 Err(anyhow::anyhow!("invalid status"))
@@ -85,6 +90,7 @@ pub fn inmemory_switch_usage(
     }
 }
 
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
 pub fn query_switch_usage(
     current_status: String,
     next_status: String,
@@ -92,27 +98,27 @@ pub fn query_switch_usage(
     match current_status.as_str() {
         "available" => {
             if next_status == "occupied" {
-                Ok("update sections set available = available - 1, occupied = occupied + 1 where id = $1 returning *")
+                Ok("update sections set available = available - 1, occupied = occupied + 1 where id = $1 and deleted_at is null returning *")
             } else if next_status == "disabled" {
-                Ok("update sections set available = available - 1, disabled_rooms = disabled_rooms + 1 where id = $1 returning *")
+                Ok("update sections set available = available - 1, disabled_rooms = disabled_rooms + 1 where id = $1 and deleted_at is null returning *")
             } else {
                 Err(anyhow::anyhow!("invalid status"))
             }
         }
         "occupied" => {
             if next_status == "available" {
-                Ok("update sections set available = available + 1, occupied = occupied - 1 where id = $1 returning *")
+                Ok("update sections set available = available + 1, occupied = occupied - 1 where id = $1 and deleted_at is null returning *")
             } else if next_status == "disabled" {
-                Ok("update sections set occupied = occupied - 1, disabled_rooms = disabled_rooms + 1 where id = $1 returning *")
+                Ok("update sections set occupied = occupied - 1, disabled_rooms = disabled_rooms + 1 where id = $1 and deleted_at is null returning *")
             } else {
                 Err(anyhow::anyhow!("invalid status"))
             }
         }
         "disabled" => {
             if next_status == "available" {
-                Ok("update sections set available = available + 1, disabled_rooms = disabled_rooms - 1 where id = $1 returning *")
+                Ok("update sections set available = available + 1, disabled_rooms = disabled_rooms - 1 where id = $1 and deleted_at is null returning *")
             } else if next_status == "occupied" {
-                Ok("update sections set occupied = occupied + 1, disabled_rooms = disabled_rooms - 1 where id = $1 returning *")
+                Ok("update sections set occupied = occupied + 1, disabled_rooms = disabled_rooms - 1 where id = $1 and deleted_at is null returning *")
             } else {
                 Err(anyhow::anyhow!("invalid status"))
             }
@@ -121,15 +127,66 @@ pub fn query_switch_usage(
     }
 }
 
+// the unique (site, facility_type, gender, building, floor) index and the check constraints
+// surface as the errors the in-memory backend returns
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+pub fn location_conflict(e: sqlx::Error, info: &SectionInfo) -> anyhow::Error {
+    match e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            RepositoryError::DuplicateLocation(info.clone()).into()
+        }
+        sqlx::Error::Database(db) if db.is_check_violation() => {
+            RepositoryError::InvalidSection(db.message().to_string()).into()
+        }
+        e => e.into(),
+    }
+}
+
+// the check constraints reject moving a stall out of a status none is in, and an archived
+// section has no row to update
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+pub fn transition_conflict(e: sqlx::Error, section: &UpdateSection) -> anyhow::Error {
+    match e {
+        sqlx::Error::Database(db) if db.is_check_violation() => {
+            RepositoryError::InvalidTransition(section.current_status.clone()).into()
+        }
+        e => not_found(e, section.id),
+    }
+}
+
+// the check constraints reject a total below the stalls in use
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+pub fn capacity_conflict(e: sqlx::Error, id: i32, total: i32) -> anyhow::Error {
+    match e {
+        sqlx::Error::Database(db) if db.is_check_violation() => {
+            RepositoryError::InsufficientCapacity(total).into()
+        }
+        e => not_found(e, id),
+    }
+}
+
+// no active row with the id, so the same error as the in-memory backend rather than a
+// database error
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+pub fn not_found(e: sqlx::Error, id: i32) -> anyhow::Error {
+    match e {
+        sqlx::Error::RowNotFound => RepositoryError::NotFound(id).into(),
+        e => e.into(),
+    }
+}
+
 #[cfg(test)]
 mod utils_test {
     use super::*;
     //use crate::repositories::section::models::Usage;
 
     #[test]
+    #[cfg(feature = "in-memory")]
     fn test_inmemory_switch_usage() {
         let section = Section {
             id: 1,
+            site: "main".to_string(),
+            facility_type: "shower".to_string(),
             available: 5,
             occupied: 4,
             disabled_rooms: 1,
@@ -137,6 +194,7 @@ mod utils_test {
             building: "A".to_string(),
             floor: 4,
             total: 10,
+            deleted_at: None,
         };
         //a -> o
         let usage = inmemory_switch_usage(
@@ -201,23 +259,24 @@ mod utils_test {
     }
 
     #[test]
+    #[cfg(any(feature = "postgres", feature = "sqlite"))]
     fn test_query() {
         let query = query_switch_usage("available".to_string(), "occupied".to_string()).unwrap();
-        assert_eq!(query, "update sections set available = available - 1, occupied = occupied + 1 where id = $1 returning *");
+        assert_eq!(query, "update sections set available = available - 1, occupied = occupied + 1 where id = $1 and deleted_at is null returning *");
 
         let query = query_switch_usage("available".to_string(), "disabled".to_string()).unwrap();
-        assert_eq!(query, "update sections set available = available - 1, disabled_rooms = disabled_rooms + 1 where id = $1 returning *");
+        assert_eq!(query, "update sections set available = available - 1, disabled_rooms = disabled_rooms + 1 where id = $1 and deleted_at is null returning *");
 
         let query = query_switch_usage("occupied".to_string(), "available".to_string()).unwrap();
-        assert_eq!(query, "update sections set available = available + 1, occupied = occupied - 1 where id = $1 returning *");
+        assert_eq!(query, "update sections set available = available + 1, occupied = occupied - 1 where id = $1 and deleted_at is null returning *");
 
         let query = query_switch_usage("occupied".to_string(), "disabled".to_string()).unwrap();
-        assert_eq!(query, "update sections set occupied = occupied - 1, disabled_rooms = disabled_rooms + 1 where id = $1 returning *");
+        assert_eq!(query, "update sections set occupied = occupied - 1, disabled_rooms = disabled_rooms + 1 where id = $1 and deleted_at is null returning *");
 
         let query = query_switch_usage("disabled".to_string(), "available".to_string()).unwrap();
-        assert_eq!(query, "update sections set available = available + 1, disabled_rooms = disabled_rooms - 1 where id = $1 returning *");
+        assert_eq!(query, "update sections set available = available + 1, disabled_rooms = disabled_rooms - 1 where id = $1 and deleted_at is null returning *");
 
         let query = query_switch_usage("disabled".to_string(), "occupied".to_string()).unwrap();
-        assert_eq!(query, "update sections set occupied = occupied + 1, disabled_rooms = disabled_rooms - 1 where id = $1 returning *");
+        assert_eq!(query, "update sections set occupied = occupied + 1, disabled_rooms = disabled_rooms - 1 where id = $1 and deleted_at is null returning *");
     }
 }
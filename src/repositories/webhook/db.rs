@@ -0,0 +1,192 @@
+use crate::repositories::webhook::errors::WebhookError;
+use crate::repositories::webhook::models::{
+    CreateWebhook, Delivery, NewDelivery, UpdateWebhook, Webhook,
+};
+use crate::repositories::webhook::traits::WebhookRepository;
+use axum::async_trait;
+use sqlx::PgPool;
+
+#[derive(Clone, Debug)]
+pub struct DBWebhookRepository {
+    pool: PgPool,
+}
+
+impl DBWebhookRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for DBWebhookRepository {
+    async fn find_by_id(&self, id: i32) -> anyhow::Result<Webhook> {
+        let webhook = sqlx::query_as::<_, Webhook>("select * from webhooks where id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(WebhookError::NotFound(id))?;
+        Ok(webhook)
+    }
+
+    async fn find_all(&self) -> anyhow::Result<Vec<Webhook>> {
+        let webhooks = sqlx::query_as::<_, Webhook>("select * from webhooks order by id asc")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(webhooks)
+    }
+
+    async fn create(&self, payload: CreateWebhook) -> anyhow::Result<Webhook> {
+        let webhook = sqlx::query_as::<_, Webhook>(
+            "insert into webhooks (url, secret, events, site, facility_type, gender, building, floor) values ($1, $2, $3, $4, $5, $6, $7, $8) returning *",
+        )
+        .bind(payload.url)
+        .bind(payload.secret)
+        .bind(payload.events)
+        .bind(payload.site)
+        .bind(payload.facility_type)
+        .bind(payload.gender)
+        .bind(payload.building)
+        .bind(payload.floor)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(webhook)
+    }
+
+    async fn update(&self, id: i32, payload: UpdateWebhook) -> anyhow::Result<Webhook> {
+        let webhook = self.find_by_id(id).await?.apply(payload);
+        let webhook = sqlx::query_as::<_, Webhook>(
+            "update webhooks set url = $2, secret = $3, events = $4, site = $5, facility_type = $6, gender = $7, building = $8, floor = $9, active = $10 where id = $1 returning *",
+        )
+        .bind(id)
+        .bind(webhook.url)
+        .bind(webhook.secret)
+        .bind(webhook.events)
+        .bind(webhook.site)
+        .bind(webhook.facility_type)
+        .bind(webhook.gender)
+        .bind(webhook.building)
+        .bind(webhook.floor)
+        .bind(webhook.active)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(WebhookError::NotFound(id))?;
+        Ok(webhook)
+    }
+
+    async fn delete(&self, id: i32) -> anyhow::Result<()> {
+        let result = sqlx::query("delete from webhooks where id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(WebhookError::NotFound(id).into());
+        }
+        Ok(())
+    }
+
+    async fn log_delivery(&self, delivery: NewDelivery) -> anyhow::Result<Delivery> {
+        let delivery = sqlx::query_as::<_, Delivery>(
+            "insert into webhook_deliveries (webhook_id, event_id, event, payload, attempt, status, response_status, error) values ($1, $2, $3, $4, $5, $6, $7, $8) returning *",
+        )
+        .bind(delivery.webhook_id)
+        .bind(delivery.event_id)
+        .bind(delivery.event)
+        .bind(delivery.payload)
+        .bind(delivery.attempt)
+        .bind(delivery.status)
+        .bind(delivery.response_status)
+        .bind(delivery.error)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(delivery)
+    }
+
+    async fn find_deliveries(&self, webhook_id: i32) -> anyhow::Result<Vec<Delivery>> {
+        let deliveries = sqlx::query_as::<_, Delivery>(
+            "select * from webhook_deliveries where webhook_id = $1 order by id asc",
+        )
+        .bind(webhook_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(deliveries)
+    }
+
+    async fn find_dead_letters(&self) -> anyhow::Result<Vec<Delivery>> {
+        let deliveries = sqlx::query_as::<_, Delivery>(
+            "select * from webhook_deliveries where status = 'dead' order by id asc",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(deliveries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use dotenv::dotenv;
+    use std::env;
+
+    async fn setup() -> Result<DBWebhookRepository> {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL")?;
+        let pool = PgPool::connect(&database_url).await?;
+        Ok(DBWebhookRepository::new(pool))
+    }
+
+    #[tokio::test]
+    async fn test_webhook_lifecycle() -> Result<()> {
+        let repository = setup().await?;
+
+        let webhook = repository
+            .create(CreateWebhook {
+                url: "http://localhost:8000/hook".to_string(),
+                secret: "0123456789abcdef".to_string(),
+                events: vec!["section.updated".to_string()],
+                site: None,
+                facility_type: None,
+                gender: Some("male".to_string()),
+                building: Some("A".to_string()),
+                floor: None,
+            })
+            .await?;
+        assert_eq!(webhook.events, vec!["section.updated".to_string()]);
+
+        let updated = repository
+            .update(
+                webhook.id,
+                UpdateWebhook {
+                    floor: Some(2),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        assert_eq!(updated.floor, Some(2));
+        assert_eq!(updated.building, Some("A".to_string()));
+
+        let delivery = repository
+            .log_delivery(NewDelivery {
+                webhook_id: webhook.id,
+                event_id: 1,
+                event: "section.updated".to_string(),
+                payload: "{}".to_string(),
+                attempt: 1,
+                status: "dead".to_string(),
+                response_status: None,
+                error: Some("connection refused".to_string()),
+            })
+            .await?;
+        assert_eq!(
+            repository.find_deliveries(webhook.id).await?,
+            vec![delivery.clone()]
+        );
+        assert!(repository.find_dead_letters().await?.contains(&delivery));
+
+        repository.delete(webhook.id).await?;
+        assert!(repository.find_by_id(webhook.id).await.is_err());
+        assert!(repository.find_deliveries(webhook.id).await?.is_empty());
+
+        Ok(())
+    }
+}
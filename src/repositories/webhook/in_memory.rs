@@ -0,0 +1,184 @@
+use crate::repositories::webhook::errors::WebhookError;
+use crate::repositories::webhook::models::{
+    CreateWebhook, Delivery, NewDelivery, UpdateWebhook, Webhook,
+};
+use crate::repositories::webhook::traits::WebhookRepository;
+use axum::async_trait;
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Default)]
+struct WebhookDatas {
+    webhooks: BTreeMap<i32, Webhook>,
+    deliveries: Vec<Delivery>,
+    last_id: i32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryWebhookRepository {
+    store: Arc<RwLock<WebhookDatas>>,
+}
+
+impl InMemoryWebhookRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for InMemoryWebhookRepository {
+    async fn find_by_id(&self, id: i32) -> anyhow::Result<Webhook> {
+        let store = self.store.read().unwrap();
+        let webhook = store
+            .webhooks
+            .get(&id)
+            .cloned()
+            .ok_or(WebhookError::NotFound(id))?;
+        Ok(webhook)
+    }
+
+    async fn find_all(&self) -> anyhow::Result<Vec<Webhook>> {
+        let store = self.store.read().unwrap();
+        Ok(store.webhooks.values().cloned().collect())
+    }
+
+    async fn create(&self, payload: CreateWebhook) -> anyhow::Result<Webhook> {
+        let mut store = self.store.write().unwrap();
+        store.last_id += 1;
+        let webhook = Webhook {
+            id: store.last_id,
+            url: payload.url,
+            secret: payload.secret,
+            events: payload.events,
+            site: payload.site,
+            facility_type: payload.facility_type,
+            gender: payload.gender,
+            building: payload.building,
+            floor: payload.floor,
+            active: true,
+            created_at: Utc::now(),
+        };
+        store.webhooks.insert(webhook.id, webhook.clone());
+        Ok(webhook)
+    }
+
+    async fn update(&self, id: i32, payload: UpdateWebhook) -> anyhow::Result<Webhook> {
+        let mut store = self.store.write().unwrap();
+        let webhook = store
+            .webhooks
+            .remove(&id)
+            .ok_or(WebhookError::NotFound(id))?
+            .apply(payload);
+        store.webhooks.insert(id, webhook.clone());
+        Ok(webhook)
+    }
+
+    async fn delete(&self, id: i32) -> anyhow::Result<()> {
+        let mut store = self.store.write().unwrap();
+        store
+            .webhooks
+            .remove(&id)
+            .ok_or(WebhookError::NotFound(id))?;
+        store
+            .deliveries
+            .retain(|delivery| delivery.webhook_id != id);
+        Ok(())
+    }
+
+    async fn log_delivery(&self, delivery: NewDelivery) -> anyhow::Result<Delivery> {
+        let mut store = self.store.write().unwrap();
+        let delivery = Delivery {
+            id: store.deliveries.len() as i64 + 1,
+            webhook_id: delivery.webhook_id,
+            event_id: delivery.event_id,
+            event: delivery.event,
+            payload: delivery.payload,
+            attempt: delivery.attempt,
+            status: delivery.status,
+            response_status: delivery.response_status,
+            error: delivery.error,
+            created_at: Utc::now(),
+        };
+        store.deliveries.push(delivery.clone());
+        Ok(delivery)
+    }
+
+    async fn find_deliveries(&self, webhook_id: i32) -> anyhow::Result<Vec<Delivery>> {
+        let store = self.store.read().unwrap();
+        Ok(store
+            .deliveries
+            .iter()
+            .filter(|delivery| delivery.webhook_id == webhook_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn find_dead_letters(&self) -> anyhow::Result<Vec<Delivery>> {
+        let store = self.store.read().unwrap();
+        Ok(store
+            .deliveries
+            .iter()
+            .filter(|delivery| delivery.status == "dead")
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod in_memory_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_webhook_repository() {
+        let repo = InMemoryWebhookRepository::new();
+
+        let webhook = repo
+            .create(CreateWebhook {
+                url: "http://localhost:8000/hook".to_string(),
+                secret: "0123456789abcdef".to_string(),
+                events: vec![],
+                site: None,
+                facility_type: None,
+                gender: Some("female".to_string()),
+                building: None,
+                floor: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(webhook.id, 1);
+        assert!(webhook.active);
+
+        let webhook = repo
+            .update(
+                1,
+                UpdateWebhook {
+                    active: Some(false),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(!webhook.active);
+        assert_eq!(webhook.gender, Some("female".to_string()));
+
+        repo.log_delivery(NewDelivery {
+            webhook_id: 1,
+            event_id: 1,
+            event: "section.updated".to_string(),
+            payload: "{}".to_string(),
+            attempt: 3,
+            status: "dead".to_string(),
+            response_status: Some(500),
+            error: None,
+        })
+        .await
+        .unwrap();
+        assert_eq!(repo.find_deliveries(1).await.unwrap().len(), 1);
+        assert_eq!(repo.find_dead_letters().await.unwrap().len(), 1);
+
+        repo.delete(1).await.unwrap();
+        assert!(repo.find_by_id(1).await.is_err());
+        assert!(repo.find_dead_letters().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,118 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::repositories::events::models::{Event, EventFilter};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    // only used for signing, never sent back to clients
+    #[serde(skip_serializing, default)]
+    pub secret: String,
+    // event kinds to deliver, empty means all of them
+    pub events: Vec<String>,
+    pub site: Option<String>,
+    pub facility_type: Option<String>,
+    pub gender: Option<String>,
+    pub building: Option<String>,
+    pub floor: Option<i32>,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct CreateWebhook {
+    #[validate(url)]
+    pub url: String,
+    #[validate(length(min = 16))]
+    pub secret: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+    pub site: Option<String>,
+    pub facility_type: Option<String>,
+    pub gender: Option<String>,
+    pub building: Option<String>,
+    pub floor: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, Validate)]
+pub struct UpdateWebhook {
+    #[validate(url)]
+    pub url: Option<String>,
+    #[validate(length(min = 16))]
+    pub secret: Option<String>,
+    pub events: Option<Vec<String>>,
+    pub site: Option<String>,
+    pub facility_type: Option<String>,
+    pub gender: Option<String>,
+    pub building: Option<String>,
+    pub floor: Option<i32>,
+    pub active: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct Delivery {
+    pub id: i64,
+    pub webhook_id: i32,
+    pub event_id: i64,
+    pub event: String,
+    pub payload: String,
+    pub attempt: i32,
+    // "delivered", "failed" (will be retried) or "dead"
+    pub status: String,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewDelivery {
+    pub webhook_id: i32,
+    pub event_id: i64,
+    pub event: String,
+    pub payload: String,
+    pub attempt: i32,
+    pub status: String,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct WebhookPayload {
+    pub id: u64,
+    pub event: String,
+    pub topic: String,
+    pub data: String,
+}
+
+impl Webhook {
+    pub fn matches(&self, event: &Event) -> bool {
+        let filter = EventFilter {
+            site: self.site.clone(),
+            facility_type: self.facility_type.clone(),
+            gender: self.gender.clone(),
+            building: self.building.clone(),
+            floor: self.floor,
+        };
+        self.active
+            && (self.events.is_empty() || self.events.iter().any(|kind| kind == event.kind()))
+            && filter.matches(&event.topic)
+    }
+
+    pub fn apply(self, payload: UpdateWebhook) -> Self {
+        Self {
+            url: payload.url.unwrap_or(self.url),
+            secret: payload.secret.unwrap_or(self.secret),
+            events: payload.events.unwrap_or(self.events),
+            site: payload.site.or(self.site),
+            facility_type: payload.facility_type.or(self.facility_type),
+            gender: payload.gender.or(self.gender),
+            building: payload.building.or(self.building),
+            floor: payload.floor.or(self.floor),
+            active: payload.active.unwrap_or(self.active),
+            ..self
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use crate::repositories::webhook::models::{
+    CreateWebhook, Delivery, NewDelivery, UpdateWebhook, Webhook,
+};
+use axum::async_trait;
+
+#[async_trait]
+pub trait WebhookRepository: std::marker::Send + std::marker::Sync + 'static {
+    async fn find_by_id(&self, id: i32) -> anyhow::Result<Webhook>;
+    async fn find_all(&self) -> anyhow::Result<Vec<Webhook>>;
+    async fn create(&self, payload: CreateWebhook) -> anyhow::Result<Webhook>;
+    async fn update(&self, id: i32, payload: UpdateWebhook) -> anyhow::Result<Webhook>;
+    async fn delete(&self, id: i32) -> anyhow::Result<()>;
+    async fn log_delivery(&self, delivery: NewDelivery) -> anyhow::Result<Delivery>;
+    async fn find_deliveries(&self, webhook_id: i32) -> anyhow::Result<Vec<Delivery>>;
+    async fn find_dead_letters(&self) -> anyhow::Result<Vec<Delivery>>;
+}
@@ -0,0 +1,282 @@
+use hmac::{Hmac, Mac};
+use hyper::client::HttpConnector;
+use hyper::{header, Body, Client, Method, Request};
+use hyper_tls::HttpsConnector;
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::repositories::events::models::{Event, EventFilter};
+use crate::repositories::events::traits::EventTrait;
+use crate::repositories::webhook::models::{Delivery, NewDelivery, Webhook, WebhookPayload};
+use crate::repositories::webhook::traits::WebhookRepository;
+
+pub const SIGNATURE_HEADER: &str = "X-Shower-Signature";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// hex encoded HMAC-SHA256 of the body, keyed with the webhook secret
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+// posts bus events to the registered webhooks, retrying with exponential backoff
+pub struct WebhookWorker {
+    repository: Arc<dyn WebhookRepository>,
+    client: Client<HttpsConnector<HttpConnector>>,
+    max_attempts: i32,
+    base_delay: Duration,
+}
+
+impl WebhookWorker {
+    pub fn new(
+        repository: Arc<dyn WebhookRepository>,
+        max_attempts: i32,
+        base_delay: Duration,
+    ) -> Self {
+        Self {
+            repository,
+            client: Client::builder().build(HttpsConnector::new()),
+            max_attempts: max_attempts.max(1),
+            base_delay,
+        }
+    }
+
+    pub async fn run(self, events: Arc<dyn EventTrait>) -> anyhow::Result<()> {
+        let mut subscription = events.subscribe(None, EventFilter::default()).await?;
+        let worker = Arc::new(self);
+        while let Some(event) = subscription.recv().await {
+            if let Err(e) = worker.dispatch(&event).await {
+                tracing::error!("failed to dispatch event {} to webhooks: {}", event.id, e);
+            }
+        }
+        Ok(())
+    }
+
+    // deliveries run in the background so a slow receiver does not hold up the others
+    pub async fn dispatch(self: &Arc<Self>, event: &Event) -> anyhow::Result<()> {
+        let webhooks = self.repository.find_all().await?;
+        for webhook in webhooks
+            .into_iter()
+            .filter(|webhook| webhook.matches(event))
+        {
+            let worker = Arc::clone(self);
+            let event = event.clone();
+            tokio::spawn(async move {
+                if let Err(e) = worker.deliver(&webhook, &event).await {
+                    tracing::error!("failed to log delivery to webhook {}: {}", webhook.id, e);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    // returns the last logged attempt
+    pub async fn deliver(&self, webhook: &Webhook, event: &Event) -> anyhow::Result<Delivery> {
+        let payload = serde_json::to_string(&WebhookPayload {
+            id: event.id,
+            event: event.kind().to_string(),
+            topic: event.topic.clone(),
+            data: event.data.clone(),
+        })?;
+        let mut attempt = 1;
+        loop {
+            let (response_status, error) = match self.post(webhook, event, &payload).await {
+                Ok(status) if (200..300).contains(&status) => (Some(status), None),
+                Ok(status) => (Some(status), Some(format!("unexpected status {}", status))),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            let status = match (&error, attempt >= self.max_attempts) {
+                (None, _) => "delivered",
+                (Some(_), false) => "failed",
+                (Some(_), true) => "dead",
+            };
+            let delivery = self
+                .repository
+                .log_delivery(NewDelivery {
+                    webhook_id: webhook.id,
+                    event_id: event.id as i64,
+                    event: event.kind().to_string(),
+                    payload: payload.clone(),
+                    attempt,
+                    status: status.to_string(),
+                    response_status,
+                    error,
+                })
+                .await?;
+            if status != "failed" {
+                return Ok(delivery);
+            }
+
+            tokio::time::sleep(self.base_delay * 2u32.pow(attempt as u32 - 1)).await;
+            attempt += 1;
+        }
+    }
+
+    async fn post(&self, webhook: &Webhook, event: &Event, payload: &str) -> anyhow::Result<i32> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(&webhook.url)
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header(SIGNATURE_HEADER, sign(&webhook.secret, payload))
+            .header("X-Shower-Event", event.kind())
+            .body(Body::from(payload.to_string()))?;
+        let response =
+            tokio::time::timeout(REQUEST_TIMEOUT, self.client.request(request)).await??;
+        Ok(response.status().as_u16() as i32)
+    }
+}
+
+#[cfg(all(test, feature = "in-memory"))]
+mod tests {
+    use super::*;
+    use crate::repositories::events::models::Events;
+    use crate::repositories::webhook::in_memory::InMemoryWebhookRepository;
+    use crate::repositories::webhook::models::CreateWebhook;
+    use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Router};
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+    const SECRET: &str = "0123456789abcdef";
+
+    #[derive(Clone)]
+    struct Receiver {
+        requests: UnboundedSender<(HeaderMap, String)>,
+        // number of requests answered with 500 before accepting
+        failures: Arc<AtomicUsize>,
+    }
+
+    async fn receive(
+        State(receiver): State<Receiver>,
+        headers: HeaderMap,
+        body: String,
+    ) -> StatusCode {
+        let _ = receiver.requests.send((headers, body));
+        let failed = receiver
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+        if failed.is_ok() {
+            StatusCode::INTERNAL_SERVER_ERROR
+        } else {
+            StatusCode::NO_CONTENT
+        }
+    }
+
+    // local stand-in for the dormitory office system
+    async fn spawn_receiver(
+        failures: usize,
+    ) -> (
+        SocketAddr,
+        tokio::sync::mpsc::UnboundedReceiver<(HeaderMap, String)>,
+    ) {
+        let (tx, rx) = unbounded_channel();
+        let app = Router::new()
+            .route("/hook", post(receive))
+            .with_state(Receiver {
+                requests: tx,
+                failures: Arc::new(AtomicUsize::new(failures)),
+            });
+        let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .serve(app.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        (addr, rx)
+    }
+
+    async fn register(repository: &InMemoryWebhookRepository, addr: SocketAddr) -> Webhook {
+        repository
+            .create(CreateWebhook {
+                url: format!("http://{}/hook", addr),
+                secret: SECRET.to_string(),
+                events: vec![],
+                site: None,
+                facility_type: None,
+                gender: Some("female".to_string()),
+                building: Some("C".to_string()),
+                floor: None,
+            })
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_sign() {
+        // echo -n '{}' | openssl dgst -sha256 -hmac 0123456789abcdef
+        assert_eq!(
+            sign(SECRET, "{}"),
+            "sha256=f91e3e9f05cc2df64ac1c26f8adccdffda8d1e4a7a8c50a1a08eeadac6ddfec5"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_deliver_signed_payload() {
+        let (addr, mut requests) = spawn_receiver(0).await;
+        let repository = InMemoryWebhookRepository::new();
+        let webhook = register(&repository, addr).await;
+        let repository = Arc::new(repository);
+        let events = Arc::new(Events::new());
+        let worker = WebhookWorker::new(repository.clone(), 3, Duration::from_millis(1));
+        tokio::spawn(worker.run(events.clone()));
+        // let the worker subscribe before publishing
+        while events.subscribers().await == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        events.notify("male/C/1".to_string().into()).await.unwrap();
+        events
+            .notify("female/C/2".to_string().into())
+            .await
+            .unwrap();
+        let (headers, body) = requests.recv().await.unwrap();
+        let payload: WebhookPayload = serde_json::from_str(&body).unwrap();
+        assert_eq!(payload.id, 2);
+        assert_eq!(payload.event, "section.updated");
+        assert_eq!(payload.data, "female/C/2");
+        assert_eq!(
+            headers.get(SIGNATURE_HEADER).unwrap(),
+            sign(SECRET, &body).as_str()
+        );
+        assert!(requests.try_recv().is_err());
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let deliveries = repository.find_deliveries(webhook.id).await.unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].status, "delivered");
+        assert_eq!(deliveries[0].response_status, Some(204));
+    }
+
+    #[tokio::test]
+    async fn test_retry_then_dead_letter() {
+        let (addr, _requests) = spawn_receiver(1).await;
+        let repository = InMemoryWebhookRepository::new();
+        let webhook = register(&repository, addr).await;
+        let worker = WebhookWorker::new(Arc::new(repository.clone()), 2, Duration::from_millis(1));
+
+        let event = Event::new(1, "female/C/1".to_string());
+        let delivery = worker.deliver(&webhook, &event).await.unwrap();
+        assert_eq!(delivery.status, "delivered");
+        assert_eq!(delivery.attempt, 2);
+
+        // nothing listens on the receiver's port once it is gone
+        let unreachable = Webhook {
+            url: "http://127.0.0.1:1/hook".to_string(),
+            ..webhook.clone()
+        };
+        let delivery = worker.deliver(&unreachable, &event).await.unwrap();
+        assert_eq!(delivery.status, "dead");
+        assert_eq!(delivery.attempt, 2);
+
+        let statuses: Vec<String> = repository
+            .find_deliveries(webhook.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|delivery| delivery.status)
+            .collect();
+        assert_eq!(statuses, vec!["failed", "delivered", "failed", "dead"]);
+        assert_eq!(repository.find_dead_letters().await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,176 @@
+use axum::extract::FromRef;
+use std::sync::Arc;
+
+use crate::handlers::{
+    audit::TrustedProxies, auth::JwtKeys, facility::FacilityTypes, site::DefaultSite,
+};
+use crate::repositories::{
+    alert::traits::AlertRepository,
+    api_key::traits::ApiKeyRepository,
+    audit::traits::AuditRepository,
+    events::traits::EventTrait,
+    section::{cached::SectionCacheStats, instrumented::SectionMetrics, traits::SectionRepository},
+    webhook::traits::WebhookRepository,
+};
+
+pub struct AppState<R: SectionRepository> {
+    pub repository: Arc<R>,
+    pub events: Arc<dyn EventTrait>,
+    pub webhooks: Arc<dyn WebhookRepository>,
+    pub alerts: Arc<dyn AlertRepository>,
+    pub audit: Arc<dyn AuditRepository>,
+    pub site: DefaultSite,
+    pub facilities: FacilityTypes,
+    // none leaves the write routes open, only the in-memory demo with OPEN_WRITES=true
+    pub api_keys: Option<Arc<dyn ApiKeyRepository>>,
+    pub jwt: JwtKeys,
+    pub proxies: TrustedProxies,
+    // set when the repository is wrapped in the cache and instrumentation decorators
+    pub cache: Option<SectionCacheStats>,
+    pub metrics: Option<SectionMetrics>,
+}
+
+impl<R: SectionRepository> AppState<R> {
+    pub fn new(
+        repository: R,
+        events: Arc<dyn EventTrait>,
+        webhooks: Arc<dyn WebhookRepository>,
+        alerts: Arc<dyn AlertRepository>,
+        audit: Arc<dyn AuditRepository>,
+    ) -> Self {
+        Self {
+            repository: Arc::new(repository),
+            events,
+            webhooks,
+            alerts,
+            audit,
+            site: DefaultSite::default(),
+            facilities: FacilityTypes::default(),
+            api_keys: None,
+            jwt: JwtKeys::default(),
+            proxies: TrustedProxies::default(),
+            cache: None,
+            metrics: None,
+        }
+    }
+
+    pub fn with_default_site(self, site: DefaultSite) -> Self {
+        Self { site, ..self }
+    }
+
+    pub fn with_facility_types(self, facilities: FacilityTypes) -> Self {
+        Self { facilities, ..self }
+    }
+
+    pub fn with_api_keys(self, api_keys: Option<Arc<dyn ApiKeyRepository>>) -> Self {
+        Self { api_keys, ..self }
+    }
+
+    pub fn with_jwt_keys(self, jwt: JwtKeys) -> Self {
+        Self { jwt, ..self }
+    }
+
+    pub fn with_trusted_proxies(self, proxies: TrustedProxies) -> Self {
+        Self { proxies, ..self }
+    }
+
+    pub fn with_cache_stats(self, cache: Option<SectionCacheStats>) -> Self {
+        Self { cache, ..self }
+    }
+
+    pub fn with_metrics(self, metrics: Option<SectionMetrics>) -> Self {
+        Self { metrics, ..self }
+    }
+}
+
+impl<R: SectionRepository> Clone for AppState<R> {
+    fn clone(&self) -> Self {
+        Self {
+            repository: Arc::clone(&self.repository),
+            events: Arc::clone(&self.events),
+            webhooks: Arc::clone(&self.webhooks),
+            alerts: Arc::clone(&self.alerts),
+            audit: Arc::clone(&self.audit),
+            site: self.site.clone(),
+            facilities: self.facilities.clone(),
+            api_keys: self.api_keys.clone(),
+            jwt: self.jwt.clone(),
+            proxies: self.proxies.clone(),
+            cache: self.cache.clone(),
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+// lets handlers extract `State<Arc<R>>`, `State<Arc<dyn EventTrait>>`... separately
+impl<R: SectionRepository> FromRef<AppState<R>> for Arc<R> {
+    fn from_ref(state: &AppState<R>) -> Self {
+        Arc::clone(&state.repository)
+    }
+}
+
+impl<R: SectionRepository> FromRef<AppState<R>> for Arc<dyn EventTrait> {
+    fn from_ref(state: &AppState<R>) -> Self {
+        Arc::clone(&state.events)
+    }
+}
+
+impl<R: SectionRepository> FromRef<AppState<R>> for Arc<dyn WebhookRepository> {
+    fn from_ref(state: &AppState<R>) -> Self {
+        Arc::clone(&state.webhooks)
+    }
+}
+
+impl<R: SectionRepository> FromRef<AppState<R>> for Arc<dyn AlertRepository> {
+    fn from_ref(state: &AppState<R>) -> Self {
+        Arc::clone(&state.alerts)
+    }
+}
+
+impl<R: SectionRepository> FromRef<AppState<R>> for Arc<dyn AuditRepository> {
+    fn from_ref(state: &AppState<R>) -> Self {
+        Arc::clone(&state.audit)
+    }
+}
+
+impl<R: SectionRepository> FromRef<AppState<R>> for DefaultSite {
+    fn from_ref(state: &AppState<R>) -> Self {
+        state.site.clone()
+    }
+}
+
+impl<R: SectionRepository> FromRef<AppState<R>> for FacilityTypes {
+    fn from_ref(state: &AppState<R>) -> Self {
+        state.facilities.clone()
+    }
+}
+
+impl<R: SectionRepository> FromRef<AppState<R>> for Option<Arc<dyn ApiKeyRepository>> {
+    fn from_ref(state: &AppState<R>) -> Self {
+        state.api_keys.clone()
+    }
+}
+
+impl<R: SectionRepository> FromRef<AppState<R>> for JwtKeys {
+    fn from_ref(state: &AppState<R>) -> Self {
+        state.jwt.clone()
+    }
+}
+
+impl<R: SectionRepository> FromRef<AppState<R>> for TrustedProxies {
+    fn from_ref(state: &AppState<R>) -> Self {
+        state.proxies.clone()
+    }
+}
+
+impl<R: SectionRepository> FromRef<AppState<R>> for Option<SectionCacheStats> {
+    fn from_ref(state: &AppState<R>) -> Self {
+        state.cache.clone()
+    }
+}
+
+impl<R: SectionRepository> FromRef<AppState<R>> for Option<SectionMetrics> {
+    fn from_ref(state: &AppState<R>) -> Self {
+        state.metrics.clone()
+    }
+}